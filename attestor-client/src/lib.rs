@@ -1,5 +1,5 @@
-//! # cg-oracle-client
-//! Http client wrapper for the Crypto Garage DLC oracle
+//! # attestor-client
+//! Http client wrapper for DLC.Link attestors and p2pderivatives compatible oracles
 #![feature(async_fn_in_trait)]
 // Coding conventions
 #![deny(non_upper_case_globals)]
@@ -16,9 +16,9 @@ extern crate dlc_messages;
 extern crate secp256k1_zkp;
 extern crate serde;
 
-use std::{fmt, io::Cursor, num::ParseIntError, time::Duration};
+use std::{fmt, io::Cursor, num::ParseIntError, str::FromStr, time::Duration};
 
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, SecondsFormat, TimeZone, Utc};
 use dlc_link_manager::AsyncOracle;
 use dlc_manager::error::Error as DlcManagerError;
 use dlc_messages::oracle_msgs::{OracleAnnouncement, OracleAttestation};
@@ -27,11 +27,50 @@ use secp256k1_zkp::{schnorr::Signature, XOnlyPublicKey};
 use serde_json::Value;
 
 const REQWEST_TIMEOUT: Duration = Duration::from_secs(30);
+/// Length of the asset id prefix of a p2pderivatives event id, e.g. `btcusd1623344400`.
+const P2PD_ASSET_ID_LENGTH: usize = 6;
+
+/// The REST dialect spoken by the oracle behind an [`AttestorClient`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OracleApi {
+    /// DLC.Link attestor, serving hex encoded `rust_announcement` and
+    /// `rust_attestation` fields under `event/{uuid}`.
+    DlcLink,
+    /// p2pderivatives oracle REST API, serving JSON announcements and
+    /// attestations under `asset/{asset_id}/...`.
+    P2pDerivatives,
+}
+
+impl FromStr for OracleApi {
+    type Err = DlcManagerError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "dlclink" | "dlc-link" | "attestor" => Ok(OracleApi::DlcLink),
+            "p2pd" | "p2pderivatives" => Ok(OracleApi::P2pDerivatives),
+            _ => Err(DlcManagerError::InvalidParameters(format!(
+                "Unknown oracle api {}",
+                s
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for OracleApi {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            OracleApi::DlcLink => "dlclink".fmt(f),
+            OracleApi::P2pDerivatives => "p2pderivatives".fmt(f),
+        }
+    }
+}
+
 /// Enables interacting with a DLC oracle.
 pub struct AttestorClient {
     host: String,
     public_key: XOnlyPublicKey,
     client: reqwest::Client,
+    api: OracleApi,
 }
 
 #[derive(serde::Deserialize, serde::Serialize)]
@@ -40,31 +79,6 @@ struct PublicKeyResponse {
     public_key: XOnlyPublicKey,
 }
 
-#[derive(serde::Deserialize, serde::Serialize)]
-#[serde(rename_all = "camelCase")]
-struct EventDescriptor {
-    base: u16,
-    is_signed: bool,
-    unit: String,
-    precision: i32,
-}
-
-#[derive(serde::Deserialize, serde::Serialize)]
-#[serde(rename_all = "camelCase")]
-struct Event {
-    nonces: Vec<XOnlyPublicKey>,
-    event_maturity: DateTime<Utc>,
-    event_id: String,
-    event_descriptor: EventDescriptor,
-}
-
-#[derive(serde::Deserialize, serde::Serialize)]
-#[serde(rename_all = "camelCase")]
-struct AnnoucementResponse {
-    oracle_public_key: XOnlyPublicKey,
-    oracle_event: Event,
-}
-
 #[derive(serde::Deserialize, serde::Serialize)]
 #[serde(rename_all = "camelCase")]
 struct AttestationResponse {
@@ -85,12 +99,69 @@ fn attestation_path(host: &str, event_id: &str) -> String {
     format!("{}event/{}", host, event_id)
 }
 
+fn p2pd_pubkey_path(host: &str) -> String {
+    format!("{}{}", host, "oracle/publickey")
+}
+
+fn p2pd_announcement_path(host: &str, asset_id: &str, date_time: &DateTime<Utc>) -> String {
+    format!(
+        "{}asset/{}/announcement/{}",
+        host,
+        asset_id,
+        date_time.to_rfc3339_opts(SecondsFormat::Secs, true)
+    )
+}
+
+fn p2pd_attestation_path(host: &str, asset_id: &str, date_time: &DateTime<Utc>) -> String {
+    format!(
+        "{}asset/{}/attestation/{}",
+        host,
+        asset_id,
+        date_time.to_rfc3339_opts(SecondsFormat::Secs, true)
+    )
+}
+
+/// Splits a p2pderivatives event id (`{asset_id}{unix timestamp}`) into its
+/// asset id and maturity.
+fn parse_p2pd_event_id(event_id: &str) -> Result<(String, DateTime<Utc>), DlcManagerError> {
+    if event_id.len() <= P2PD_ASSET_ID_LENGTH || !event_id.is_char_boundary(P2PD_ASSET_ID_LENGTH) {
+        return Err(DlcManagerError::InvalidParameters(format!(
+            "Invalid p2pderivatives event id {}",
+            event_id
+        )));
+    }
+    let (asset_id, timestamp_str) = event_id.split_at(P2PD_ASSET_ID_LENGTH);
+    let timestamp: i64 = timestamp_str.parse().map_err(|_| {
+        DlcManagerError::InvalidParameters(format!(
+            "Invalid timestamp in p2pderivatives event id {}",
+            event_id
+        ))
+    })?;
+    let date_time = Utc.timestamp_opt(timestamp, 0).single().ok_or_else(|| {
+        DlcManagerError::InvalidParameters(format!(
+            "Out of range timestamp in p2pderivatives event id {}",
+            event_id
+        ))
+    })?;
+    Ok((asset_id.to_string(), date_time))
+}
+
 impl AttestorClient {
     /// Try to create an instance of an oracle client connecting to the provided
-    /// host. Returns an error if the host could not be reached. Panics if the
-    /// oracle uses an incompatible format.
+    /// host, detecting whether it is a DLC.Link attestor or a p2pderivatives
+    /// oracle from its public key endpoint. Returns an error if the host could
+    /// not be reached or speaks neither format.
     #[allow(dead_code)]
     pub async fn new(host: &str) -> Result<AttestorClient, DlcManagerError> {
+        Self::new_with_api(host, None).await
+    }
+
+    /// Try to create an instance of an oracle client connecting to the provided
+    /// host using the given API. When `api` is `None` the API is auto-detected.
+    pub async fn new_with_api(
+        host: &str,
+        api: Option<OracleApi>,
+    ) -> Result<AttestorClient, DlcManagerError> {
         if host.is_empty() {
             return Err(DlcManagerError::InvalidParameters(
                 "Invalid host".to_string(),
@@ -101,9 +172,7 @@ impl AttestorClient {
         } else {
             host.to_string()
         };
-        info!("Creating p2pd oracle client (by getting public key first) ...");
-        let path = pubkey_path(&host);
-        info!("Getting pubkey from {}", path);
+        info!("Creating oracle client (by getting public key first) ...");
 
         let mut client_builder = reqwest::ClientBuilder::new();
         #[cfg(not(target_arch = "wasm32"))]
@@ -115,6 +184,48 @@ impl AttestorClient {
             .build()
             .expect("Attestor Client should be able to create a reqwest client");
 
+        let (public_key, api) = match api {
+            Some(OracleApi::DlcLink) => (
+                Self::get_dlc_link_public_key(&client, &host).await?,
+                OracleApi::DlcLink,
+            ),
+            Some(OracleApi::P2pDerivatives) => (
+                Self::get_p2pd_public_key(&client, &host).await?,
+                OracleApi::P2pDerivatives,
+            ),
+            None => match Self::get_dlc_link_public_key(&client, &host).await {
+                Ok(public_key) => (public_key, OracleApi::DlcLink),
+                Err(e) => {
+                    debug!("Not a DLC.Link attestor ({e}), trying p2pderivatives format");
+                    (
+                        Self::get_p2pd_public_key(&client, &host).await?,
+                        OracleApi::P2pDerivatives,
+                    )
+                }
+            },
+        };
+
+        info!("Attestor Pub Key: {}", public_key.to_string());
+        info!("The {} oracle client has been created successfully", api);
+        Ok(AttestorClient {
+            host,
+            public_key,
+            client,
+            api,
+        })
+    }
+
+    /// Returns the API this client uses to talk to its oracle.
+    pub fn api(&self) -> OracleApi {
+        self.api
+    }
+
+    async fn get_dlc_link_public_key(
+        client: &reqwest::Client,
+        host: &str,
+    ) -> Result<XOnlyPublicKey, DlcManagerError> {
+        let path = pubkey_path(host);
+        info!("Getting pubkey from {}", path);
         let attestor_key = client
             .get(path)
             .send()
@@ -124,20 +235,37 @@ impl AttestorClient {
             .await
             .map_err(|e| DlcManagerError::OracleError(format!("Oracle PubKey Error: {e}")))?;
 
-        info!("Attestor Pub Key: {}", attestor_key.to_string());
-
-        let public_key: XOnlyPublicKey = attestor_key
+        attestor_key
+            .trim()
             .parse()
-            .map_err(|_| DlcManagerError::OracleError("Oracle PubKey Error".to_string()))?;
-        info!("The p2pd oracle client has been created successfully");
-        Ok(AttestorClient {
-            host,
-            public_key,
-            client,
-        })
+            .map_err(|_| DlcManagerError::OracleError("Oracle PubKey Error".to_string()))
+    }
+
+    async fn get_p2pd_public_key(
+        client: &reqwest::Client,
+        host: &str,
+    ) -> Result<XOnlyPublicKey, DlcManagerError> {
+        let path = p2pd_pubkey_path(host);
+        info!("Getting pubkey from {}", path);
+        let response = client
+            .get(path)
+            .send()
+            .await
+            .map_err(|e| DlcManagerError::OracleError(format!("Oracle PubKey Error: {e}")))?
+            .json::<PublicKeyResponse>()
+            .await
+            .map_err(|e| DlcManagerError::OracleError(format!("Oracle PubKey Error: {e}")))?;
+
+        Ok(response.public_key)
     }
 
     pub async fn get_chain(&self, event_id: &str) -> Result<String, DlcManagerError> {
+        if self.api != OracleApi::DlcLink {
+            return Err(DlcManagerError::OracleError(format!(
+                "{} oracles do not track the chain of event {}",
+                self.api, event_id
+            )));
+        }
         debug!("Getting chain for event_id {event_id}");
         let path = announcement_path(&self.host, event_id);
         debug!("Getting chain at URL {path}");
@@ -165,6 +293,27 @@ impl AttestorClient {
         Ok(chain.to_string())
     }
 
+    /// Like `get_json`, but a 404 is answered with `None`, and other error
+    /// statuses are errors.
+    async fn get_json_if_found(&self, path: &str) -> Result<Option<Value>, DlcManagerError> {
+        let response = self
+            .client
+            .get(path)
+            .send()
+            .await
+            .map_err(|x| DlcManagerError::OracleError(x.to_string()))?;
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        response
+            .error_for_status()
+            .map_err(|x| DlcManagerError::OracleError(x.to_string()))?
+            .json::<Value>()
+            .await
+            .map(Some)
+            .map_err(|x| DlcManagerError::OracleError(x.to_string()))
+    }
+
     async fn get_json(&self, path: &str) -> Result<Value, DlcManagerError> {
         self.client
             .get(path)
@@ -175,6 +324,133 @@ impl AttestorClient {
             .await
             .map_err(|x| dlc_manager::error::Error::OracleError(x.to_string()))
     }
+
+    /// Returns the announcement of the event, or `None` if the oracle has
+    /// none for it. Failing to reach the oracle, or to decode its answer, is
+    /// an error.
+    pub async fn find_announcement(
+        &self,
+        event_id: &str,
+    ) -> Result<Option<OracleAnnouncement>, DlcManagerError> {
+        debug!("Looking for announcement of event_id {event_id}");
+        match self.api {
+            OracleApi::DlcLink => self.find_dlc_link_announcement(event_id).await,
+            OracleApi::P2pDerivatives => self.find_p2pd_announcement(event_id).await,
+        }
+    }
+
+    async fn find_dlc_link_announcement(
+        &self,
+        event_id: &str,
+    ) -> Result<Option<OracleAnnouncement>, DlcManagerError> {
+        let path = announcement_path(&self.host, event_id);
+        debug!("Getting announcement at URL {path}");
+        let v = match self.get_json_if_found(&path).await {
+            Ok(Some(v)) => v,
+            Ok(None) => return Ok(None),
+            Err(e) => {
+                return Err(DlcManagerError::OracleError(format!(
+                    "Error getting announcement {event_id}: {e}"
+                )))
+            }
+        };
+
+        let encoded_hex_announcement = match v["rust_announcement"].as_str() {
+            //call to_string instead of as_str and watch your world crumble to pieces
+            None => return Ok(None),
+            Some(s) => s,
+        };
+
+        let buffer = decode_hex(encoded_hex_announcement)
+            .map_err(|e| DlcManagerError::OracleError(format!("Error decoding hex: {e}", e = e)))?;
+
+        let mut announcement_cursor = Cursor::new(buffer);
+        <dlc_messages::oracle_msgs::OracleAnnouncement as lightning::util::ser::Readable>::read(
+            &mut announcement_cursor,
+        )
+        .map(Some)
+        .map_err(|e| {
+            DlcManagerError::OracleError(format!("Error decoding announcement {event_id}: {e}"))
+        })
+    }
+
+    async fn find_p2pd_announcement(
+        &self,
+        event_id: &str,
+    ) -> Result<Option<OracleAnnouncement>, DlcManagerError> {
+        // An event id the oracle can't have an event for
+        let (asset_id, date_time) = match parse_p2pd_event_id(event_id) {
+            Ok(parsed) => parsed,
+            Err(_) => return Ok(None),
+        };
+        let path = p2pd_announcement_path(&self.host, &asset_id, &date_time);
+        debug!("Getting announcement at URL {path}");
+        let v = match self.get_json_if_found(&path).await.map_err(|e| {
+            DlcManagerError::OracleError(format!("Error getting announcement {event_id}: {e}"))
+        })? {
+            Some(v) => v,
+            None => return Ok(None),
+        };
+
+        serde_json::from_value::<OracleAnnouncement>(v)
+            .map(Some)
+            .map_err(|e| {
+                DlcManagerError::OracleError(format!("Error decoding announcement {event_id}: {e}"))
+            })
+    }
+
+    async fn get_dlc_link_attestation(
+        &self,
+        event_id: &str,
+    ) -> Result<OracleAttestation, DlcManagerError> {
+        let path = attestation_path(&self.host, event_id);
+        let v = self.get_json(&path).await?;
+
+        let encoded_hex_attestation = match v["rust_attestation"].as_str() {
+            None => {
+                return Err(DlcManagerError::OracleError(format!(
+                    "missing attestation for event {}",
+                    event_id,
+                )))
+            }
+            Some(s) => s,
+        };
+
+        let buffer = decode_hex(encoded_hex_attestation)
+            .map_err(|e| DlcManagerError::OracleError(format!("Error decoding hex: {e}", e = e)))?;
+
+        let mut attestation_cursor = Cursor::new(buffer);
+        <dlc_messages::oracle_msgs::OracleAttestation as lightning::util::ser::Readable>::read(
+            &mut attestation_cursor,
+        )
+        .map_err(|e| {
+            DlcManagerError::OracleError(format!("Error decoding attestation {event_id}: {e}"))
+        })
+    }
+
+    async fn get_p2pd_attestation(
+        &self,
+        event_id: &str,
+    ) -> Result<OracleAttestation, DlcManagerError> {
+        let (asset_id, date_time) = parse_p2pd_event_id(event_id)?;
+        let path = p2pd_attestation_path(&self.host, &asset_id, &date_time);
+        debug!("Getting attestation at URL {path}");
+        let v = self.get_json(&path).await?;
+
+        let AttestationResponse {
+            event_id: _,
+            signatures,
+            values,
+        } = serde_json::from_value::<AttestationResponse>(v).map_err(|e| {
+            DlcManagerError::OracleError(format!("Error decoding attestation {event_id}: {e}"))
+        })?;
+
+        Ok(OracleAttestation {
+            oracle_public_key: self.public_key,
+            signatures,
+            outcomes: values,
+        })
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -221,69 +497,54 @@ impl AsyncOracle for AttestorClient {
         event_id: &str,
     ) -> Result<OracleAnnouncement, DlcManagerError> {
         debug!("Getting announcement for event_id {event_id}");
-        let path = announcement_path(&self.host, event_id);
-        debug!("Getting announcement at URL {path}");
-        let v = match self.get_json(&path).await {
-            Ok(v) => v,
-            Err(e) => {
-                return Err(DlcManagerError::OracleError(format!(
-                    "Error getting announcement {event_id}: {e}"
-                )))
-            }
-        };
-
-        let encoded_hex_announcement = match v["rust_announcement"].as_str() {
-            //call to_string instead of as_str and watch your world crumble to pieces
-            None => {
-                return Err(DlcManagerError::OracleError(format!(
-                    "missing announcement for event {}",
-                    event_id,
-                )))
-            }
-            Some(s) => s,
-        };
-
-        let buffer = decode_hex(encoded_hex_announcement)
-            .map_err(|e| DlcManagerError::OracleError(format!("Error decoding hex: {e}", e = e)))?;
-
-        let mut announcement_cursor = Cursor::new(buffer);
-        let decoded_announcement =
-        <dlc_messages::oracle_msgs::OracleAnnouncement as lightning::util::ser::Readable>::read(
-            &mut announcement_cursor,
-        )
-        .unwrap();
-
-        Ok(decoded_announcement)
+        if let OracleApi::P2pDerivatives = self.api {
+            parse_p2pd_event_id(event_id)?;
+        }
+        self.find_announcement(event_id).await?.ok_or_else(|| {
+            DlcManagerError::OracleError(format!("missing announcement for event {}", event_id))
+        })
     }
 
     async fn get_attestation(
         &self,
         event_id: &str,
     ) -> Result<OracleAttestation, dlc_manager::error::Error> {
-        let path = attestation_path(&self.host, event_id);
-        let v = self.get_json(&path).await?;
-
-        //TODO: this next line might be None, throwing at unwrap, fix
-        let encoded_hex_attestation = match v["rust_attestation"].as_str() {
-            None => {
-                return Err(DlcManagerError::OracleError(format!(
-                    "missing attestation for event {}",
-                    event_id,
-                )))
-            }
-            Some(s) => s,
-        };
-
-        let buffer = decode_hex(encoded_hex_attestation).unwrap();
+        debug!("Getting attestation for event_id {event_id}");
+        match self.api {
+            OracleApi::DlcLink => self.get_dlc_link_attestation(event_id).await,
+            OracleApi::P2pDerivatives => self.get_p2pd_attestation(event_id).await,
+        }
+    }
+}
 
-        let mut attestation_cursor = Cursor::new(buffer);
-        let decoded_attestation =
-            <dlc_messages::oracle_msgs::OracleAttestation as lightning::util::ser::Readable>::read(
-                &mut attestation_cursor,
-            )
-            .unwrap();
+#[cfg(test)]
+mod p2pd_tests {
+    use super::*;
+
+    #[test]
+    fn parses_p2pd_event_id() {
+        let (asset_id, date_time) =
+            parse_p2pd_event_id("btcusd1623344400").expect("a valid event id");
+        assert_eq!("btcusd", asset_id);
+        assert_eq!(
+            "http://oracle/asset/btcusd/announcement/2021-06-10T17:00:00Z",
+            p2pd_announcement_path("http://oracle/", &asset_id, &date_time)
+        );
+        assert!(parse_p2pd_event_id("btcusd").is_err());
+        assert!(parse_p2pd_event_id("btcusdnotatime").is_err());
+    }
 
-        Ok(decoded_attestation)
+    #[test]
+    fn parses_oracle_api() {
+        assert_eq!(
+            OracleApi::P2pDerivatives,
+            "p2pd".parse::<OracleApi>().expect("a known api")
+        );
+        assert_eq!(
+            OracleApi::DlcLink,
+            "DLCLink".parse::<OracleApi>().expect("a known api")
+        );
+        assert!("cg".parse::<OracleApi>().is_err());
     }
 }
 
@@ -126,6 +126,7 @@ impl StorageApiConn {
                 EventsRequestParams {
                     key: self.public_key.clone(),
                     event_id: None,
+                    event_id_prefix: None,
                 },
                 secret_key,
             )
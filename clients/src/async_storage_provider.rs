@@ -1,4 +1,5 @@
-use dlc_link_manager::AsyncStorage;
+use dlc_link_manager::{AsyncStorage, ContractEvent, ContractMetadata};
+use dlc_manager::contract::accepted_contract::AcceptedContract;
 use dlc_manager::contract::offered_contract::OfferedContract;
 use dlc_manager::contract::signed_contract::SignedContract;
 use dlc_manager::contract::Contract as DlcContract;
@@ -7,10 +8,11 @@ use dlc_manager::error::Error;
 use dlc_manager::ContractId;
 use secp256k1_zkp::SecretKey;
 
-use crate::utils::{get_contract_id_string, to_storage_error};
+use crate::utils::{get_contract_id_string, parse_contract_id_string, to_storage_error};
 use crate::{
-    ApiError, Contract, ContractRequestParams, ContractsRequestParams, NewContract,
-    StorageApiClient, UpdateContract,
+    ApiError, Contract, ContractRequestParams, ContractsRequestParams, EventRequestParams,
+    EventsRequestParams, NewContract, NewEvent, StorageApiClient, SwapEvent, UpdateContract,
+    UpdateEvent,
 };
 
 use super::utils::{deserialize_contract, get_contract_state_str, serialize_contract};
@@ -63,6 +65,86 @@ impl AsyncStorageApiProvider {
     }
 }
 
+/// Id of the storage event holding the manager metadata of a contract.
+fn contract_metadata_event_id(id: &ContractId) -> String {
+    format!("contract-metadata-{}", get_contract_id_string(*id))
+}
+
+/// Id of the storage event holding the id of the contract indexed under an
+/// oracle event.
+fn contract_event_index_event_id(event_id: &str) -> String {
+    format!("contract-event-{}", event_id)
+}
+
+/// Prefix of the ids of the storage events holding the journal of contract
+/// events.
+const CONTRACT_JOURNAL_PREFIX: &str = "contract-journal-";
+
+fn contract_journal_event_id(event: &ContractEvent) -> String {
+    format!("{}{}", CONTRACT_JOURNAL_PREFIX, event.journal_id())
+}
+
+/// Id of the storage event holding the lease on a contract.
+fn contract_lease_event_id(id: &ContractId) -> String {
+    format!("contract-lease-{}", get_contract_id_string(*id))
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+struct ContractLease {
+    owner: String,
+    expires_at: u64,
+}
+
+impl AsyncStorageApiProvider {
+    /// Returns the lease of a contract along with the stored content it was
+    /// read from, which later updates of the lease are conditioned on.
+    async fn get_contract_lease(
+        &self,
+        event_id: &str,
+    ) -> Result<Option<(ContractLease, String)>, Error> {
+        let event = self
+            .client
+            .get_event(
+                EventRequestParams {
+                    key: self.public_key.clone(),
+                    event_id: event_id.to_string(),
+                },
+                self.secret_key,
+            )
+            .await
+            .map_err(to_storage_error)?;
+        match event {
+            Some(event) => Ok(Some((
+                serde_json::from_str(&event.content).map_err(to_storage_error)?,
+                event.content,
+            ))),
+            None => Ok(None),
+        }
+    }
+
+    /// Replaces the lease only if it still holds `expected_content`, returns
+    /// whether it was replaced.
+    async fn swap_contract_lease(
+        &self,
+        event_id: &str,
+        expected_content: String,
+        content: String,
+    ) -> Result<bool, Error> {
+        self.client
+            .swap_event(
+                SwapEvent {
+                    event_id: event_id.to_string(),
+                    content,
+                    expected_content,
+                    key: self.public_key.clone(),
+                },
+                self.secret_key,
+            )
+            .await
+            .map_err(to_storage_error)
+    }
+}
+
 impl AsyncStorage for AsyncStorageApiProvider {
     async fn get_contract(&self, id: &ContractId) -> Result<Option<DlcContract>, Error> {
         let cid = get_contract_id_string(*id);
@@ -208,6 +290,17 @@ impl AsyncStorage for AsyncStorageApiProvider {
         Ok(res)
     }
 
+    async fn get_accepted_contracts(&self) -> Result<Vec<AcceptedContract>, Error> {
+        let contracts_per_state = self.get_contracts_by_state("accepted".to_string()).await?;
+        let mut res: Vec<AcceptedContract> = Vec::new();
+        for val in contracts_per_state {
+            if let DlcContract::Accepted(c) = val {
+                res.push(c.clone());
+            }
+        }
+        Ok(res)
+    }
+
     async fn get_signed_contracts(&self) -> Result<Vec<SignedContract>, Error> {
         let contracts_per_state = self.get_contracts_by_state("signed".to_string()).await?;
         let mut res: Vec<SignedContract> = Vec::new();
@@ -242,4 +335,255 @@ impl AsyncStorage for AsyncStorageApiProvider {
         }
         Ok(res)
     }
+
+    async fn get_contract_metadata(
+        &self,
+        id: &ContractId,
+    ) -> Result<Option<ContractMetadata>, Error> {
+        let event = self
+            .client
+            .get_event(
+                EventRequestParams {
+                    key: self.public_key.clone(),
+                    event_id: contract_metadata_event_id(id),
+                },
+                self.secret_key,
+            )
+            .await
+            .map_err(to_storage_error)?;
+        match event {
+            Some(event) => Ok(Some(
+                serde_json::from_str(&event.content).map_err(to_storage_error)?,
+            )),
+            None => Ok(None),
+        }
+    }
+
+    async fn upsert_contract_metadata(
+        &self,
+        id: &ContractId,
+        metadata: &ContractMetadata,
+    ) -> Result<(), Error> {
+        let content = serde_json::to_string(metadata).map_err(to_storage_error)?;
+        match self
+            .client
+            .update_event(
+                UpdateEvent {
+                    event_id: contract_metadata_event_id(id),
+                    content: content.clone(),
+                    key: self.public_key.clone(),
+                },
+                self.secret_key,
+            )
+            .await
+        {
+            Ok(_) => Ok(()),
+            Err(_) => {
+                self.client
+                    .create_event(
+                        NewEvent {
+                            event_id: contract_metadata_event_id(id),
+                            content,
+                            key: self.public_key.clone(),
+                        },
+                        self.secret_key,
+                    )
+                    .await
+                    .map_err(to_storage_error)?;
+                Ok(())
+            }
+        }
+    }
+
+    async fn get_contract_id_by_event_id(
+        &self,
+        event_id: &str,
+    ) -> Result<Option<ContractId>, Error> {
+        let event = self
+            .client
+            .get_event(
+                EventRequestParams {
+                    key: self.public_key.clone(),
+                    event_id: contract_event_index_event_id(event_id),
+                },
+                self.secret_key,
+            )
+            .await
+            .map_err(to_storage_error)?;
+        match event {
+            Some(event) => parse_contract_id_string(&event.content)
+                .map(Some)
+                .ok_or_else(|| {
+                    to_storage_error(format!(
+                        "Invalid contract id indexed under event {}",
+                        event_id
+                    ))
+                }),
+            None => Ok(None),
+        }
+    }
+
+    async fn index_contract_event_id(&self, event_id: &str, id: &ContractId) -> Result<(), Error> {
+        let content = get_contract_id_string(*id);
+        match self
+            .client
+            .update_event(
+                UpdateEvent {
+                    event_id: contract_event_index_event_id(event_id),
+                    content: content.clone(),
+                    key: self.public_key.clone(),
+                },
+                self.secret_key,
+            )
+            .await
+        {
+            Ok(_) => Ok(()),
+            Err(_) => {
+                self.client
+                    .create_event(
+                        NewEvent {
+                            event_id: contract_event_index_event_id(event_id),
+                            content,
+                            key: self.public_key.clone(),
+                        },
+                        self.secret_key,
+                    )
+                    .await
+                    .map_err(to_storage_error)?;
+                Ok(())
+            }
+        }
+    }
+
+    async fn journal_contract_event(&self, event: &ContractEvent) -> Result<(), Error> {
+        let event_id = contract_journal_event_id(event);
+        let created = self
+            .client
+            .create_event(
+                NewEvent {
+                    event_id: event_id.clone(),
+                    content: serde_json::to_string(event).map_err(to_storage_error)?,
+                    key: self.public_key.clone(),
+                },
+                self.secret_key,
+            )
+            .await;
+        match created {
+            Ok(_) => Ok(()),
+            // The storage rejects a second event with the same id
+            Err(e) => match self
+                .client
+                .get_event(
+                    EventRequestParams {
+                        key: self.public_key.clone(),
+                        event_id,
+                    },
+                    self.secret_key,
+                )
+                .await
+                .map_err(to_storage_error)?
+            {
+                Some(_) => Ok(()),
+                None => Err(to_storage_error(e)),
+            },
+        }
+    }
+
+    async fn get_journaled_contract_events(&self) -> Result<Vec<ContractEvent>, Error> {
+        let events = self
+            .client
+            .get_events(
+                EventsRequestParams {
+                    key: self.public_key.clone(),
+                    event_id: None,
+                    event_id_prefix: Some(CONTRACT_JOURNAL_PREFIX.to_string()),
+                },
+                self.secret_key,
+            )
+            .await
+            .map_err(to_storage_error)?;
+        events
+            .iter()
+            .map(|event| serde_json::from_str(&event.content).map_err(to_storage_error))
+            .collect()
+    }
+
+    async fn remove_journaled_contract_event(&self, event: &ContractEvent) -> Result<(), Error> {
+        self.client
+            .delete_event(
+                EventRequestParams {
+                    key: self.public_key.clone(),
+                    event_id: contract_journal_event_id(event),
+                },
+                self.secret_key,
+            )
+            .await
+            .map_err(to_storage_error)
+    }
+
+    async fn acquire_contract_lease(
+        &self,
+        id: &ContractId,
+        owner: &str,
+        now: u64,
+        expires_at: u64,
+    ) -> Result<bool, Error> {
+        let event_id = contract_lease_event_id(id);
+        let content = serde_json::to_string(&ContractLease {
+            owner: owner.to_string(),
+            expires_at,
+        })
+        .map_err(to_storage_error)?;
+
+        if let Some((lease, current_content)) = self.get_contract_lease(&event_id).await? {
+            if lease.owner != owner && lease.expires_at > now {
+                return Ok(false);
+            }
+            // Our own lease is renewed and an expired one taken over only if
+            // no other replica changed it since it was read.
+            return self
+                .swap_contract_lease(&event_id, current_content, content)
+                .await;
+        }
+
+        // The storage rejects a second event with the same id, so only one
+        // replica can create the lease.
+        match self
+            .client
+            .create_event(
+                NewEvent {
+                    event_id: event_id.clone(),
+                    content,
+                    key: self.public_key.clone(),
+                },
+                self.secret_key,
+            )
+            .await
+        {
+            Ok(_) => Ok(true),
+            Err(e) => match self.get_contract_lease(&event_id).await? {
+                Some((lease, _)) if lease.owner != owner => Ok(false),
+                _ => Err(to_storage_error(e)),
+            },
+        }
+    }
+
+    async fn release_contract_lease(&self, id: &ContractId, owner: &str) -> Result<(), Error> {
+        let event_id = contract_lease_event_id(id);
+        match self.get_contract_lease(&event_id).await? {
+            // The lease is expired rather than deleted, so that a replica that
+            // took it over in the meantime keeps it.
+            Some((lease, current_content)) if lease.owner == owner => {
+                let content = serde_json::to_string(&ContractLease {
+                    owner: owner.to_string(),
+                    expires_at: 0,
+                })
+                .map_err(to_storage_error)?;
+                self.swap_contract_lease(&event_id, current_content, content)
+                    .await
+                    .map(|_| ())
+            }
+            _ => Ok(()),
+        }
+    }
 }
@@ -153,6 +153,15 @@ pub struct UpdateEvent {
     pub key: String,
 }
 
+/// An update of an event applied only while it holds `expected_content`.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+pub struct SwapEvent {
+    pub event_id: String,
+    pub content: String,
+    pub expected_content: String,
+    pub key: String,
+}
+
 #[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
 struct SignedEventsRequestParams {
     key: String,
@@ -162,6 +171,12 @@ struct SignedEventsRequestParams {
         with = "::serde_with::rust::unwrap_or_skip"
     )]
     event_id: Option<String>,
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "::serde_with::rust::unwrap_or_skip"
+    )]
+    event_id_prefix: Option<String>,
     signature: String,
 }
 
@@ -175,6 +190,8 @@ pub struct EventRequestParams {
 pub struct EventsRequestParams {
     pub key: String,
     pub event_id: Option<String>,
+    /// Only the events whose id starts with the prefix are returned.
+    pub event_id_prefix: Option<String>,
 }
 
 #[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
@@ -317,6 +334,7 @@ impl StorageApiClient {
         let signed_request_params = SignedEventsRequestParams {
             key: event_req.key.clone(),
             event_id: event_req.event_id.clone(),
+            event_id_prefix: event_req.event_id_prefix.clone(),
             signature: sig.to_string(),
         };
 
@@ -349,6 +367,7 @@ impl StorageApiClient {
                 EventsRequestParams {
                     key: event_req.key.clone(),
                     event_id: Some(event_req.event_id.clone()),
+                    event_id_prefix: None,
                 },
                 secret_key,
             )
@@ -457,6 +476,41 @@ impl StorageApiClient {
         }
     }
 
+    /// Updates the content of an event only if it still holds the expected
+    /// content. Returns whether the event was updated.
+    pub async fn swap_event(
+        &self,
+        event: SwapEvent,
+        secret_key: SecretKey,
+    ) -> Result<bool, ApiError> {
+        let uri = format!("{}/events/swap", String::as_str(&self.host.clone()));
+        debug!("calling event swap on url: {:?}", uri);
+
+        let (nonce, message_body) = self.build_signed_message(secret_key, json!(event)).await?;
+
+        let res = self
+            .client
+            .put(uri)
+            .header("authorization", nonce)
+            .json(&message_body)
+            .send()
+            .await?;
+        let status = res.status();
+        if status == reqwest::StatusCode::CONFLICT {
+            return Ok(false);
+        }
+        res.json::<EffectedNumResponse>()
+            .await
+            .map_err(|e| ApiError {
+                message: format!(
+                    "Swapping event failed, response from API not a number, error: {}",
+                    e
+                ),
+                status: status.into(),
+            })?;
+        Ok(true)
+    }
+
     pub async fn update_contract(
         &self,
         contract: UpdateContract,
@@ -154,3 +154,16 @@ pub fn get_contract_id_string(contract_id: [u8; 32]) -> String {
     }
     string_id
 }
+
+/// Parses a contract id formatted by [`get_contract_id_string`].
+pub fn parse_contract_id_string(string_id: &str) -> Option<[u8; 32]> {
+    let hex = string_id.strip_prefix("0x")?;
+    if hex.len() != 64 || !hex.is_ascii() {
+        return None;
+    }
+    let mut contract_id = [0u8; 32];
+    for (i, byte) in contract_id.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).ok()?;
+    }
+    Some(contract_id)
+}
@@ -0,0 +1,102 @@
+//! A view of a contract for operators, gathering its state, transactions
+//! and oracles.
+
+use crate::contract_metadata::{ContractMetadata, ContractSummary};
+use crate::dlc_manager::contract::{
+    accepted_contract::AcceptedContract, offered_contract::OfferedContract, Contract,
+};
+use crate::events::{get_oracle_event_ids, ContractState};
+use bitcoin::Txid;
+use secp256k1_zkp::XOnlyPublicKey;
+use serde::Serialize;
+
+/// The details of a contract, see
+/// [`Manager::get_contract_details`](crate::Manager::get_contract_details).
+/// Values depending on a stage the contract has not reached are `None`.
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ContractDetails {
+    pub contract_id: String,
+    pub state: ContractState,
+    pub oracle_event_ids: Vec<String>,
+    pub offer_collateral: Option<u64>,
+    pub accept_collateral: Option<u64>,
+    pub funding: Option<TransactionStatus>,
+    pub cet: Option<TransactionStatus>,
+    pub refund: Option<TransactionStatus>,
+    pub refund_locktime: Option<u32>,
+    pub oracles: Vec<OracleStatus>,
+    /// Profit and loss of the contract once closed.
+    pub pnl: Option<i64>,
+    pub metadata: ContractMetadata,
+}
+
+/// A transaction of the contract and its confirmations, `None` when the
+/// blockchain does not know the transaction.
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TransactionStatus {
+    pub txid: Txid,
+    pub confirmations: Option<u32>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OracleStatus {
+    pub public_key: XOnlyPublicKey,
+    pub attested: bool,
+}
+
+impl From<&AcceptedContract> for ContractSummary {
+    fn from(accepted_contract: &AcceptedContract) -> Self {
+        let offered_contract = &accepted_contract.offered_contract;
+        let dlc_transactions = &accepted_contract.dlc_transactions;
+        ContractSummary {
+            oracle_event_ids: get_oracle_event_ids(offered_contract),
+            oracle_public_keys: oracle_public_keys(offered_contract),
+            offer_collateral: offered_contract.offer_params.collateral,
+            total_collateral: offered_contract.total_collateral,
+            fund_txid: dlc_transactions.fund.txid(),
+            refund_txid: dlc_transactions.refund.txid(),
+            refund_locktime: dlc_transactions.refund.lock_time.0,
+        }
+    }
+}
+
+/// Returns the accepted contract a contract holds, if any.
+pub(crate) fn get_accepted_contract(contract: &Contract) -> Option<&AcceptedContract> {
+    match contract {
+        Contract::Accepted(c) => Some(c),
+        Contract::Signed(c) | Contract::Confirmed(c) | Contract::Refunded(c) => {
+            Some(&c.accepted_contract)
+        }
+        Contract::PreClosed(c) => Some(&c.signed_contract.accepted_contract),
+        Contract::FailedSign(c) => Some(&c.accepted_contract),
+        _ => None,
+    }
+}
+
+/// Returns the offer a contract originates from, closed contracts no longer
+/// hold it.
+pub(crate) fn get_offered_contract(contract: &Contract) -> Option<&OfferedContract> {
+    match contract {
+        Contract::Offered(c) | Contract::Rejected(c) => Some(c),
+        Contract::FailedAccept(c) => Some(&c.offered_contract),
+        _ => get_accepted_contract(contract).map(|c| &c.offered_contract),
+    }
+}
+
+pub(crate) fn oracle_public_keys(offered_contract: &OfferedContract) -> Vec<XOnlyPublicKey> {
+    let mut keys: Vec<XOnlyPublicKey> = Vec::new();
+    for key in offered_contract
+        .contract_info
+        .iter()
+        .flat_map(|info| info.oracle_announcements.iter())
+        .map(|announcement| announcement.oracle_public_key)
+    {
+        if !keys.contains(&key) {
+            keys.push(key);
+        }
+    }
+    keys
+}
@@ -0,0 +1,39 @@
+//! In-process mutual exclusion between the tasks working on a contract.
+
+use dlc_manager::ContractId;
+use std::collections::HashSet;
+use std::sync::Mutex;
+
+/// The set of contracts currently being worked on.
+#[derive(Default)]
+pub(crate) struct ContractLocks {
+    locked: Mutex<HashSet<ContractId>>,
+}
+
+impl ContractLocks {
+    /// Locks the contract, or returns `None` if it is already locked.
+    pub(crate) fn try_lock(&self, id: &ContractId) -> Option<ContractLockGuard<'_>> {
+        let mut locked = self.locked.lock().ok()?;
+        if !locked.insert(*id) {
+            return None;
+        }
+        Some(ContractLockGuard {
+            locks: self,
+            id: *id,
+        })
+    }
+}
+
+/// Unlocks the contract when dropped.
+pub(crate) struct ContractLockGuard<'a> {
+    locks: &'a ContractLocks,
+    id: ContractId,
+}
+
+impl Drop for ContractLockGuard<'_> {
+    fn drop(&mut self) {
+        if let Ok(mut locked) = self.locks.locked.lock() {
+            locked.remove(&self.id);
+        }
+    }
+}
@@ -0,0 +1,152 @@
+//! Bookkeeping the manager stores next to a contract, for facts the
+//! dlc-manager contract states have no room for.
+
+use bitcoin::{BlockHash, Txid};
+use secp256k1_zkp::XOnlyPublicKey;
+use serde::{Deserialize, Serialize};
+
+/// The number of retries of a failed contract kept in its metadata.
+pub const MAX_RETRY_HISTORY: usize = 10;
+
+/// Manager owned data kept alongside a contract in the storage.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ContractMetadata {
+    /// Unix time at which the contract was offered.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub offered_at: Option<u64>,
+    /// Unix time at which the offer was rejected for not being signed in
+    /// time.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expired_at: Option<u64>,
+    /// Set once the refund transaction of a confirmed contract has been
+    /// broadcast. The contract stays in the confirmed state until the refund
+    /// has enough confirmations.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub refund_broadcast: Option<RefundBroadcast>,
+    /// Set when the funding output was spent by a transaction that is
+    /// neither one of the contract CETs nor its refund.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub suspected_fraud: Option<SuspectedFraud>,
+    /// Block the funding transaction was confirmed in when the contract was
+    /// last checked.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub funding_anchor: Option<ConfirmationAnchor>,
+    /// Unix time at which a chain reorganisation last moved the confirmed
+    /// contract back to signed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub funding_reorged_at: Option<u64>,
+    /// Block the CET of a pre-closed contract was confirmed in when the
+    /// contract was last checked.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cet_anchor: Option<ConfirmationAnchor>,
+    /// Transactions of the contract found waiting in the mempool, one per
+    /// txid, used to tell for how long each has been stuck.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub unconfirmed_transactions: Vec<UnconfirmedTransaction>,
+    /// Child transactions broadcast to bump the fee of stuck transactions of
+    /// the contract.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub fee_bumps: Vec<FeeBump>,
+    /// The latest retries of the contract after it failed, oldest first.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub retries: Vec<RetryAttempt>,
+    /// Number of retries of the contract, including those no longer in
+    /// `retries`.
+    #[serde(default)]
+    pub total_retries: u32,
+    /// What the contract was made of, recorded once closed as the closed
+    /// state no longer holds it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub closed_summary: Option<ContractSummary>,
+}
+
+impl ContractMetadata {
+    /// Number of times the contract was retried. Metadata stored before
+    /// `total_retries` was counted only has the retry history.
+    pub fn retry_count(&self) -> u32 {
+        self.total_retries.max(self.retries.len() as u32)
+    }
+
+    /// Records a retry, dropping the oldest ones beyond
+    /// [`MAX_RETRY_HISTORY`].
+    pub fn push_retry(&mut self, retry: RetryAttempt) {
+        self.total_retries = self.retry_count() + 1;
+        self.retries.push(retry);
+        if self.retries.len() > MAX_RETRY_HISTORY {
+            let excess = self.retries.len() - MAX_RETRY_HISTORY;
+            self.retries.drain(..excess);
+        }
+    }
+}
+
+/// A retry of a contract that failed accepting or signing.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RetryAttempt {
+    /// Unix time of the retry.
+    pub attempted_at: u64,
+    /// Error of the retry, `None` if it succeeded.
+    pub error: Option<String>,
+}
+
+/// A broadcast refund transaction waiting for its confirmations.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RefundBroadcast {
+    pub txid: Txid,
+    /// Unix time of the first broadcast, or at which the refund was found
+    /// on chain if it was broadcast by the counterparty.
+    pub broadcast_at: u64,
+    /// Unix time of the latest broadcast.
+    pub last_broadcast_at: u64,
+    /// Number of times the refund was broadcast by this party.
+    pub broadcast_count: u32,
+}
+
+/// An unknown transaction spending the funding output of a contract.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SuspectedFraud {
+    pub spending_txid: Txid,
+    /// Unix time at which the spend was detected.
+    pub detected_at: u64,
+}
+
+/// The block a transaction was counted as confirmed in. The confirmation no
+/// longer holds once the block leaves the best chain.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConfirmationAnchor {
+    pub txid: Txid,
+    pub block_hash: BlockHash,
+}
+
+/// A transaction of the contract seen unconfirmed.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UnconfirmedTransaction {
+    pub txid: Txid,
+    /// Unix time at which the transaction was first seen unconfirmed.
+    pub first_seen_at: u64,
+}
+
+/// A child-pays-for-parent transaction spending an output of ours.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FeeBump {
+    pub parent_txid: Txid,
+    pub child_txid: Txid,
+    /// Fee rate of the parent and child together, in sats per vbyte.
+    pub fee_rate: u64,
+    /// Fee paid by the child, in sats.
+    pub fee: u64,
+    /// Unix time of the broadcast of the child.
+    pub bumped_at: u64,
+}
+
+/// The offer and transactions of a contract.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ContractSummary {
+    /// Oracle event ids of the legs of the contract.
+    pub oracle_event_ids: Vec<String>,
+    pub oracle_public_keys: Vec<XOnlyPublicKey>,
+    pub offer_collateral: u64,
+    pub total_collateral: u64,
+    pub fund_txid: Txid,
+    pub refund_txid: Txid,
+    pub refund_locktime: u32,
+}
@@ -0,0 +1,193 @@
+//! Contract state transitions published to the subscribers of a
+//! [`Manager`](crate::Manager).
+
+use crate::dlc_manager::contract::{
+    offered_contract::OfferedContract, signed_contract::SignedContract, Contract,
+};
+use bitcoin::Txid;
+use dlc_manager::ContractId;
+use serde::{Deserialize, Serialize};
+
+/// The state of a contract, without the associated data.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ContractState {
+    Offered,
+    Accepted,
+    Signed,
+    Confirmed,
+    PreClosed,
+    Closed,
+    Refunded,
+    FailedAccept,
+    FailedSign,
+    Rejected,
+}
+
+impl From<&Contract> for ContractState {
+    fn from(contract: &Contract) -> Self {
+        match contract {
+            Contract::Offered(_) => ContractState::Offered,
+            Contract::Accepted(_) => ContractState::Accepted,
+            Contract::Signed(_) => ContractState::Signed,
+            Contract::Confirmed(_) => ContractState::Confirmed,
+            Contract::PreClosed(_) => ContractState::PreClosed,
+            Contract::Closed(_) => ContractState::Closed,
+            Contract::Refunded(_) => ContractState::Refunded,
+            Contract::FailedAccept(_) => ContractState::FailedAccept,
+            Contract::FailedSign(_) => ContractState::FailedSign,
+            Contract::Rejected(_) => ContractState::Rejected,
+        }
+    }
+}
+
+/// A contract moved from one state to another. `from` is `None` for newly
+/// stored contracts.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ContractEvent {
+    pub contract_id: ContractId,
+    pub from: Option<ContractState>,
+    pub to: ContractState,
+    /// Oracle event id of the leg the event is about, the uuid of the DLC.Link
+    /// contract. For a contract closed by a CET this is the leg of the CET,
+    /// otherwise the first leg.
+    pub oracle_event_id: Option<String>,
+    /// Oracle event ids of all the legs of the contract.
+    pub oracle_event_ids: Vec<String>,
+    pub fund_txid: Option<Txid>,
+    pub cet_txid: Option<Txid>,
+    pub refund_txid: Option<Txid>,
+    /// Profit and loss of the contract once closed.
+    pub pnl: Option<i64>,
+    /// Set when the contract reaches again a state a chain reorganisation
+    /// moved it back from, which subscribers were already told about.
+    #[serde(default)]
+    pub after_reorg: bool,
+}
+
+impl ContractEvent {
+    /// Describes the transition of a contract to the state of `to`.
+    /// `offered_contract` is the offer the contract originates from, as
+    /// closed contracts no longer hold it.
+    pub(crate) fn new(
+        from: Option<ContractState>,
+        to: &Contract,
+        offered_contract: &OfferedContract,
+    ) -> Self {
+        let accepted_contract = match to {
+            Contract::Accepted(c) => Some(c),
+            Contract::Signed(c) | Contract::Confirmed(c) | Contract::Refunded(c) => {
+                Some(&c.accepted_contract)
+            }
+            Contract::PreClosed(c) => Some(&c.signed_contract.accepted_contract),
+            Contract::FailedSign(c) => Some(&c.accepted_contract),
+            _ => None,
+        };
+
+        let (cet_txid, pnl) = match to {
+            Contract::PreClosed(c) => (Some(c.signed_cet.txid()), None),
+            Contract::Closed(c) => (c.signed_cet.as_ref().map(|cet| cet.txid()), Some(c.pnl)),
+            _ => (None, None),
+        };
+
+        ContractEvent {
+            contract_id: to.get_id(),
+            from,
+            to: ContractState::from(to),
+            oracle_event_id: get_oracle_event_id(offered_contract),
+            oracle_event_ids: get_oracle_event_ids(offered_contract),
+            fund_txid: accepted_contract.map(|c| c.dlc_transactions.fund.txid()),
+            cet_txid,
+            refund_txid: match to {
+                Contract::Refunded(c) => Some(c.accepted_contract.dlc_transactions.refund.txid()),
+                _ => None,
+            },
+            pnl,
+            after_reorg: false,
+        }
+    }
+
+    /// Id of the event in the journal of the storage, the same for every
+    /// occurrence of the transition.
+    pub fn journal_id(&self) -> String {
+        let contract_id: String = self
+            .contract_id
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect();
+        match self.from {
+            Some(from) => format!("{}-{:?}-{:?}", contract_id, from, self.to),
+            None => format!("{}-{:?}", contract_id, self.to),
+        }
+    }
+
+    /// Marks the transition as undoing a move back caused by a chain
+    /// reorganisation.
+    pub(crate) fn after_reorg(mut self) -> Self {
+        self.after_reorg = true;
+        self
+    }
+
+    /// Reports the leg of the CET of a closing contract instead of the first
+    /// leg.
+    pub(crate) fn with_cet_leg(mut self, signed_contract: &SignedContract) -> Self {
+        if let Some(cet_leg) = self
+            .cet_txid
+            .and_then(|txid| get_cet_oracle_event_id(signed_contract, &txid))
+        {
+            self.oracle_event_id = Some(cet_leg);
+        }
+        self
+    }
+}
+
+/// Returns the event id of the first oracle announcement of the contract.
+pub(crate) fn get_oracle_event_id(offered_contract: &OfferedContract) -> Option<String> {
+    offered_contract
+        .contract_info
+        .first()
+        .and_then(|info| info.oracle_announcements.first())
+        .map(|announcement| announcement.oracle_event.event_id.clone())
+}
+
+/// Returns the event ids of the legs of the contract, one per contract info.
+pub(crate) fn get_oracle_event_ids(offered_contract: &OfferedContract) -> Vec<String> {
+    let mut event_ids: Vec<String> = Vec::new();
+    for event_id in offered_contract
+        .contract_info
+        .iter()
+        .filter_map(|info| info.oracle_announcements.first())
+        .map(|announcement| &announcement.oracle_event.event_id)
+    {
+        if !event_ids.contains(event_id) {
+            event_ids.push(event_id.clone());
+        }
+    }
+    event_ids
+}
+
+/// Returns the event id of the leg the given CET belongs to. The CETs of a
+/// contract are ordered by contract info, with one CET per payout of each.
+pub(crate) fn get_cet_oracle_event_id(
+    signed_contract: &SignedContract,
+    cet_txid: &Txid,
+) -> Option<String> {
+    let accepted_contract = &signed_contract.accepted_contract;
+    let cet_index = accepted_contract
+        .dlc_transactions
+        .cets
+        .iter()
+        .position(|cet| cet.txid() == *cet_txid)?;
+    let total_collateral = accepted_contract.offered_contract.total_collateral;
+
+    let mut leg_start = 0;
+    for contract_info in &accepted_contract.offered_contract.contract_info {
+        leg_start += contract_info.get_payouts(total_collateral).ok()?.len();
+        if cet_index < leg_start {
+            return contract_info
+                .oracle_announcements
+                .first()
+                .map(|announcement| announcement.oracle_event.event_id.clone());
+        }
+    }
+    None
+}
@@ -0,0 +1,141 @@
+//! Fee rates returned to LDK as a `FeeEstimator` by the blockchain
+//! providers, from the estimates of Esplora by confirmation target.
+
+use bitcoin::Network;
+use lightning::chain::chaininterface::{ConfirmationTarget, FEERATE_FLOOR_SATS_PER_KW};
+use std::collections::HashMap;
+
+/// Bounds of the fee rates returned as a `FeeEstimator`, in sats/vbyte.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FeeRateBounds {
+    /// Estimates are raised to this rate, or to the floor of LDK if higher.
+    pub min_fee_rate: u64,
+    /// Estimates are lowered to this rate.
+    pub max_fee_rate: u64,
+    /// Rate used until estimates are fetched.
+    pub fallback_fee_rate: u64,
+}
+
+impl FeeRateBounds {
+    /// Bounds of a wallet without fee settings. Regtest Esplora instances
+    /// usually have no fee estimates, so the fallback is 1 there.
+    pub fn for_network(network: Network) -> Self {
+        FeeRateBounds {
+            min_fee_rate: 1,
+            max_fee_rate: 1000,
+            fallback_fee_rate: match network {
+                Network::Regtest => 1,
+                _ => 400,
+            },
+        }
+    }
+}
+
+/// Returns the estimate for a confirmation within `confirmation_target`
+/// blocks. When there is none for that target, the estimate of the closest
+/// longer target is used, or of the longest one.
+pub fn select_fee_rate(estimates: &HashMap<u16, f64>, confirmation_target: u16) -> Option<f64> {
+    let mut estimates: Vec<(u16, f64)> = estimates.iter().map(|(t, r)| (*t, *r)).collect();
+    estimates.sort_by_key(|(target, _)| *target);
+    estimates
+        .iter()
+        .find(|(target, _)| *target >= confirmation_target)
+        .or_else(|| estimates.last())
+        .map(|(_, rate)| *rate)
+}
+
+/// Number of blocks to confirm within for each target of the `FeeEstimator`.
+pub fn confirmation_blocks(confirmation_target: ConfirmationTarget) -> u16 {
+    match confirmation_target {
+        ConfirmationTarget::HighPriority => 6,
+        ConfirmationTarget::Normal => 18,
+        _ => 144,
+    }
+}
+
+/// Converts a fee rate in sats/vbyte to sats per 1000 weight units, within
+/// `bounds` and the floor of LDK.
+pub fn to_sat_per_1000_weight(fee_rate: f64, bounds: &FeeRateBounds) -> u32 {
+    // A vbyte is 4 weight units, the casts saturate
+    let per_1000_weight = |rate: u64| rate.saturating_mul(250).min(u32::MAX as u64) as u32;
+    let min = per_1000_weight(bounds.min_fee_rate).max(FEERATE_FLOOR_SATS_PER_KW);
+    let max = per_1000_weight(bounds.max_fee_rate).max(min);
+    ((fee_rate * 250.0).ceil() as u32).clamp(min, max)
+}
+
+/// The fee rate to confirm within `confirmation_target` from the fetched
+/// `estimates`, the fallback rate when there are none.
+pub fn estimate_sat_per_1000_weight(
+    estimates: &HashMap<u16, f64>,
+    confirmation_target: ConfirmationTarget,
+    bounds: &FeeRateBounds,
+) -> u32 {
+    let fee_rate = select_fee_rate(estimates, confirmation_blocks(confirmation_target))
+        .unwrap_or(bounds.fallback_fee_rate as f64);
+    to_sat_per_1000_weight(fee_rate, bounds)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn estimates() -> HashMap<u16, f64> {
+        HashMap::from([(1, 50.5), (6, 20.0), (144, 2.0), (1008, 0.5)])
+    }
+
+    fn bounds() -> FeeRateBounds {
+        FeeRateBounds {
+            min_fee_rate: 1,
+            max_fee_rate: 1000,
+            fallback_fee_rate: 400,
+        }
+    }
+
+    #[test]
+    fn fee_rate_of_the_closest_longer_target_is_selected() {
+        assert_eq!(select_fee_rate(&estimates(), 6), Some(20.0));
+        assert_eq!(select_fee_rate(&estimates(), 18), Some(2.0));
+        assert_eq!(select_fee_rate(&estimates(), 0), Some(50.5));
+        assert_eq!(select_fee_rate(&estimates(), 2000), Some(0.5));
+        assert_eq!(select_fee_rate(&HashMap::new(), 6), None);
+    }
+
+    #[test]
+    fn fee_rate_is_converted_within_the_bounds() {
+        assert_eq!(to_sat_per_1000_weight(20.0, &bounds()), 5000);
+        assert_eq!(to_sat_per_1000_weight(2.001, &bounds()), 501);
+        assert_eq!(
+            to_sat_per_1000_weight(0.5, &bounds()),
+            FEERATE_FLOOR_SATS_PER_KW
+        );
+        assert_eq!(to_sat_per_1000_weight(5000.0, &bounds()), 1000 * 250);
+        assert_eq!(
+            to_sat_per_1000_weight(f64::NAN, &bounds()),
+            FEERATE_FLOOR_SATS_PER_KW
+        );
+
+        let bounds = FeeRateBounds {
+            min_fee_rate: 5,
+            max_fee_rate: 50,
+            fallback_fee_rate: 10,
+        };
+        assert_eq!(to_sat_per_1000_weight(2.0, &bounds), 5 * 250);
+        assert_eq!(to_sat_per_1000_weight(100.0, &bounds), 50 * 250);
+    }
+
+    #[test]
+    fn fallback_rate_is_used_without_estimates() {
+        assert_eq!(
+            estimate_sat_per_1000_weight(&HashMap::new(), ConfirmationTarget::Normal, &bounds()),
+            400 * 250
+        );
+        assert_eq!(
+            estimate_sat_per_1000_weight(&estimates(), ConfirmationTarget::HighPriority, &bounds()),
+            5000
+        );
+        assert_eq!(
+            estimate_sat_per_1000_weight(&estimates(), ConfirmationTarget::Normal, &bounds()),
+            500
+        );
+    }
+}
@@ -14,26 +14,69 @@ use crate::dlc_manager::contract::{
 };
 use crate::dlc_manager::contract_updater::{accept_contract, verify_accepted_and_sign_contract};
 use crate::dlc_manager::error::Error;
-use crate::dlc_manager::{Blockchain, Time, Wallet};
+use crate::dlc_manager::{Blockchain, Signer, Time, Wallet};
 
-use bitcoin::{Address, Transaction, Txid};
+use bitcoin::{
+    Address, BlockHash, OutPoint, PackedLockTime, Script, Sequence, Transaction, TxIn, TxOut, Txid,
+    Witness,
+};
 
 use dlc_manager::ContractId;
 use dlc_messages::oracle_msgs::{OracleAnnouncement, OracleAttestation};
 use dlc_messages::{AcceptDlc, Message as DlcMessage, OfferDlc, SignDlc};
 
+use futures::channel::mpsc::{unbounded, UnboundedReceiver, UnboundedSender};
+use futures::future::{self, Either};
+use futures::stream::{self, FuturesUnordered, StreamExt};
+use futures_timer::Delay;
+
 use log::*;
 use secp256k1_zkp::XOnlyPublicKey;
 use secp256k1_zkp::{All, PublicKey, Secp256k1};
 use std::collections::HashMap;
+use std::future::Future;
 use std::ops::Deref;
 use std::string::ToString;
+use std::sync::Mutex;
+
+mod contract_details;
+mod contract_lock;
+mod contract_metadata;
+mod events;
+mod fee_estimates;
+mod manager_config;
+mod offer_policy;
+mod signed_envelope;
+use contract_details::{get_accepted_contract, get_offered_contract, oracle_public_keys};
+pub use contract_details::{ContractDetails, OracleStatus, TransactionStatus};
+use contract_lock::ContractLocks;
+pub use contract_metadata::{
+    ConfirmationAnchor, ContractMetadata, ContractSummary, FeeBump, RefundBroadcast, RetryAttempt,
+    SuspectedFraud, UnconfirmedTransaction, MAX_RETRY_HISTORY,
+};
+use events::{get_cet_oracle_event_id, get_oracle_event_ids};
+pub use events::{ContractEvent, ContractState};
+pub use fee_estimates::{
+    confirmation_blocks, estimate_sat_per_1000_weight, select_fee_rate, to_sat_per_1000_weight,
+    FeeRateBounds,
+};
+pub use manager_config::{
+    CollateralConfirmationDepths, ConfirmationDepths, ContractLeaseConfig, FeeBumpConfig,
+    ManagerConfig, DEFAULT_CHECK_CONCURRENCY, DEFAULT_MAX_RETRIES,
+};
+pub use offer_policy::OfferAcceptancePolicy;
+pub use signed_envelope::SignedEnvelope;
 
-/// The number of confirmations required before moving the the confirmed state.
+/// The default number of confirmations required before moving the the
+/// confirmed state.
 pub const NB_CONFIRMATIONS: u32 = 6;
 /// The upper bound for the delay refund verification check, 10 years.
 pub const FIFTY_YEARS: u32 = 86400 * 365 * 50;
 pub const ONE_DAY_IN_SECONDS: u32 = 86400;
+/// Virtual size of a child spending a P2WPKH output of ours to a new one.
+const CPFP_CHILD_VSIZE: u64 = 110;
+/// The smallest P2WPKH output nodes relay.
+const P2WPKH_DUST_LIMIT: u64 = 294;
 /// The nSequence value used for CETs in DLC channels
 pub const CET_NSEQUENCE: u32 = 288;
 /// Timeout in seconds when waiting for a peer's reply, after which a DLC channel
@@ -69,6 +112,8 @@ pub trait AsyncStorage {
     async fn update_contract(&self, contract: &Contract) -> Result<(), Error>;
     /// Returns the set of contracts in offered state.
     async fn get_contract_offers(&self) -> Result<Vec<OfferedContract>, Error>;
+    /// Returns the set of contracts in accepted state.
+    async fn get_accepted_contracts(&self) -> Result<Vec<AcceptedContract>, Error>;
     /// Returns the set of contracts in signed state.
     async fn get_signed_contracts(&self) -> Result<Vec<SignedContract>, Error>;
     /// Returns the set of confirmed contracts.
@@ -76,6 +121,45 @@ pub trait AsyncStorage {
     /// Returns the set of contracts whos broadcasted cet has not been verified to be confirmed on
     /// blockchain
     async fn get_preclosed_contracts(&self) -> Result<Vec<PreClosedContract>, Error>;
+    /// Returns the manager metadata of the contract with given id if found.
+    async fn get_contract_metadata(
+        &self,
+        id: &ContractId,
+    ) -> Result<Option<ContractMetadata>, Error>;
+    /// Create or replace the manager metadata of the contract with given id.
+    async fn upsert_contract_metadata(
+        &self,
+        id: &ContractId,
+        metadata: &ContractMetadata,
+    ) -> Result<(), Error>;
+    /// Returns the id of the contract indexed under the oracle event with
+    /// given id if found.
+    async fn get_contract_id_by_event_id(
+        &self,
+        event_id: &str,
+    ) -> Result<Option<ContractId>, Error>;
+    /// Indexes the contract with given id under the oracle event with given
+    /// id, replacing the contract indexed under it before.
+    async fn index_contract_event_id(&self, event_id: &str, id: &ContractId) -> Result<(), Error>;
+    /// Stores a contract event in the journal under its journal id. Storing
+    /// an event already in the journal does nothing.
+    async fn journal_contract_event(&self, event: &ContractEvent) -> Result<(), Error>;
+    /// Returns the contract events in the journal.
+    async fn get_journaled_contract_events(&self) -> Result<Vec<ContractEvent>, Error>;
+    /// Removes a contract event from the journal.
+    async fn remove_journaled_contract_event(&self, event: &ContractEvent) -> Result<(), Error>;
+    /// Takes the lease on a contract for `owner` until `expires_at`, unless
+    /// another owner holds a lease not yet expired at `now`. Returns whether
+    /// the lease was taken.
+    async fn acquire_contract_lease(
+        &self,
+        id: &ContractId,
+        owner: &str,
+        now: u64,
+        expires_at: u64,
+    ) -> Result<bool, Error>;
+    /// Releases the lease on a contract if held by `owner`.
+    async fn release_contract_lease(&self, id: &ContractId, owner: &str) -> Result<(), Error>;
 }
 
 pub trait AsyncBlockchain {
@@ -87,6 +171,34 @@ pub trait AsyncBlockchain {
     async fn get_network_async(&self) -> Result<bitcoin::Network, Error>;
 
     async fn get_transaction_async(&self, tx_id: &Txid) -> Result<Transaction, Error>;
+
+    /// Returns the transaction, or `None` if it is neither confirmed nor in
+    /// the mempool. Failing to reach the chain source is an error.
+    async fn find_transaction_async(&self, tx_id: &Txid) -> Result<Option<Transaction>, Error>;
+
+    /// Returns the id of the transaction spending the given output, if any.
+    async fn get_output_spending_txid_async(
+        &self,
+        outpoint: &OutPoint,
+    ) -> Result<Option<Txid>, Error>;
+
+    /// Returns the median time past of the chain tip, used to check that time
+    /// locked transactions can be broadcast. Providers that cannot report it
+    /// return `None`.
+    async fn get_median_time_past_async(&self) -> Result<Option<u64>, Error> {
+        Ok(None)
+    }
+
+    /// Returns the hash of the block the transaction is confirmed in, `None`
+    /// if it is unconfirmed.
+    async fn get_transaction_block_hash_async(
+        &self,
+        tx_id: &Txid,
+    ) -> Result<Option<BlockHash>, Error>;
+
+    /// Returns whether the block is part of the best chain, which it stops
+    /// being after a chain reorganisation.
+    async fn is_block_in_best_chain_async(&self, block_hash: &BlockHash) -> Result<bool, Error>;
 }
 
 fn calculate_denominator_from_basis_points(basis_points: u64) -> u64 {
@@ -96,6 +208,87 @@ fn calculate_denominator_from_basis_points(basis_points: u64) -> u64 {
     ((100.0 / basis_points as f64) * 100.0) as u64
 }
 
+/// Lists a contract once per oracle event id, the way `periodic_check`
+/// reports the legs of the contracts it updated. A contract without event id
+/// is logged and left out, rather than failing the report of the others.
+fn report_legs(contract_id: ContractId, event_ids: Vec<String>) -> Vec<(ContractId, String)> {
+    if event_ids.is_empty() {
+        error!(
+            "Missing oracle event ID of updated contract {:02x?}",
+            contract_id
+        );
+    }
+    event_ids
+        .into_iter()
+        .map(|event_id| (contract_id, event_id))
+        .collect()
+}
+
+/// Returns the first threshold sized subset of the attestations, sorted by
+/// announcement index, whose outcomes select a CET of the contract info.
+fn find_agreeing_attestations(
+    contract_info: &ContractInfo,
+    adaptor_info: &AdaptorInfo,
+    attestations: &[(usize, OracleAttestation)],
+) -> Option<Vec<(usize, OracleAttestation)>> {
+    let threshold = contract_info.threshold;
+    if threshold == 0 || attestations.len() < threshold {
+        return None;
+    }
+    let mut subset: Vec<usize> = (0..threshold).collect();
+    loop {
+        let outcomes: Vec<(usize, &Vec<String>)> = subset
+            .iter()
+            .map(|&position| {
+                let (i, attestation) = &attestations[position];
+                (*i, &attestation.outcomes)
+            })
+            .collect();
+        if contract_info
+            .get_range_info_for_outcome(adaptor_info, &outcomes, 0)
+            .is_some()
+        {
+            return Some(
+                subset
+                    .iter()
+                    .map(|&position| attestations[position].clone())
+                    .collect(),
+            );
+        }
+        if !next_combination(&mut subset, attestations.len()) {
+            return None;
+        }
+    }
+}
+
+/// Moves `combination`, sorted indexes below `n`, to the next combination of
+/// the same size in lexicographic order. Returns false after the last one.
+fn next_combination(combination: &mut [usize], n: usize) -> bool {
+    let k = combination.len();
+    let position = match (0..k).rev().find(|&p| combination[p] < n - k + p) {
+        Some(position) => position,
+        None => return false,
+    };
+    let start = combination[position] + 1;
+    for (offset, index) in combination[position..].iter_mut().enumerate() {
+        *index = start + offset;
+    }
+    true
+}
+
+/// Whether contracts in the given state are stored under the temporary id of
+/// their offer rather than their final id.
+fn is_stored_under_temporary_id(state: &ContractState) -> bool {
+    matches!(
+        state,
+        ContractState::Offered | ContractState::FailedAccept | ContractState::Rejected
+    )
+}
+
+fn contract_locked_error(id: &ContractId) -> Error {
+    Error::InvalidState(format!("Contract {:02x?} is being processed.", id))
+}
+
 /// Used to create and update DLCs.
 pub struct Manager<W: Deref, B: Deref, S: Deref, O: Deref, T: Deref>
 where
@@ -111,6 +304,9 @@ where
     store: S,
     secp: Secp256k1<All>,
     time: T,
+    config: ManagerConfig,
+    subscribers: Mutex<Vec<UnboundedSender<ContractEvent>>>,
+    contract_locks: ContractLocks,
 }
 
 macro_rules! get_object_in_state {
@@ -171,7 +367,9 @@ where
         store: S,
         oracles: Option<HashMap<XOnlyPublicKey, O>>,
         time: T,
+        config: ManagerConfig,
     ) -> Result<Self, Error> {
+        config.validate()?;
         Ok(Manager {
             secp: secp256k1_zkp::Secp256k1::new(),
             wallet,
@@ -179,7 +377,197 @@ where
             store,
             oracles,
             time,
+            config,
+            subscribers: Mutex::new(Vec::new()),
+            contract_locks: ContractLocks::default(),
+        })
+    }
+
+    /// Returns a receiver of the state transitions of the contracts handled
+    /// by the manager, from this call on.
+    pub fn subscribe(&self) -> UnboundedReceiver<ContractEvent> {
+        let (sender, receiver) = unbounded();
+        if let Ok(mut subscribers) = self.subscribers.lock() {
+            subscribers.push(sender);
+        }
+        receiver
+    }
+
+    /// Stores the event in the journal when configured, before the state it
+    /// leads to is stored, so that it is not lost if the process stops before
+    /// the subscribers handle it.
+    async fn journal(&self, event: &ContractEvent) -> Result<(), Error> {
+        if self.config.journal_events {
+            self.store.journal_contract_event(event).await?;
+        }
+        Ok(())
+    }
+
+    fn notify(&self, event: ContractEvent) {
+        if let Ok(mut subscribers) = self.subscribers.lock() {
+            // Subscribers whose receiver was dropped are removed.
+            subscribers.retain(|subscriber| subscriber.unbounded_send(event.clone()).is_ok());
+        }
+    }
+
+    /// Stores the new state of a contract and notifies the subscribers.
+    async fn update_contract_state(
+        &self,
+        from: ContractState,
+        contract: &Contract,
+        offered_contract: &OfferedContract,
+    ) -> Result<(), Error> {
+        let event = ContractEvent::new(Some(from), contract, offered_contract);
+        self.journal(&event).await?;
+        self.store.update_contract(contract).await?;
+        // Offered, failed to accept and rejected contracts are stored under
+        // the temporary id of the offer, the others under their final id.
+        let to = ContractState::from(contract);
+        if is_stored_under_temporary_id(&from) != is_stored_under_temporary_id(&to) {
+            self.index_contract(&contract.get_id(), offered_contract)
+                .await;
+        }
+        self.notify(event);
+        Ok(())
+    }
+
+    /// Indexes the contract with given id under the oracle event ids of its
+    /// legs. Failing to do so is only logged, the contract is indexed again
+    /// by [`Self::index_contract_event_ids`].
+    async fn index_contract(&self, id: &ContractId, offered_contract: &OfferedContract) {
+        for event_id in get_oracle_event_ids(offered_contract) {
+            if let Err(e) = self.store.index_contract_event_id(&event_id, id).await {
+                warn!(
+                    "Failed to index contract {:02x?} under event {}: {}",
+                    id, event_id, e
+                );
+            }
+        }
+    }
+
+    /// Stores a contract that a CET moved to the pre-closed or closed state,
+    /// and notifies the subscribers with the leg of the CET.
+    async fn update_closing_contract_state(
+        &self,
+        from: ContractState,
+        contract: &Contract,
+        signed_contract: &SignedContract,
+    ) -> Result<(), Error> {
+        let event = ContractEvent::new(
+            Some(from),
+            contract,
+            &signed_contract.accepted_contract.offered_contract,
+        )
+        .with_cet_leg(signed_contract);
+        self.journal(&event).await?;
+        self.store.update_contract(contract).await?;
+        if let Contract::Closed(_) = contract {
+            // The summary only serves the details of the contract, failing
+            // to record it does not undo the closing.
+            if let Err(e) = self
+                .record_closed_summary(&contract.get_id(), signed_contract)
+                .await
+            {
+                warn!(
+                    "Failed to record the summary of closed contract {:02x?}: {}",
+                    contract.get_id(),
+                    e
+                );
+            }
+        }
+        self.notify(event);
+        Ok(())
+    }
+
+    async fn record_closed_summary(
+        &self,
+        contract_id: &ContractId,
+        signed_contract: &SignedContract,
+    ) -> Result<(), Error> {
+        let mut metadata = self
+            .store
+            .get_contract_metadata(contract_id)
+            .await?
+            .unwrap_or_default();
+        metadata.closed_summary = Some(ContractSummary::from(&signed_contract.accepted_contract));
+        self.store
+            .upsert_contract_metadata(contract_id, &metadata)
+            .await
+    }
+
+    /// Runs `action` while holding the lock of the contract, and its lease in
+    /// the storage when configured. Returns `None` without running `action`
+    /// if another task or replica is working on the contract.
+    async fn with_contract_lock<R>(
+        &self,
+        id: &ContractId,
+        action: impl Future<Output = Result<R, Error>>,
+    ) -> Result<Option<R>, Error> {
+        let _lock = match self.contract_locks.try_lock(id) {
+            Some(lock) => lock,
+            None => {
+                debug!("Contract {:02x?} is locked by another task", id);
+                return Ok(None);
+            }
+        };
+
+        let lease = self.config.contract_lease.as_ref();
+        if let Some(lease) = lease {
+            let now = self.time.unix_time_now();
+            if !self
+                .store
+                .acquire_contract_lease(id, &lease.owner, now, now + lease.duration)
+                .await?
+            {
+                debug!("Contract {:02x?} is leased by another replica", id);
+                return Ok(None);
+            }
+        }
+
+        let result = action.await;
+
+        if let Some(lease) = lease {
+            // The lease expires anyway if it cannot be released.
+            if let Err(e) = self.store.release_contract_lease(id, &lease.owner).await {
+                warn!("Failed to release lease of contract {:02x?}: {}", id, e);
+            }
+        }
+
+        result.map(Some)
+    }
+
+    /// Runs the periodic check of a contract under its lock, giving up once
+    /// the configured check timeout elapses. Only the check is cut off, so
+    /// that the lock and the lease are released as after any other check.
+    ///
+    /// A check cut off after broadcasting a transaction but before storing
+    /// the new state of the contract is retried by the next periodic check,
+    /// which finds the transaction on chain and stores the state then.
+    async fn check_contract<R>(
+        &self,
+        id: &ContractId,
+        check: impl Future<Output = Result<R, Error>>,
+    ) -> Result<Option<R>, Error> {
+        let timeout = match self.config.contract_check_timeout {
+            Some(timeout) => timeout,
+            None => return self.with_contract_lock(id, check).await,
+        };
+        self.with_contract_lock(id, async {
+            futures::pin_mut!(check);
+            match future::select(check, Delay::new(timeout)).await {
+                Either::Left((result, _)) => result,
+                Either::Right(_) => Err(Error::InvalidState(format!(
+                    "Check of contract {:02x?} timed out after {:?}.",
+                    id, timeout
+                ))),
+            }
         })
+        .await
+    }
+
+    fn confirmation_depths(&self, contract: &SignedContract) -> ConfirmationDepths {
+        self.config
+            .confirmations_for(contract.accepted_contract.offered_contract.total_collateral)
     }
 
     /// Get the store from the Manager to access contracts.
@@ -198,18 +586,39 @@ where
         msg: &DlcMessage,
         counter_party: PublicKey,
     ) -> Result<Option<DlcMessage>, Error> {
-        match msg {
-            DlcMessage::Offer(o) => {
-                self.on_offer_message(o, counter_party).await?;
-                Ok(None)
-            }
-            DlcMessage::Accept(a) => Ok(Some(self.on_accept_message(a, &counter_party).await?)),
-            DlcMessage::Sign(s) => {
-                self.on_sign_message(s, &counter_party).await?;
-                Ok(None)
+        let contract_id = match msg {
+            DlcMessage::Offer(o) => o.temporary_contract_id,
+            DlcMessage::Accept(a) => a.temporary_contract_id,
+            DlcMessage::Sign(s) => s.contract_id,
+            _ => return Err(Error::InvalidState("Invalid message type.".to_string())),
+        };
+        self.with_contract_lock(&contract_id, async {
+            match msg {
+                DlcMessage::Offer(o) => {
+                    self.on_offer_message(o, counter_party).await?;
+                    Ok(None)
+                }
+                DlcMessage::Accept(a) => Ok(Some(self.on_accept_message(a, &counter_party).await?)),
+                DlcMessage::Sign(s) => {
+                    self.on_sign_message(s, &counter_party).await?;
+                    Ok(None)
+                }
+                _ => Err(Error::InvalidState("Invalid message type.".to_string())),
             }
-            _ => Err(Error::InvalidState("Invalid message type.".to_string())),
-        }
+        })
+        .await?
+        .ok_or_else(|| contract_locked_error(&contract_id))
+    }
+
+    /// Handles a message received in a signed envelope, its sender being the
+    /// counterparty. Contracts only move forward with messages from the
+    /// counterparty they were offered to or received from.
+    pub async fn on_signed_dlc_message(
+        &self,
+        envelope: &SignedEnvelope,
+    ) -> Result<Option<DlcMessage>, Error> {
+        let (msg, sender) = envelope.open(&self.secp)?;
+        self.on_dlc_message(&msg, sender).await
     }
 
     /// Function called to create a new DLC. The offered contract will be stored
@@ -234,16 +643,11 @@ where
         };
         contract_input.validate()?;
 
-        // let contract_infos = &contract_input.contract_infos;
-        // let mut oracle_announcements = Vec::new();
-        let event_id = &contract_input
-            .contract_infos
-            .first()
-            .ok_or(Error::InvalidParameters(
+        if contract_input.contract_infos.is_empty() {
+            return Err(Error::InvalidParameters(
                 "Contract Input Info missing".to_string(),
-            ))?
-            .oracles
-            .event_id;
+            ));
+        }
         debug!(
             "manager_oracles keys {:?}",
             manager_oracles
@@ -252,7 +656,8 @@ where
                 .collect::<Vec<String>>()
                 .join(", ")
         );
-        let oracle_set: Vec<Vec<&O>> = contract_input
+        // Each contract info is a leg with its own oracle event.
+        let oracle_set: Vec<(&str, Vec<&O>)> = contract_input
             .contract_infos
             .iter()
             .map(|x| {
@@ -265,7 +670,8 @@ where
                         .collect::<Vec<String>>()
                         .join(", ")
                 );
-                x.oracles
+                let oracles = x
+                    .oracles
                     .public_keys
                     .iter()
                     .map(|pubkey| match manager_oracles.get(pubkey) {
@@ -274,13 +680,14 @@ where
                             "Unknown oracle public key".to_string(),
                         )),
                     })
-                    .collect::<Result<Vec<&O>, Error>>()
+                    .collect::<Result<Vec<&O>, Error>>()?;
+                Ok((x.oracles.event_id.as_str(), oracles))
             })
-            .collect::<Result<Vec<Vec<&O>>, Error>>()?;
+            .collect::<Result<Vec<(&str, Vec<&O>)>, Error>>()?;
 
         let mut oracle_announcements = Vec::new();
 
-        for oracles in oracle_set {
+        for (event_id, oracles) in oracle_set {
             let mut announcements = Vec::new();
             for oracle in oracles {
                 announcements.push(oracle.get_announcement(event_id).await?);
@@ -303,7 +710,7 @@ where
 
         offered_contract.validate()?;
 
-        self.store.create_contract(&offered_contract).await?;
+        self.store_new_offer(&offered_contract).await?;
 
         Ok(offer_msg)
     }
@@ -312,6 +719,15 @@ where
     pub async fn accept_contract_offer(
         &self,
         contract_id: &ContractId,
+    ) -> Result<(ContractId, PublicKey, AcceptDlc), Error> {
+        self.with_contract_lock(contract_id, self.accept_locked_contract_offer(contract_id))
+            .await?
+            .ok_or_else(|| contract_locked_error(contract_id))
+    }
+
+    async fn accept_locked_contract_offer(
+        &self,
+        contract_id: &ContractId,
     ) -> Result<(ContractId, PublicKey, AcceptDlc), Error> {
         let offered_contract =
             get_contract_in_state!(self, contract_id, Offered, None as Option<PublicKey>)?;
@@ -324,6 +740,9 @@ where
             &self.wallet,
             &self.blockchain,
         )?;
+        self.config
+            .offer_policy
+            .check_protocol_fee(&accepted_contract)?;
 
         self.wallet.import_address(&Address::p2wsh(
             &accepted_contract.dlc_transactions.funding_script_pubkey,
@@ -332,20 +751,54 @@ where
 
         let contract_id = accepted_contract.get_contract_id();
 
-        self.store
-            .update_contract(&Contract::Accepted(accepted_contract))
+        self.carry_offered_at(&offered_contract.id, &contract_id)
             .await?;
+        self.update_contract_state(
+            ContractState::Offered,
+            &Contract::Accepted(accepted_contract),
+            &offered_contract,
+        )
+        .await?;
 
         Ok((contract_id, counter_party, accept_msg))
     }
 
+    /// Copies the time of the offer to the metadata of the id the accepted
+    /// contract is stored under, as its expiry counts from the offer.
+    async fn carry_offered_at(
+        &self,
+        temporary_id: &ContractId,
+        contract_id: &ContractId,
+    ) -> Result<(), Error> {
+        let offered_at = match self
+            .store
+            .get_contract_metadata(temporary_id)
+            .await?
+            .and_then(|metadata| metadata.offered_at)
+        {
+            Some(offered_at) => offered_at,
+            None => return Ok(()),
+        };
+        let mut metadata = self
+            .store
+            .get_contract_metadata(contract_id)
+            .await?
+            .unwrap_or_default();
+        metadata.offered_at = Some(offered_at);
+        self.store
+            .upsert_contract_metadata(contract_id, &metadata)
+            .await
+    }
+
     /// Function to call to check the state of the currently executing DLCs and
-    /// update them if possible.
+    /// update them if possible. Updated contracts are returned once per oracle
+    /// event id of the legs concerned.
     pub async fn periodic_check(&self) -> Result<Vec<(ContractId, String)>, Error> {
         let mut affected_contracts = Vec::<(ContractId, String)>::new();
         affected_contracts.extend_from_slice(&self.check_signed_contracts().await?);
         affected_contracts.extend_from_slice(&self.check_confirmed_contracts().await?);
         affected_contracts.extend_from_slice(&self.check_preclosed_contracts().await?);
+        affected_contracts.extend_from_slice(&self.check_expired_offers().await?);
 
         Ok(affected_contracts)
     }
@@ -359,6 +812,10 @@ where
         let contract: OfferedContract =
             OfferedContract::try_from_offer_dlc(offered_message, counter_party)?;
         contract.validate()?;
+        self.config.offer_policy.check_offer(
+            &contract,
+            self.oracles.iter().flat_map(|oracles| oracles.keys()),
+        )?;
 
         if self.store.get_contract(&contract.id).await?.is_some() {
             return Err(Error::InvalidParameters(
@@ -366,8 +823,30 @@ where
             ));
         }
 
-        self.store.create_contract(&contract).await?;
+        self.store_new_offer(&contract).await?;
+
+        Ok(())
+    }
 
+    /// Stores a new offered contract along with the time of the offer.
+    async fn store_new_offer(&self, offered_contract: &OfferedContract) -> Result<(), Error> {
+        let event = ContractEvent::new(
+            None,
+            &Contract::Offered(offered_contract.clone()),
+            offered_contract,
+        );
+        self.journal(&event).await?;
+        self.store.create_contract(offered_contract).await?;
+        let metadata = ContractMetadata {
+            offered_at: Some(self.time.unix_time_now()),
+            ..Default::default()
+        };
+        self.store
+            .upsert_contract_metadata(&offered_contract.id, &metadata)
+            .await?;
+        self.index_contract(&offered_contract.id, offered_contract)
+            .await;
+        self.notify(event);
         Ok(())
     }
 
@@ -405,9 +884,12 @@ where
             self.blockchain.get_network_async().await?,
         ))?;
 
-        self.store
-            .update_contract(&Contract::Signed(signed_contract))
-            .await?;
+        self.update_contract_state(
+            ContractState::Offered,
+            &Contract::Signed(signed_contract),
+            &offered_contract,
+        )
+        .await?;
 
         Ok(DlcMessage::Sign(signed_msg))
     }
@@ -435,9 +917,12 @@ where
                 }
             };
 
-        self.store
-            .update_contract(&Contract::Signed(signed_contract))
-            .await?;
+        self.update_contract_state(
+            ContractState::Accepted,
+            &Contract::Signed(signed_contract),
+            &accepted_contract.offered_contract,
+        )
+        .await?;
 
         self.blockchain.send_transaction_async(&fund_tx).await?;
 
@@ -451,16 +936,320 @@ where
         e: Error,
     ) -> Result<R, Error> {
         error!("Error in on_sign {}", e);
-        self.store
-            .update_contract(&Contract::FailedSign(FailedSignContract {
+        let offered_contract = accepted_contract.offered_contract.clone();
+        self.update_contract_state(
+            ContractState::Accepted,
+            &Contract::FailedSign(FailedSignContract {
                 accepted_contract,
                 sign_message,
                 error_message: e.to_string(),
-            }))
-            .await?;
+            }),
+            &offered_contract,
+        )
+        .await?;
         Err(e)
     }
 
+    /// Retries a contract that failed verifying the accept or sign message of
+    /// the counterparty, from the offer and messages saved with it. Returns
+    /// the message to send to the counterparty, if any. Every retry is
+    /// recorded in the contract metadata, and retries beyond the configured
+    /// maximum are rejected.
+    pub async fn retry_failed_contract(
+        &self,
+        contract_id: &ContractId,
+    ) -> Result<Option<DlcMessage>, Error> {
+        self.with_contract_lock(contract_id, self.retry_locked_failed_contract(contract_id))
+            .await?
+            .ok_or_else(|| contract_locked_error(contract_id))
+    }
+
+    async fn retry_locked_failed_contract(
+        &self,
+        contract_id: &ContractId,
+    ) -> Result<Option<DlcMessage>, Error> {
+        let mut metadata = self
+            .store
+            .get_contract_metadata(contract_id)
+            .await?
+            .unwrap_or_default();
+        if metadata.retry_count() >= self.config.max_retries {
+            return Err(Error::InvalidState(format!(
+                "Contract {:02x?} was already retried {} times, the maximum.",
+                contract_id,
+                metadata.retry_count()
+            )));
+        }
+
+        let result = match self.store.get_contract(contract_id).await? {
+            Some(Contract::FailedAccept(c)) => self.retry_failed_accept(c).await.map(Some),
+            Some(Contract::FailedSign(c)) => self.retry_failed_sign(c).await.map(|_| None),
+            Some(c) => {
+                return Err(Error::InvalidState(format!(
+                    "Invalid state {:?} expected FailedAccept or FailedSign.",
+                    ContractState::from(&c)
+                )))
+            }
+            None => return Err(Error::InvalidParameters("Unknown Contract id.".to_string())),
+        };
+
+        metadata.push_retry(RetryAttempt {
+            attempted_at: self.time.unix_time_now(),
+            error: result.as_ref().err().map(|e| e.to_string()),
+        });
+        self.store
+            .upsert_contract_metadata(contract_id, &metadata)
+            .await?;
+
+        result
+    }
+
+    async fn retry_failed_accept(
+        &self,
+        failed_contract: FailedAcceptContract,
+    ) -> Result<DlcMessage, Error> {
+        let (signed_contract, signed_msg) = verify_accepted_and_sign_contract(
+            &self.secp,
+            &failed_contract.offered_contract,
+            &failed_contract.accept_message,
+            &self.wallet,
+        )?;
+
+        self.wallet.import_address(&Address::p2wsh(
+            &signed_contract
+                .accepted_contract
+                .dlc_transactions
+                .funding_script_pubkey,
+            self.blockchain.get_network_async().await?,
+        ))?;
+
+        self.update_contract_state(
+            ContractState::FailedAccept,
+            &Contract::Signed(signed_contract),
+            &failed_contract.offered_contract,
+        )
+        .await?;
+
+        Ok(DlcMessage::Sign(signed_msg))
+    }
+
+    async fn retry_failed_sign(&self, failed_contract: FailedSignContract) -> Result<(), Error> {
+        let (signed_contract, fund_tx) =
+            crate::dlc_manager::contract_updater::verify_signed_contract(
+                &self.secp,
+                &failed_contract.accepted_contract,
+                &failed_contract.sign_message,
+                &self.wallet,
+            )?;
+
+        self.update_contract_state(
+            ContractState::FailedSign,
+            &Contract::Signed(signed_contract),
+            &failed_contract.accepted_contract.offered_contract,
+        )
+        .await?;
+
+        self.blockchain.send_transaction_async(&fund_tx).await
+    }
+
+    /// Gathers the state, collateral, transactions and oracles of a contract.
+    /// The confirmations of the transactions are looked up on the blockchain,
+    /// and the oracles are asked whether they attested until the contract
+    /// holds their attestations.
+    pub async fn get_contract_details(
+        &self,
+        contract: &Contract,
+    ) -> Result<ContractDetails, Error> {
+        let contract_id = contract.get_id();
+        let metadata = self
+            .store
+            .get_contract_metadata(&contract_id)
+            .await?
+            .unwrap_or_default();
+        let summary = get_accepted_contract(contract)
+            .map(ContractSummary::from)
+            .or_else(|| metadata.closed_summary.clone());
+        let offered_contract = get_offered_contract(contract);
+
+        let (oracle_event_ids, public_keys) = match (&summary, offered_contract) {
+            (Some(summary), _) => (
+                summary.oracle_event_ids.clone(),
+                summary.oracle_public_keys.clone(),
+            ),
+            (None, Some(offered_contract)) => (
+                get_oracle_event_ids(offered_contract),
+                oracle_public_keys(offered_contract),
+            ),
+            (None, None) => (Vec::new(), Vec::new()),
+        };
+        let (offer_collateral, total_collateral) = match (&summary, offered_contract) {
+            (Some(summary), _) => (
+                Some(summary.offer_collateral),
+                Some(summary.total_collateral),
+            ),
+            (None, Some(offered_contract)) => (
+                Some(offered_contract.offer_params.collateral),
+                Some(offered_contract.total_collateral),
+            ),
+            (None, None) => (None, None),
+        };
+
+        let (cet_txid, attestations, pnl) = match contract {
+            Contract::PreClosed(c) => (Some(c.signed_cet.txid()), c.attestations.clone(), None),
+            Contract::Closed(c) => (
+                c.signed_cet.as_ref().map(|cet| cet.txid()),
+                c.attestations.clone(),
+                Some(c.pnl),
+            ),
+            _ => (None, None, None),
+        };
+
+        let mut oracles = Vec::new();
+        for public_key in public_keys {
+            let attested = match &attestations {
+                Some(attestations) => attestations
+                    .iter()
+                    .any(|attestation| attestation.oracle_public_key == public_key),
+                None => self.has_attested(&public_key, &oracle_event_ids).await,
+            };
+            oracles.push(OracleStatus {
+                public_key,
+                attested,
+            });
+        }
+
+        let mut funding = None;
+        let mut refund = None;
+        if let Some(summary) = &summary {
+            funding = Some(self.get_transaction_status(summary.fund_txid).await);
+            refund = Some(self.get_transaction_status(summary.refund_txid).await);
+        }
+        let cet = match cet_txid {
+            Some(txid) => Some(self.get_transaction_status(txid).await),
+            None => None,
+        };
+
+        Ok(ContractDetails {
+            contract_id: contract_id.iter().map(|b| format!("{:02x}", b)).collect(),
+            state: ContractState::from(contract),
+            oracle_event_ids,
+            offer_collateral,
+            accept_collateral: offer_collateral
+                .zip(total_collateral)
+                .map(|(offer, total)| total - offer),
+            funding,
+            cet,
+            refund,
+            refund_locktime: summary
+                .as_ref()
+                .map(|summary| summary.refund_locktime)
+                .or_else(|| offered_contract.map(|c| c.refund_locktime)),
+            oracles,
+            pnl,
+            metadata,
+        })
+    }
+
+    /// Returns the contract with a leg on the given oracle event. When
+    /// several contracts had one, e.g. after a failed attempt, the contract
+    /// last indexed under the event is returned.
+    pub async fn find_contract_by_event_id(
+        &self,
+        event_id: &str,
+    ) -> Result<Option<Contract>, Error> {
+        match self.store.get_contract_id_by_event_id(event_id).await? {
+            Some(id) => self.store.get_contract(&id).await,
+            None => Ok(None),
+        }
+    }
+
+    /// Indexes the stored contracts under the oracle event ids of their legs,
+    /// for those events that have no contract indexed yet. Contracts are
+    /// indexed as they are offered and signed, this covers the contracts
+    /// stored before the index existed and the index writes that failed.
+    /// Closed contracts are only found through the summary recorded when
+    /// closing them, those closed without one cannot be indexed. When several
+    /// contracts have a leg on an event, a contract that is neither failed nor
+    /// rejected is preferred.
+    pub async fn index_contract_event_ids(&self) -> Result<usize, Error> {
+        let mut indexed = HashMap::<String, (ContractId, bool)>::new();
+        let mut without_summary = 0;
+        for contract in self.store.get_contracts().await? {
+            let event_ids = match get_offered_contract(&contract) {
+                Some(offered_contract) => get_oracle_event_ids(offered_contract),
+                None => match self
+                    .store
+                    .get_contract_metadata(&contract.get_id())
+                    .await?
+                    .and_then(|metadata| metadata.closed_summary)
+                {
+                    Some(summary) => summary.oracle_event_ids,
+                    None => {
+                        without_summary += 1;
+                        continue;
+                    }
+                },
+            };
+            let preferred = !matches!(
+                contract,
+                Contract::FailedAccept(_) | Contract::FailedSign(_) | Contract::Rejected(_)
+            );
+            for event_id in event_ids {
+                match indexed.get(&event_id) {
+                    Some((_, true)) => {}
+                    Some((_, false)) if !preferred => {}
+                    _ => {
+                        indexed.insert(event_id, (contract.get_id(), preferred));
+                    }
+                }
+            }
+        }
+        if without_summary > 0 {
+            warn!(
+                "{} closed contracts have no summary and cannot be found by event id",
+                without_summary
+            );
+        }
+
+        let mut count = 0;
+        for (event_id, (id, _)) in indexed {
+            if self
+                .store
+                .get_contract_id_by_event_id(&event_id)
+                .await?
+                .is_none()
+            {
+                self.store.index_contract_event_id(&event_id, &id).await?;
+                count += 1;
+            }
+        }
+        Ok(count)
+    }
+
+    async fn has_attested(&self, public_key: &XOnlyPublicKey, event_ids: &[String]) -> bool {
+        let oracle = match self.oracles.as_ref().and_then(|o| o.get(public_key)) {
+            Some(oracle) => oracle,
+            None => return false,
+        };
+        for event_id in event_ids {
+            if oracle.get_attestation(event_id).await.is_ok() {
+                return true;
+            }
+        }
+        false
+    }
+
+    async fn get_transaction_status(&self, txid: Txid) -> TransactionStatus {
+        TransactionStatus {
+            txid,
+            confirmations: self
+                .blockchain
+                .get_transaction_confirmations_async(&txid)
+                .await
+                .ok(),
+        }
+    }
+
     async fn accept_fail_on_error<R>(
         &self,
         offered_contract: OfferedContract,
@@ -468,16 +1257,141 @@ where
         e: Error,
     ) -> Result<R, Error> {
         error!("Error in on_accept {}", e);
-        self.store
-            .update_contract(&Contract::FailedAccept(FailedAcceptContract {
-                offered_contract,
+        self.update_contract_state(
+            ContractState::Offered,
+            &Contract::FailedAccept(FailedAcceptContract {
+                offered_contract: offered_contract.clone(),
                 accept_message,
                 error_message: e.to_string(),
-            }))
-            .await?;
+            }),
+            &offered_contract,
+        )
+        .await?;
         Err(e)
     }
 
+    async fn check_expired_offers(&self) -> Result<Vec<(ContractId, String)>, Error> {
+        let offer_ttl = match self.config.offer_ttl {
+            Some(offer_ttl) => offer_ttl,
+            None => return Ok(Vec::new()),
+        };
+
+        let mut pending_offers: Vec<(ContractId, OfferedContract)> = self
+            .store
+            .get_contract_offers()
+            .await?
+            .into_iter()
+            .map(|c| (c.id, c))
+            .collect();
+        pending_offers.extend(
+            self.store
+                .get_accepted_contracts()
+                .await?
+                .into_iter()
+                .map(|c| (c.get_contract_id(), c.offered_contract)),
+        );
+
+        let results = stream::iter(&pending_offers)
+            .map(|(id, offered_contract)| async move {
+                let checked = self
+                    .check_contract(id, async {
+                        // Another task may have moved the contract on meanwhile.
+                        match self.store.get_contract(id).await? {
+                            Some(Contract::Offered(c)) => {
+                                self.check_offer_expiry(id, ContractState::Offered, c, offer_ttl)
+                                    .await
+                            }
+                            Some(Contract::Accepted(c)) => {
+                                self.check_offer_expiry(
+                                    id,
+                                    ContractState::Accepted,
+                                    c.offered_contract,
+                                    offer_ttl,
+                                )
+                                .await
+                            }
+                            _ => Ok(false),
+                        }
+                    })
+                    .await;
+                (offered_contract, checked)
+            })
+            .buffer_unordered(self.config.check_concurrency)
+            .collect::<Vec<_>>()
+            .await;
+
+        let mut expired_offers = Vec::new();
+        for (offered_contract, checked) in results {
+            match checked {
+                Ok(Some(true)) => {
+                    expired_offers.extend(report_legs(
+                        offered_contract.id,
+                        get_oracle_event_ids(offered_contract),
+                    ));
+                }
+                Ok(_) => (),
+                Err(e) => error!(
+                    "Error checking expiry of offer {:02x?}: {}",
+                    offered_contract.id, e
+                ),
+            }
+        }
+
+        Ok(expired_offers)
+    }
+
+    /// Rejects an offered or accepted contract that was not signed within
+    /// `offer_ttl` seconds of the offer. `id` is the id the contract is
+    /// currently stored under.
+    async fn check_offer_expiry(
+        &self,
+        id: &ContractId,
+        from: ContractState,
+        offered_contract: OfferedContract,
+        offer_ttl: u64,
+    ) -> Result<bool, Error> {
+        let now = self.time.unix_time_now();
+        let mut metadata = self
+            .store
+            .get_contract_metadata(id)
+            .await?
+            .unwrap_or_default();
+        let offered_at = match metadata.offered_at {
+            Some(offered_at) => offered_at,
+            None => {
+                // Contracts stored without an offer time, such as accepted
+                // ones stored before it was carried to their final id,
+                // expire one TTL after being first seen.
+                metadata.offered_at = Some(now);
+                self.store.upsert_contract_metadata(id, &metadata).await?;
+                return Ok(false);
+            }
+        };
+        if now < offered_at.saturating_add(offer_ttl) {
+            return Ok(false);
+        }
+
+        info!("Offer {:02x?} expired, rejecting it", offered_contract.id);
+        if from == ContractState::Accepted {
+            // Rejected contracts are stored under the temporary id of the
+            // offer, not the id of the accepted contract.
+            self.store.delete_contract(id).await?;
+            self.store.create_contract(&offered_contract).await?;
+        }
+        metadata.expired_at = Some(now);
+        self.store
+            .upsert_contract_metadata(&offered_contract.id, &metadata)
+            .await?;
+        self.update_contract_state(
+            from,
+            &Contract::Rejected(offered_contract.clone()),
+            &offered_contract,
+        )
+        .await?;
+
+        Ok(true)
+    }
+
     async fn check_signed_contract(&self, contract: &SignedContract) -> Result<bool, Error> {
         let confirmations = self
             .blockchain
@@ -485,33 +1399,79 @@ where
                 &contract.accepted_contract.dlc_transactions.fund.txid(),
             )
             .await?;
-        if confirmations >= NB_CONFIRMATIONS {
+        if confirmations >= self.confirmation_depths(contract).funding {
+            let contract_id = contract.accepted_contract.get_contract_id();
+            let mut metadata = self
+                .store
+                .get_contract_metadata(&contract_id)
+                .await?
+                .unwrap_or_default();
+            metadata.funding_anchor = self
+                .recheck_anchor(
+                    contract.accepted_contract.dlc_transactions.fund.txid(),
+                    None,
+                )
+                .await?;
             self.store
-                .update_contract(&Contract::Confirmed(contract.clone()))
+                .upsert_contract_metadata(&contract_id, &metadata)
                 .await?;
+            let confirmed_contract = Contract::Confirmed(contract.clone());
+            let event = ContractEvent::new(
+                Some(ContractState::Signed),
+                &confirmed_contract,
+                &contract.accepted_contract.offered_contract,
+            );
+            // A contract confirmed again after a reorganisation was already
+            // reported funded.
+            let event = match metadata.funding_reorged_at {
+                Some(_) => event.after_reorg(),
+                None => event,
+            };
+            self.journal(&event).await?;
+            self.store.update_contract(&confirmed_contract).await?;
+            self.notify(event);
             return Ok(true);
         }
+        if confirmations == 0 {
+            self.bump_fee_if_stuck(
+                &contract.accepted_contract,
+                contract.accepted_contract.dlc_transactions.fund.txid(),
+            )
+            .await?;
+        }
         Ok(false)
     }
 
     async fn check_signed_contracts(&self) -> Result<Vec<(ContractId, String)>, Error> {
+        let contracts = self.store.get_signed_contracts().await?;
+        let results = stream::iter(&contracts)
+            .map(|c| async move {
+                let contract_id = c.accepted_contract.get_contract_id();
+                let checked = self
+                    .check_contract(&contract_id, async {
+                        // Another task may have moved the contract on meanwhile.
+                        match self.store.get_contract(&contract_id).await? {
+                            Some(Contract::Signed(c)) => self.check_signed_contract(&c).await,
+                            _ => Ok(false),
+                        }
+                    })
+                    .await;
+                (c, checked)
+            })
+            .buffer_unordered(self.config.check_concurrency)
+            .collect::<Vec<_>>()
+            .await;
+
         let mut contracts_to_confirm = Vec::new();
-        for c in self.store.get_signed_contracts().await? {
-            match self.check_signed_contract(&c).await {
-                Ok(true) => {
-                    let contract_id = c.accepted_contract.get_contract_id();
-                    let oracle_event_id = c
-                        .accepted_contract
-                        .offered_contract
-                        .contract_info
-                        .first()
-                        .and_then(|info| info.oracle_announcements.first())
-                        .map(|announcement| announcement.oracle_event.event_id.clone())
-                        .ok_or(Error::InvalidState("Missing oracle event ID".to_string()))?;
-
-                    contracts_to_confirm.push((contract_id, oracle_event_id));
+        for (c, checked) in results {
+            match checked {
+                Ok(Some(true)) => {
+                    contracts_to_confirm.extend(report_legs(
+                        c.accepted_contract.get_contract_id(),
+                        get_oracle_event_ids(&c.accepted_contract.offered_contract),
+                    ));
                 }
-                Ok(false) => (),
+                Ok(_) => (),
                 Err(e) => error!(
                     "Error checking signed contract {}: {}",
                     c.accepted_contract.get_contract_id_string(),
@@ -524,13 +1484,30 @@ where
     }
 
     async fn check_confirmed_contracts(&self) -> Result<Vec<(ContractId, String)>, Error> {
-        let mut contracts_to_close = Vec::new();
-        for c in self.store.get_confirmed_contracts().await? {
+        let contracts = self.store.get_confirmed_contracts().await?;
+        let results = stream::iter(&contracts)
             // Confirmed contracts from channel are processed in channel specific methods.
-            if c.channel_id.is_some() {
-                continue;
-            }
-            match self.check_confirmed_contract(&c).await {
+            .filter(|c| future::ready(c.channel_id.is_none()))
+            .map(|c| async move {
+                let contract_id = c.accepted_contract.get_contract_id();
+                let checked = self
+                    .check_contract(&contract_id, async {
+                        // Another task may have moved the contract on meanwhile.
+                        match self.store.get_contract(&contract_id).await? {
+                            Some(Contract::Confirmed(c)) => self.check_confirmed_contract(&c).await,
+                            _ => Ok(false),
+                        }
+                    })
+                    .await;
+                (c, checked)
+            })
+            .buffer_unordered(self.config.check_concurrency)
+            .collect::<Vec<_>>()
+            .await;
+
+        let mut contracts_to_close = Vec::new();
+        for (c, checked) in results {
+            match checked {
                 Err(e) => {
                     error!(
                         "Error checking confirmed contract {}: {}",
@@ -538,20 +1515,33 @@ where
                         e
                     )
                 }
-                Ok(true) => {
+                Ok(Some(true)) => {
                     let contract_id = c.accepted_contract.get_contract_id();
-                    let oracle_event_id = c
-                        .accepted_contract
-                        .offered_contract
-                        .contract_info
-                        .first()
-                        .and_then(|info| info.oracle_announcements.first())
-                        .map(|announcement| announcement.oracle_event.event_id.clone())
-                        .ok_or(Error::InvalidState("Missing oracle event ID".to_string()))?;
-
-                    contracts_to_close.push((contract_id, oracle_event_id));
+                    // A contract closed by a CET reports the leg of the CET,
+                    // a refunded one all of its legs.
+                    let cet_txid = match self.store.get_contract(&contract_id).await {
+                        Ok(Some(Contract::PreClosed(p))) => Some(p.signed_cet.txid()),
+                        Ok(Some(Contract::Closed(closed))) => {
+                            closed.signed_cet.map(|cet| cet.txid())
+                        }
+                        Ok(_) => None,
+                        Err(e) => {
+                            error!(
+                                "Error reading checked contract {}: {}",
+                                c.accepted_contract.get_contract_id_string(),
+                                e
+                            );
+                            continue;
+                        }
+                    };
+                    let event_ids =
+                        match cet_txid.and_then(|txid| get_cet_oracle_event_id(c, &txid)) {
+                            Some(event_id) => vec![event_id],
+                            None => get_oracle_event_ids(&c.accepted_contract.offered_contract),
+                        };
+                    contracts_to_close.extend(report_legs(contract_id, event_ids));
                 }
-                Ok(false) => (),
+                Ok(_) => (),
             }
         }
 
@@ -582,8 +1572,13 @@ where
                 .collect();
 
             if announcements.len() >= contract_info.threshold {
-                let attestations: Vec<_> = futures::future::join_all(announcements.iter().map(
-                    |(i, announcement)| async move {
+                // Query every attestor at once, but stop waiting as soon as
+                // a threshold of them agree: a threshold sized subset is all
+                // that is needed to close, so slow or unavailable attestors
+                // must not hold the contract back.
+                let mut pending_attestations: FuturesUnordered<_> = announcements
+                    .iter()
+                    .map(|(i, announcement)| async move {
                         let oracle = match manager_oracles.get(&announcement.oracle_public_key) {
                             Some(x) => x,
                             None => {
@@ -599,20 +1594,37 @@ where
                             .map_err(|err| Error::OracleError(err.to_string()))?;
 
                         Ok((*i, attestation))
-                    },
-                ))
-                .await;
-                let attestations: Vec<_> = attestations
-                    .iter()
-                    .filter(|&pair| pair.is_ok())
-                    .flat_map(|pair| {
-                        pair.as_ref()
-                            .map(|(i, attestation)| (*i, attestation.clone()))
                     })
                     .collect();
 
+                let mut attestations: Vec<(usize, OracleAttestation)> = Vec::new();
+                while let Some(result) = pending_attestations.next().await {
+                    match result {
+                        Ok(attestation) => {
+                            let position =
+                                attestations.partition_point(|(i, _)| *i < attestation.0);
+                            attestations.insert(position, attestation);
+                        }
+                        Err(e) => {
+                            debug!("Attestation not available: {}", e);
+                            continue;
+                        }
+                    }
+                    // Attestors may disagree, in which case the contract waits
+                    // for more of them to find a threshold that agrees.
+                    if let Some(agreeing) =
+                        find_agreeing_attestations(contract_info, adaptor_info, &attestations)
+                    {
+                        return Ok(Some((contract_info, adaptor_info, agreeing)));
+                    }
+                }
                 if attestations.len() >= contract_info.threshold {
-                    return Ok(Some((contract_info, adaptor_info, attestations)));
+                    warn!(
+                        "No {} of the {} attestations of contract {} agree on an outcome",
+                        contract_info.threshold,
+                        attestations.len(),
+                        contract.accepted_contract.get_contract_id_string()
+                    );
                 }
             }
         }
@@ -621,6 +1633,51 @@ where
     }
 
     async fn check_confirmed_contract(&self, contract: &SignedContract) -> Result<bool, Error> {
+        let contract_id = contract.accepted_contract.get_contract_id();
+        let mut metadata = self
+            .store
+            .get_contract_metadata(&contract_id)
+            .await?
+            .unwrap_or_default();
+
+        let funding_anchor = self
+            .recheck_anchor(
+                contract.accepted_contract.dlc_transactions.fund.txid(),
+                metadata.funding_anchor.as_ref(),
+            )
+            .await?;
+        if funding_anchor.is_none() {
+            return self.demote_confirmed_contract(contract, metadata).await;
+        }
+        if funding_anchor != metadata.funding_anchor {
+            metadata.funding_anchor = funding_anchor;
+            self.store
+                .upsert_contract_metadata(&contract_id, &metadata)
+                .await?;
+        }
+
+        let fund_outpoint = contract
+            .accepted_contract
+            .dlc_transactions
+            .get_fund_outpoint();
+        if let Some(spending_txid) = self
+            .blockchain
+            .get_output_spending_txid_async(&fund_outpoint)
+            .await?
+        {
+            return self
+                .check_funding_spend(contract, metadata, spending_txid)
+                .await;
+        }
+
+        if let Some(refund_broadcast) = &metadata.refund_broadcast {
+            // The refund was broadcast but no longer spends the funding
+            // output, so only its rebroadcast and confirmation are left.
+            return self
+                .check_refund_broadcast(contract, &metadata, refund_broadcast)
+                .await;
+        }
+
         let closable_contract_info = self.get_closable_contract_info(contract).await;
         if let Ok(Some((contract_info, adaptor_info, attestations))) = closable_contract_info {
             let cet = crate::dlc_manager::contract_updater::get_signed_cet(
@@ -640,7 +1697,12 @@ where
                 .await
             {
                 Ok(closed_contract) => {
-                    self.store.update_contract(&closed_contract).await?;
+                    self.update_closing_contract_state(
+                        ContractState::Confirmed,
+                        &closed_contract,
+                        contract,
+                    )
+                    .await?;
                     return Ok(true);
                 }
                 Err(e) => {
@@ -653,30 +1715,134 @@ where
                 }
             }
         }
-        self.check_refund(contract).await?;
+        self.check_refund(contract, metadata).await?;
+
+        Ok(false)
+    }
+
+    /// Handles a confirmed contract whose funding output has been spent,
+    /// whoever broadcast the spending transaction. A CET moves the contract to
+    /// the pre-closed state, the refund is followed until it confirms, and any
+    /// other transaction is flagged as possible fraud.
+    async fn check_funding_spend(
+        &self,
+        contract: &SignedContract,
+        mut metadata: ContractMetadata,
+        spending_txid: Txid,
+    ) -> Result<bool, Error> {
+        let contract_id = contract.accepted_contract.get_contract_id();
+        let dlc_transactions = &contract.accepted_contract.dlc_transactions;
+
+        if spending_txid == dlc_transactions.refund.txid() {
+            let refund_broadcast = match &metadata.refund_broadcast {
+                Some(refund_broadcast) => refund_broadcast.clone(),
+                None => {
+                    info!(
+                        "Refund {} of contract {} was broadcast by the counterparty",
+                        spending_txid,
+                        contract.accepted_contract.get_contract_id_string()
+                    );
+                    let now = self.time.unix_time_now();
+                    let refund_broadcast = RefundBroadcast {
+                        txid: spending_txid,
+                        broadcast_at: now,
+                        last_broadcast_at: now,
+                        broadcast_count: 0,
+                    };
+                    metadata.refund_broadcast = Some(refund_broadcast.clone());
+                    self.store
+                        .upsert_contract_metadata(&contract_id, &metadata)
+                        .await?;
+                    refund_broadcast
+                }
+            };
+            return self
+                .check_refund_broadcast(contract, &metadata, &refund_broadcast)
+                .await;
+        }
+
+        if dlc_transactions
+            .cets
+            .iter()
+            .any(|cet| cet.txid() == spending_txid)
+        {
+            info!(
+                "CET {} of contract {} was broadcast by the counterparty",
+                spending_txid,
+                contract.accepted_contract.get_contract_id_string()
+            );
+            let signed_cet = self
+                .blockchain
+                .get_transaction_async(&spending_txid)
+                .await?;
+            self.update_closing_contract_state(
+                ContractState::Confirmed,
+                &Contract::PreClosed(PreClosedContract {
+                    signed_contract: contract.clone(),
+                    attestations: None,
+                    signed_cet,
+                }),
+                contract,
+            )
+            .await?;
+            return Ok(true);
+        }
+
+        if metadata.suspected_fraud.is_none() {
+            error!(
+                "Funding output of contract {} spent by unknown transaction {}, possible fraud",
+                contract.accepted_contract.get_contract_id_string(),
+                spending_txid
+            );
+            metadata.suspected_fraud = Some(SuspectedFraud {
+                spending_txid,
+                detected_at: self.time.unix_time_now(),
+            });
+            self.store
+                .upsert_contract_metadata(&contract_id, &metadata)
+                .await?;
+        }
 
         Ok(false)
     }
 
     async fn check_preclosed_contracts(&self) -> Result<Vec<(ContractId, String)>, Error> {
+        let contracts = self.store.get_preclosed_contracts().await?;
+        let results = stream::iter(&contracts)
+            .map(|c| async move {
+                let contract_id = c.signed_contract.accepted_contract.get_contract_id();
+                let checked = self
+                    .check_contract(&contract_id, async {
+                        // Another task may have moved the contract on meanwhile.
+                        match self.store.get_contract(&contract_id).await? {
+                            Some(Contract::PreClosed(c)) => self.check_preclosed_contract(&c).await,
+                            _ => Ok(false),
+                        }
+                    })
+                    .await;
+                (c, checked)
+            })
+            .buffer_unordered(self.config.check_concurrency)
+            .collect::<Vec<_>>()
+            .await;
+
         let mut contracts_to_close = Vec::new();
-        for c in self.store.get_preclosed_contracts().await? {
-            match self.check_preclosed_contract(&c).await {
-                Ok(true) => {
-                    let contract_id = c.signed_contract.accepted_contract.get_contract_id();
-                    let oracle_event_id = c
-                        .signed_contract
-                        .accepted_contract
-                        .offered_contract
-                        .contract_info
-                        .first()
-                        .and_then(|info| info.oracle_announcements.first())
-                        .map(|announcement| announcement.oracle_event.event_id.clone())
-                        .ok_or(Error::InvalidState("Missing oracle event ID".to_string()))?;
-
-                    contracts_to_close.push((contract_id, oracle_event_id));
+        for (c, checked) in results {
+            match checked {
+                Ok(Some(true)) => {
+                    let event_ids =
+                        match get_cet_oracle_event_id(&c.signed_contract, &c.signed_cet.txid()) {
+                            Some(event_id) => vec![event_id],
+                            None => get_oracle_event_ids(
+                                &c.signed_contract.accepted_contract.offered_contract,
+                            ),
+                        };
+                    contracts_to_close.extend(report_legs(
+                        c.signed_contract.accepted_contract.get_contract_id(),
+                        event_ids,
+                    ));
                 }
-                Ok(false) => (),
+                Ok(_) => (),
                 Err(e) => error!(
                     "Error checking pre-closed contract {}: {}",
                     c.signed_contract.accepted_contract.get_contract_id_string(),
@@ -689,12 +1855,34 @@ where
     }
 
     async fn check_preclosed_contract(&self, contract: &PreClosedContract) -> Result<bool, Error> {
+        let contract_id = contract.signed_contract.accepted_contract.get_contract_id();
+        let mut metadata = self
+            .store
+            .get_contract_metadata(&contract_id)
+            .await?
+            .unwrap_or_default();
+
         let broadcasted_txid = contract.signed_cet.txid();
         let confirmations = self
             .blockchain
             .get_transaction_confirmations_async(&broadcasted_txid)
             .await?;
-        if confirmations >= NB_CONFIRMATIONS {
+
+        if metadata.cet_anchor.is_some() || confirmations > 0 {
+            let cet_anchor = self
+                .recheck_anchor(broadcasted_txid, metadata.cet_anchor.as_ref())
+                .await?;
+            if metadata.cet_anchor.is_some() && cet_anchor.is_none() {
+                return self.demote_preclosed_contract(contract, metadata).await;
+            }
+            if cet_anchor != metadata.cet_anchor {
+                metadata.cet_anchor = cet_anchor;
+                self.store
+                    .upsert_contract_metadata(&contract_id, &metadata)
+                    .await?;
+            }
+        }
+        if confirmations >= self.confirmation_depths(&contract.signed_contract).cet {
             let closed_contract = ClosedContract {
                 attestations: contract.attestations.clone(),
                 signed_cet: Some(contract.signed_cet.clone()),
@@ -714,11 +1902,135 @@ where
                     .accepted_contract
                     .compute_pnl(&contract.signed_cet),
             };
-            self.store
-                .update_contract(&Contract::Closed(closed_contract.clone()))
-                .await?;
+            self.update_closing_contract_state(
+                ContractState::PreClosed,
+                &Contract::Closed(closed_contract),
+                &contract.signed_contract,
+            )
+            .await?;
             return Ok(true);
         }
+        if confirmations == 0 {
+            self.bump_fee_if_stuck(
+                &contract.signed_contract.accepted_contract,
+                broadcasted_txid,
+            )
+            .await?;
+        }
+
+        Ok(false)
+    }
+
+    /// Returns the block a transaction counted as confirmed is confirmed in.
+    /// The recorded anchor is kept while its block is in the best chain,
+    /// otherwise the transaction is looked up again, `None` meaning a chain
+    /// reorganisation removed it.
+    async fn recheck_anchor(
+        &self,
+        txid: Txid,
+        anchor: Option<&ConfirmationAnchor>,
+    ) -> Result<Option<ConfirmationAnchor>, Error> {
+        if let Some(anchor) = anchor.filter(|anchor| anchor.txid == txid) {
+            if self
+                .blockchain
+                .is_block_in_best_chain_async(&anchor.block_hash)
+                .await?
+            {
+                return Ok(Some(anchor.clone()));
+            }
+        }
+        Ok(self
+            .blockchain
+            .get_transaction_block_hash_async(&txid)
+            .await?
+            .map(|block_hash| ConfirmationAnchor { txid, block_hash }))
+    }
+
+    /// Moves a confirmed contract whose funding transaction was reorganised
+    /// out of the chain back to the signed state, and rebroadcasts it.
+    async fn demote_confirmed_contract(
+        &self,
+        contract: &SignedContract,
+        mut metadata: ContractMetadata,
+    ) -> Result<bool, Error> {
+        let contract_id = contract.accepted_contract.get_contract_id();
+        let fund = &contract.accepted_contract.dlc_transactions.fund;
+        warn!(
+            "Funding transaction {} of contract {} is no longer confirmed, moving it back to signed",
+            fund.txid(),
+            contract.accepted_contract.get_contract_id_string()
+        );
+
+        metadata.funding_anchor = None;
+        metadata.funding_reorged_at = Some(self.time.unix_time_now());
+        self.store
+            .upsert_contract_metadata(&contract_id, &metadata)
+            .await?;
+        // Nodes usually return reorganised transactions to their mempool, in
+        // which case the broadcast is rejected.
+        if let Err(e) = self.blockchain.send_transaction_async(fund).await {
+            warn!(
+                "Failed to rebroadcast funding transaction {}: {}",
+                fund.txid(),
+                e
+            );
+        }
+        self.update_contract_state(
+            ContractState::Confirmed,
+            &Contract::Signed(contract.clone()),
+            &contract.accepted_contract.offered_contract,
+        )
+        .await?;
+
+        Ok(false)
+    }
+
+    /// Rebroadcasts the CET of a pre-closed contract that was reorganised out
+    /// of the chain. The contract stays pre-closed with its attestations, it
+    /// was already reported closed, and is closed once the CET confirms
+    /// again. The funding transaction is rebroadcast too if it went with it.
+    async fn demote_preclosed_contract(
+        &self,
+        contract: &PreClosedContract,
+        mut metadata: ContractMetadata,
+    ) -> Result<bool, Error> {
+        let signed_contract = &contract.signed_contract;
+        let contract_id = signed_contract.accepted_contract.get_contract_id();
+        warn!(
+            "CET {} of contract {} is no longer confirmed, waiting for it to confirm again",
+            contract.signed_cet.txid(),
+            signed_contract.accepted_contract.get_contract_id_string()
+        );
+
+        metadata.cet_anchor = None;
+        let fund = &signed_contract.accepted_contract.dlc_transactions.fund;
+        let funding_anchor = self
+            .recheck_anchor(fund.txid(), metadata.funding_anchor.as_ref())
+            .await?;
+        if funding_anchor.is_none() {
+            if let Err(e) = self.blockchain.send_transaction_async(fund).await {
+                warn!(
+                    "Failed to rebroadcast funding transaction {}: {}",
+                    fund.txid(),
+                    e
+                );
+            }
+        }
+        metadata.funding_anchor = funding_anchor;
+        self.store
+            .upsert_contract_metadata(&contract_id, &metadata)
+            .await?;
+        if let Err(e) = self
+            .blockchain
+            .send_transaction_async(&contract.signed_cet)
+            .await
+        {
+            warn!(
+                "Failed to rebroadcast CET {}: {}",
+                contract.signed_cet.txid(),
+                e
+            );
+        }
 
         Ok(false)
     }
@@ -739,11 +2051,27 @@ where
         // only set it after 6 confirmations
 
         if confirmations < 1 {
-            // TODO(tibo): if this fails because another tx is already in
-            // mempool or blockchain, we might have been cheated. There is
-            // not much to be done apart from possibly extracting a fraud
-            // proof but ideally it should be handled.
-            self.blockchain.send_transaction_async(&signed_cet).await?;
+            if let Err(e) = self.blockchain.send_transaction_async(&signed_cet).await {
+                // Another transaction may already spend the funding output,
+                // the next check of the contract identifies it.
+                if let Some(spending_txid) = self
+                    .blockchain
+                    .get_output_spending_txid_async(
+                        &contract
+                            .accepted_contract
+                            .dlc_transactions
+                            .get_fund_outpoint(),
+                    )
+                    .await?
+                {
+                    warn!(
+                        "Funding output of contract {} already spent by {}",
+                        contract.accepted_contract.get_contract_id_string(),
+                        spending_txid
+                    );
+                }
+                return Err(e);
+            }
 
             let preclosed_contract = PreClosedContract {
                 signed_contract: contract.clone(),
@@ -752,7 +2080,7 @@ where
             };
 
             return Ok(Contract::PreClosed(preclosed_contract));
-        } else if confirmations < NB_CONFIRMATIONS {
+        } else if confirmations < self.confirmation_depths(contract).cet {
             let preclosed_contract = PreClosedContract {
                 signed_contract: contract.clone(),
                 attestations: Some(attestations),
@@ -774,69 +2102,294 @@ where
         Ok(Contract::Closed(closed_contract))
     }
 
-    async fn get_json(&self, path: &str) -> Result<serde_json::Value, Error> {
-        reqwest::get(path)
-            .await
-            .map_err(|x| Error::WalletError(Box::new(x)))?
-            .json::<serde_json::Value>()
-            .await
-            .map_err(|x| Error::WalletError(Box::new(x)))
-    }
+    /// Returns whether the refund transaction of the contract can be
+    /// broadcast. The time provider decides when the refund lock time has
+    /// passed, and if the blockchain reports its median time past, the chain
+    /// must agree as nodes reject a time locked transaction until then.
+    async fn is_refund_time_reached(&self, contract: &SignedContract) -> Result<bool, Error> {
+        let refund_locktime = contract
+            .accepted_contract
+            .dlc_transactions
+            .refund
+            .lock_time
+            .0 as u64;
 
-    async fn get_unixtime(&self) -> Result<u64, Error> {
-        let path = "https://worldtimeapi.org/api/timezone/Etc/UTC";
-        let v = match self.get_json(path).await {
-            Ok(v) => v,
-            Err(e) => {
-                return Err(Error::WalletError(
-                    format!("Error getting unixtime: {e}").into(),
-                ))
+        if refund_locktime > self.time.unix_time_now() {
+            return Ok(false);
+        }
+
+        match self.blockchain.get_median_time_past_async().await? {
+            Some(median_time_past) if median_time_past <= refund_locktime => {
+                debug!(
+                    "Refund lock time {} reached but chain median time past is {}, waiting for {}",
+                    refund_locktime,
+                    median_time_past,
+                    contract.accepted_contract.get_contract_id_string()
+                );
+                Ok(false)
             }
-        };
+            _ => Ok(true),
+        }
+    }
+
+    /// Broadcasts the refund transaction once the refund lock time is reached,
+    /// and records it in the contract metadata so that later checks follow
+    /// its confirmation.
+    async fn check_refund(
+        &self,
+        contract: &SignedContract,
+        mut metadata: ContractMetadata,
+    ) -> Result<(), Error> {
+        if !self.is_refund_time_reached(contract).await? {
+            return Ok(());
+        }
 
-        let unixtime = match v["unixtime"].as_u64() {
-            //call to_string instead of as_str and watch your world crumble to pieces
-            None => return Err(Error::WalletError("unable to get unixtime".into())),
-            Some(s) => s,
+        let refund_txid = contract.accepted_contract.dlc_transactions.refund.txid();
+        let confirmations = self
+            .blockchain
+            .get_transaction_confirmations_async(&refund_txid)
+            .await?;
+        // Already confirmed, e.g. broadcast by the counterparty
+        let broadcast_count = if confirmations == 0 {
+            self.broadcast_refund(contract).await?;
+            1
+        } else {
+            0
         };
 
-        Ok(unixtime)
+        let now = self.time.unix_time_now();
+        metadata.refund_broadcast = Some(RefundBroadcast {
+            txid: refund_txid,
+            broadcast_at: now,
+            last_broadcast_at: now,
+            broadcast_count,
+        });
+        self.store
+            .upsert_contract_metadata(&contract.accepted_contract.get_contract_id(), &metadata)
+            .await?;
+
+        Ok(())
     }
 
-    async fn check_refund(&self, contract: &SignedContract) -> Result<(), Error> {
-        // TODO(tibo): should check for confirmation of refund before updating state
-        // use reqwest to fetch the current time
+    /// Follows a broadcast refund transaction, moving the contract to the
+    /// refunded state once the refund has enough confirmations, and
+    /// broadcasting it again if it dropped out of the mempool.
+    async fn check_refund_broadcast(
+        &self,
+        contract: &SignedContract,
+        metadata: &ContractMetadata,
+        refund_broadcast: &RefundBroadcast,
+    ) -> Result<bool, Error> {
+        let confirmations = self
+            .blockchain
+            .get_transaction_confirmations_async(&refund_broadcast.txid)
+            .await?;
+        if confirmations >= self.confirmation_depths(contract).refund {
+            self.update_contract_state(
+                ContractState::Confirmed,
+                &Contract::Refunded(contract.clone()),
+                &contract.accepted_contract.offered_contract,
+            )
+            .await?;
+            return Ok(true);
+        }
+        if confirmations > 0 {
+            return Ok(false);
+        }
 
-        let unixtime = self.get_unixtime().await?;
-        if contract
-            .accepted_contract
-            .dlc_transactions
-            .refund
-            .lock_time
-            .0 as u64
-            <= unixtime
+        // Neither mined nor known to the mempool anymore.
+        if self
+            .blockchain
+            .find_transaction_async(&refund_broadcast.txid)
+            .await?
+            .is_none()
         {
-            let accepted_contract = &contract.accepted_contract;
-            let refund = accepted_contract.dlc_transactions.refund.clone();
-            let confirmations = self
-                .blockchain
-                .get_transaction_confirmations_async(&refund.txid())
+            warn!(
+                "Refund {} of contract {} dropped out of the mempool, broadcasting again",
+                refund_broadcast.txid,
+                contract.accepted_contract.get_contract_id_string()
+            );
+            self.broadcast_refund(contract).await?;
+
+            let mut metadata = metadata.clone();
+            metadata.refund_broadcast = Some(RefundBroadcast {
+                last_broadcast_at: self.time.unix_time_now(),
+                broadcast_count: refund_broadcast.broadcast_count + 1,
+                ..refund_broadcast.clone()
+            });
+            self.store
+                .upsert_contract_metadata(&contract.accepted_contract.get_contract_id(), &metadata)
                 .await?;
-            if confirmations == 0 {
-                let refund = crate::dlc_manager::contract_updater::get_signed_refund(
-                    &self.secp,
-                    contract,
-                    &self.wallet,
-                )?;
-                self.blockchain.send_transaction_async(&refund).await?;
+        } else {
+            self.bump_fee_if_stuck(&contract.accepted_contract, refund_broadcast.txid)
+                .await?;
+        }
+
+        Ok(false)
+    }
+
+    /// Bumps the fee of an unconfirmed transaction of the contract once it
+    /// has been waiting for longer than configured, with a child spending an
+    /// output of ours. A transaction still waiting as long after its last
+    /// bump is bumped again at a higher fee rate, the new child replacing the
+    /// previous one.
+    async fn bump_fee_if_stuck(
+        &self,
+        contract: &AcceptedContract,
+        txid: Txid,
+    ) -> Result<(), Error> {
+        let fee_bump = match &self.config.fee_bump {
+            Some(fee_bump) => fee_bump,
+            None => return Ok(()),
+        };
+        let contract_id = contract.get_contract_id();
+        let mut metadata = self
+            .store
+            .get_contract_metadata(&contract_id)
+            .await?
+            .unwrap_or_default();
+        let now = self.time.unix_time_now();
+
+        let first_seen_at = match metadata
+            .unconfirmed_transactions
+            .iter()
+            .find(|unconfirmed| unconfirmed.txid == txid)
+        {
+            Some(unconfirmed) => unconfirmed.first_seen_at,
+            None => {
+                metadata
+                    .unconfirmed_transactions
+                    .push(UnconfirmedTransaction {
+                        txid,
+                        first_seen_at: now,
+                    });
+                return self
+                    .store
+                    .upsert_contract_metadata(&contract_id, &metadata)
+                    .await;
+            }
+        };
+        let previous_bumps = metadata
+            .fee_bumps
+            .iter()
+            .filter(|bump| bump.parent_txid == txid);
+        let bump_count = previous_bumps.clone().count() as u64;
+        let waiting_since = previous_bumps
+            .map(|bump| bump.bumped_at)
+            .max()
+            .unwrap_or(first_seen_at);
+        if now < waiting_since + fee_bump.stuck_after {
+            return Ok(());
+        }
+        // Each bump raises the fee rate, so that its child pays enough more
+        // than the previous one to replace it.
+        let fee_rate = fee_bump.target_fee_rate * (bump_count + 1);
+
+        // The signed version of the parent, as known to the mempool.
+        let parent = self.blockchain.get_transaction_async(&txid).await?;
+        let offered_contract = &contract.offered_contract;
+        let own_params = if offered_contract.is_offer_party {
+            &offered_contract.offer_params
+        } else {
+            &contract.accept_params
+        };
+        let (vout, own_output) = match parent.output.iter().enumerate().find(|(_, output)| {
+            output.script_pubkey == own_params.payout_script_pubkey
+                || output.script_pubkey == own_params.change_script_pubkey
+        }) {
+            Some(output) => output,
+            None => {
+                warn!(
+                    "Transaction {} of contract {} is stuck but has no output of ours to bump its fee",
+                    txid,
+                    contract.get_contract_id_string()
+                );
+                return Ok(());
             }
+        };
 
-            self.store
-                .update_contract(&Contract::Refunded(contract.clone()))
+        let mut parent_input_value = 0;
+        for input in &parent.input {
+            let previous_tx = self
+                .blockchain
+                .get_transaction_async(&input.previous_output.txid)
                 .await?;
+            parent_input_value += previous_tx
+                .output
+                .get(input.previous_output.vout as usize)
+                .map(|output| output.value)
+                .ok_or_else(|| {
+                    Error::InvalidState(format!(
+                        "Missing output {} of transaction {}",
+                        input.previous_output.vout, input.previous_output.txid
+                    ))
+                })?;
         }
+        let parent_output_value: u64 = parent.output.iter().map(|output| output.value).sum();
+        let parent_fee = parent_input_value.saturating_sub(parent_output_value);
+        let parent_vsize = (parent.weight() as u64 + 3) / 4;
 
-        Ok(())
+        let package_fee = fee_rate * (parent_vsize + CPFP_CHILD_VSIZE);
+        if package_fee <= parent_fee {
+            return Ok(());
+        }
+        let child_fee = package_fee - parent_fee;
+        if own_output.value < child_fee + P2WPKH_DUST_LIMIT {
+            warn!(
+                "Output of ours in transaction {} of contract {} is too small to pay {} sats of fee",
+                txid,
+                contract.get_contract_id_string(),
+                child_fee
+            );
+            return Ok(());
+        }
+
+        let mut child = Transaction {
+            version: 2,
+            lock_time: PackedLockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: OutPoint {
+                    txid,
+                    vout: vout as u32,
+                },
+                script_sig: Script::new(),
+                sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+                witness: Witness::new(),
+            }],
+            output: vec![TxOut {
+                value: own_output.value - child_fee,
+                script_pubkey: self.wallet.get_new_address()?.script_pubkey(),
+            }],
+        };
+        self.wallet.sign_tx_input(&mut child, 0, own_output, None)?;
+        self.blockchain.send_transaction_async(&child).await?;
+
+        info!(
+            "Bumped fee of transaction {} of contract {} with child {} paying {} sats",
+            txid,
+            contract.get_contract_id_string(),
+            child.txid(),
+            child_fee
+        );
+        metadata.fee_bumps.push(FeeBump {
+            parent_txid: txid,
+            child_txid: child.txid(),
+            fee_rate,
+            fee: child_fee,
+            bumped_at: now,
+        });
+        self.store
+            .upsert_contract_metadata(&contract_id, &metadata)
+            .await
+    }
+
+    async fn broadcast_refund(&self, contract: &SignedContract) -> Result<(), Error> {
+        let refund = crate::dlc_manager::contract_updater::get_signed_refund(
+            &self.secp,
+            contract,
+            &self.wallet,
+        )?;
+        self.blockchain.send_transaction_async(&refund).await
     }
 }
 
@@ -868,4 +2421,40 @@ mod test {
         let denominator = super::calculate_denominator_from_basis_points(basis_points);
         assert_eq!(denominator, 0);
     }
+
+    #[test]
+    fn test_next_combination() {
+        let mut combination = vec![0, 1];
+        let mut combinations = vec![combination.clone()];
+        while super::next_combination(&mut combination, 4) {
+            combinations.push(combination.clone());
+        }
+        assert_eq!(
+            combinations,
+            vec![
+                vec![0, 1],
+                vec![0, 2],
+                vec![0, 3],
+                vec![1, 2],
+                vec![1, 3],
+                vec![2, 3]
+            ]
+        );
+
+        let mut combination = vec![0, 1, 2];
+        assert!(!super::next_combination(&mut combination, 3));
+    }
+
+    #[test]
+    fn test_report_legs() {
+        let contract_id = [1; 32];
+        assert_eq!(
+            super::report_legs(contract_id, vec!["a".to_string(), "b".to_string()]),
+            vec![
+                (contract_id, "a".to_string()),
+                (contract_id, "b".to_string())
+            ]
+        );
+        assert!(super::report_legs(contract_id, Vec::new()).is_empty());
+    }
 }
@@ -0,0 +1,178 @@
+//! Deployment settings of the [`Manager`](crate::Manager).
+
+use crate::dlc_manager::error::Error;
+use crate::{OfferAcceptancePolicy, NB_CONFIRMATIONS};
+use std::time::Duration;
+
+/// The default number of contracts checked concurrently.
+pub const DEFAULT_CHECK_CONCURRENCY: usize = 10;
+
+/// The default number of times a failed contract can be retried.
+pub const DEFAULT_MAX_RETRIES: u32 = 5;
+
+/// Number of confirmations required before a transaction of a contract is
+/// considered final.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ConfirmationDepths {
+    /// Confirmations of the funding transaction before a signed contract is
+    /// confirmed.
+    pub funding: u32,
+    /// Confirmations of a CET before a pre-closed contract is closed.
+    pub cet: u32,
+    /// Confirmations of the refund transaction before a contract is refunded.
+    pub refund: u32,
+}
+
+impl Default for ConfirmationDepths {
+    fn default() -> Self {
+        ConfirmationDepths {
+            funding: NB_CONFIRMATIONS,
+            cet: NB_CONFIRMATIONS,
+            refund: NB_CONFIRMATIONS,
+        }
+    }
+}
+
+/// Confirmation depths used for contracts with a total collateral of at
+/// least `min_total_collateral` sats.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CollateralConfirmationDepths {
+    pub min_total_collateral: u64,
+    pub confirmations: ConfirmationDepths,
+}
+
+/// Lease taken in the storage on a contract before working on it, for
+/// deployments where several replicas share the same storage.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ContractLeaseConfig {
+    /// Identifies this replica, must differ between replicas.
+    pub owner: String,
+    /// Seconds after which the lease of a replica that stopped without
+    /// releasing it can be taken over.
+    pub duration: u64,
+}
+
+/// Fee bumping of transactions stuck in the mempool, with a child spending
+/// an output of ours. A transaction still stuck `stuck_after` seconds after
+/// its last bump is bumped again, with a child replacing the previous one.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FeeBumpConfig {
+    /// Seconds a transaction stays unconfirmed before its fee is bumped.
+    pub stuck_after: u64,
+    /// Fee rate of the parent and child together, in sats per vbyte. Each
+    /// further bump of the same transaction adds it again.
+    pub target_fee_rate: u64,
+}
+
+/// Settings of a [`Manager`](crate::Manager).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ManagerConfig {
+    /// Depths used when no collateral override matches.
+    pub confirmations: ConfirmationDepths,
+    /// Per-contract overrides based on the total collateral. When several
+    /// match, the one with the highest `min_total_collateral` applies.
+    pub collateral_overrides: Vec<CollateralConfirmationDepths>,
+    /// When set, contracts are also locked through a lease in the storage.
+    /// Locking is only in-process otherwise.
+    pub contract_lease: Option<ContractLeaseConfig>,
+    /// Maximum number of contracts checked at the same time by
+    /// `periodic_check`.
+    pub check_concurrency: usize,
+    /// Time after which the check of a single contract is abandoned, until
+    /// the next `periodic_check`.
+    pub contract_check_timeout: Option<Duration>,
+    /// Seconds after which offered and accepted contracts that were not
+    /// signed are rejected. Offers never expire when not set.
+    pub offer_ttl: Option<u64>,
+    /// Conditions offers from counterparties must meet to be accepted.
+    pub offer_policy: OfferAcceptancePolicy,
+    /// When set, stuck funding, CET and refund transactions get their fee
+    /// bumped.
+    pub fee_bump: Option<FeeBumpConfig>,
+    /// Number of times a failed contract can be retried, further retries are
+    /// rejected.
+    pub max_retries: u32,
+    /// When set, the events of the contracts are stored in the journal of
+    /// the storage before the state they lead to, and kept there until the
+    /// subscriber handling them removes them.
+    pub journal_events: bool,
+}
+
+impl Default for ManagerConfig {
+    fn default() -> Self {
+        ManagerConfig {
+            confirmations: ConfirmationDepths::default(),
+            collateral_overrides: Vec::new(),
+            contract_lease: None,
+            check_concurrency: DEFAULT_CHECK_CONCURRENCY,
+            contract_check_timeout: None,
+            offer_ttl: None,
+            offer_policy: OfferAcceptancePolicy::default(),
+            fee_bump: None,
+            max_retries: DEFAULT_MAX_RETRIES,
+            journal_events: false,
+        }
+    }
+}
+
+impl ManagerConfig {
+    /// Returns the confirmation depths to use for a contract with the given
+    /// total collateral.
+    pub fn confirmations_for(&self, total_collateral: u64) -> ConfirmationDepths {
+        self.collateral_overrides
+            .iter()
+            .filter(|o| o.min_total_collateral <= total_collateral)
+            .max_by_key(|o| o.min_total_collateral)
+            .map(|o| o.confirmations)
+            .unwrap_or(self.confirmations)
+    }
+
+    /// Checks the settings are usable, [`Manager::new`](crate::Manager::new)
+    /// refuses a config that is not.
+    pub fn validate(&self) -> Result<(), Error> {
+        let all_depths = std::iter::once(&self.confirmations)
+            .chain(self.collateral_overrides.iter().map(|o| &o.confirmations));
+        for depths in all_depths {
+            if depths.funding == 0 || depths.cet == 0 || depths.refund == 0 {
+                return Err(Error::InvalidParameters(
+                    "Confirmation depths must be at least 1.".to_string(),
+                ));
+            }
+        }
+        if self.check_concurrency == 0 {
+            return Err(Error::InvalidParameters(
+                "Check concurrency must be at least 1.".to_string(),
+            ));
+        }
+        if self.offer_ttl == Some(0) {
+            return Err(Error::InvalidParameters(
+                "Offer TTL must be at least 1 second.".to_string(),
+            ));
+        }
+        if self.offer_policy.min_threshold == 0 {
+            return Err(Error::InvalidParameters(
+                "Offer policy minimum threshold must be at least 1.".to_string(),
+            ));
+        }
+        if self.offer_policy.min_refund_delay > self.offer_policy.max_refund_delay {
+            return Err(Error::InvalidParameters(
+                "Offer policy minimum refund delay exceeds the maximum.".to_string(),
+            ));
+        }
+        if let Some(fee_bump) = &self.fee_bump {
+            if fee_bump.stuck_after == 0 || fee_bump.target_fee_rate == 0 {
+                return Err(Error::InvalidParameters(
+                    "Fee bumping needs a non zero stuck time and target fee rate.".to_string(),
+                ));
+            }
+        }
+        if let Some(lease) = &self.contract_lease {
+            if lease.owner.is_empty() || lease.duration == 0 {
+                return Err(Error::InvalidParameters(
+                    "Contract lease needs an owner and a non zero duration.".to_string(),
+                ));
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,162 @@
+//! Conditions an offer received from a counterparty must meet to be accepted.
+
+use crate::dlc_manager::contract::{
+    accepted_contract::AcceptedContract, offered_contract::OfferedContract,
+};
+use crate::dlc_manager::error::Error;
+use crate::FIFTY_YEARS;
+use secp256k1_zkp::XOnlyPublicKey;
+
+/// Acceptance policy for offers received from a counterparty.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OfferAcceptancePolicy {
+    /// Oracle public keys offers may use. When empty, the keys of the oracles
+    /// of the manager are used.
+    pub allowed_oracle_keys: Vec<XOnlyPublicKey>,
+    /// Minimum number of oracles required to attest to close the contract.
+    pub min_threshold: usize,
+    /// Maximum total collateral of the contract, in sats.
+    pub max_total_collateral: Option<u64>,
+    /// Minimum seconds between the oracle event maturity and the refund.
+    pub min_refund_delay: u32,
+    /// Maximum seconds between the oracle event maturity and the refund.
+    pub max_refund_delay: u32,
+    /// Maximum protocol fee paid out of the funding transaction, in basis
+    /// points of the total collateral.
+    pub max_protocol_fee_basis_points: Option<u64>,
+}
+
+impl Default for OfferAcceptancePolicy {
+    fn default() -> Self {
+        OfferAcceptancePolicy {
+            allowed_oracle_keys: Vec::new(),
+            min_threshold: 1,
+            max_total_collateral: None,
+            min_refund_delay: 0,
+            max_refund_delay: FIFTY_YEARS,
+            max_protocol_fee_basis_points: None,
+        }
+    }
+}
+
+impl OfferAcceptancePolicy {
+    /// Checks the oracles, collateral, refund delay and protocol fee of an
+    /// offer.
+    /// `manager_oracle_keys` are the keys trusted when no allowed keys are
+    /// configured.
+    pub(crate) fn check_offer<'a>(
+        &self,
+        offered_contract: &OfferedContract,
+        manager_oracle_keys: impl Iterator<Item = &'a XOnlyPublicKey>,
+    ) -> Result<(), Error> {
+        let trusted_keys: Vec<&XOnlyPublicKey> = if self.allowed_oracle_keys.is_empty() {
+            manager_oracle_keys.collect()
+        } else {
+            self.allowed_oracle_keys.iter().collect()
+        };
+
+        for contract_info in &offered_contract.contract_info {
+            for announcement in &contract_info.oracle_announcements {
+                if !trusted_keys.contains(&&announcement.oracle_public_key) {
+                    return Err(Error::InvalidParameters(format!(
+                        "Offer uses untrusted oracle {} for event {}.",
+                        announcement.oracle_public_key, announcement.oracle_event.event_id
+                    )));
+                }
+            }
+            if contract_info.threshold < self.min_threshold {
+                return Err(Error::InvalidParameters(format!(
+                    "Offer threshold of {} is below the minimum of {}.",
+                    contract_info.threshold, self.min_threshold
+                )));
+            }
+        }
+
+        if let Some(max_total_collateral) = self.max_total_collateral {
+            if offered_contract.total_collateral > max_total_collateral {
+                return Err(Error::InvalidParameters(format!(
+                    "Offer total collateral of {} sats exceeds the maximum of {} sats.",
+                    offered_contract.total_collateral, max_total_collateral
+                )));
+            }
+        }
+
+        let maturity = offered_contract
+            .contract_info
+            .iter()
+            .flat_map(|info| info.oracle_announcements.iter())
+            .map(|announcement| announcement.oracle_event.event_maturity_epoch)
+            .max()
+            .unwrap_or_default();
+        let refund_delay = offered_contract.refund_locktime.saturating_sub(maturity);
+        if refund_delay < self.min_refund_delay || refund_delay > self.max_refund_delay {
+            return Err(Error::InvalidParameters(format!(
+                "Offer refund delay of {} seconds is outside the allowed range of {} to {} seconds.",
+                refund_delay, self.min_refund_delay, self.max_refund_delay
+            )));
+        }
+
+        // The fee output of the funding transaction is the total collateral
+        // divided by the denominator of the offer, none when it is zero.
+        let protocol_fee = match offered_contract.fee_percentage_denominator {
+            0 => 0,
+            denominator => offered_contract.total_collateral / denominator,
+        };
+        self.check_protocol_fee_amount(protocol_fee, offered_contract.total_collateral)
+    }
+
+    /// Checks again the protocol fee of an accepted offer, now that the
+    /// funding transaction is built, as every output of it that is neither the
+    /// funding output nor the change of a party.
+    pub(crate) fn check_protocol_fee(
+        &self,
+        accepted_contract: &AcceptedContract,
+    ) -> Result<(), Error> {
+        if self.max_protocol_fee_basis_points.is_none() {
+            return Ok(());
+        }
+
+        let dlc_transactions = &accepted_contract.dlc_transactions;
+        let funding_script = dlc_transactions.funding_script_pubkey.to_v0_p2wsh();
+        let offer_change = &accepted_contract
+            .offered_contract
+            .offer_params
+            .change_script_pubkey;
+        let accept_change = &accepted_contract.accept_params.change_script_pubkey;
+        let protocol_fee: u64 = dlc_transactions
+            .fund
+            .output
+            .iter()
+            .filter(|output| {
+                output.script_pubkey != funding_script
+                    && &output.script_pubkey != offer_change
+                    && &output.script_pubkey != accept_change
+            })
+            .map(|output| output.value)
+            .sum();
+
+        self.check_protocol_fee_amount(
+            protocol_fee,
+            accepted_contract.offered_contract.total_collateral,
+        )
+    }
+
+    fn check_protocol_fee_amount(
+        &self,
+        protocol_fee: u64,
+        total_collateral: u64,
+    ) -> Result<(), Error> {
+        let max_basis_points = match self.max_protocol_fee_basis_points {
+            Some(max_basis_points) => max_basis_points,
+            None => return Ok(()),
+        };
+        if protocol_fee as u128 * 10_000 > total_collateral as u128 * max_basis_points as u128 {
+            return Err(Error::InvalidParameters(format!(
+                "Offer protocol fee of {} sats exceeds {} basis points of the {} sats collateral.",
+                protocol_fee, max_basis_points, total_collateral
+            )));
+        }
+
+        Ok(())
+    }
+}
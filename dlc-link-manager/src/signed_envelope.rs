@@ -0,0 +1,159 @@
+//! DLC messages signed by the party sending them, so that a contract only
+//! moves forward with messages from its counterparty.
+
+use crate::dlc_manager::error::Error;
+use dlc_messages::{AcceptDlc, Message as DlcMessage, OfferDlc, SignDlc};
+use secp256k1_zkp::ecdsa::Signature;
+use secp256k1_zkp::hashes::sha256;
+use secp256k1_zkp::{Message, PublicKey, Secp256k1, SecretKey, Signing, Verification};
+use serde::{Deserialize, Serialize};
+
+/// The messages that can be sent in an envelope, tagged with their type so
+/// that the signature also covers it.
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "type", content = "dlcMessage", rename_all = "camelCase")]
+enum EnvelopeMessage {
+    Offer(OfferDlc),
+    Accept(AcceptDlc),
+    Sign(SignDlc),
+}
+
+/// An offer, accept or sign message along with the key of its sender, the
+/// node id of the sender for the [`Manager`](crate::Manager).
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SignedEnvelope {
+    /// JSON of the message, signed as is.
+    pub message: String,
+    pub sender: PublicKey,
+    /// ECDSA signature by `sender` of the SHA256 of `message`.
+    pub signature: Signature,
+}
+
+impl SignedEnvelope {
+    /// Signs the message with the key of the sender.
+    pub fn seal<C: Signing>(
+        secp: &Secp256k1<C>,
+        message: &DlcMessage,
+        secret_key: &SecretKey,
+    ) -> Result<Self, Error> {
+        let message = match message {
+            DlcMessage::Offer(o) => EnvelopeMessage::Offer(o.clone()),
+            DlcMessage::Accept(a) => EnvelopeMessage::Accept(a.clone()),
+            DlcMessage::Sign(s) => EnvelopeMessage::Sign(s.clone()),
+            _ => {
+                return Err(Error::InvalidParameters(
+                    "Only offer, accept and sign messages can be signed.".to_string(),
+                ))
+            }
+        };
+        let message = serde_json::to_string(&message)
+            .map_err(|e| Error::InvalidParameters(format!("Invalid message: {}", e)))?;
+        let signature = secp.sign_ecdsa(&digest(&message), secret_key);
+        Ok(SignedEnvelope {
+            message,
+            sender: PublicKey::from_secret_key(secp, secret_key),
+            signature,
+        })
+    }
+
+    /// Checks the signature of the envelope and returns the message with the
+    /// key of its sender.
+    pub fn open<C: Verification>(
+        &self,
+        secp: &Secp256k1<C>,
+    ) -> Result<(DlcMessage, PublicKey), Error> {
+        secp.verify_ecdsa(&digest(&self.message), &self.signature, &self.sender)
+            .map_err(|_| {
+                Error::InvalidParameters(format!(
+                    "Invalid signature of message from {}.",
+                    self.sender
+                ))
+            })?;
+        let message = match serde_json::from_str(&self.message)
+            .map_err(|e| Error::InvalidParameters(format!("Invalid message: {}", e)))?
+        {
+            EnvelopeMessage::Offer(o) => DlcMessage::Offer(o),
+            EnvelopeMessage::Accept(a) => DlcMessage::Accept(a),
+            EnvelopeMessage::Sign(s) => DlcMessage::Sign(s),
+        };
+        Ok((message, self.sender))
+    }
+}
+
+fn digest(message: &str) -> Message {
+    Message::from_hashed_data::<sha256::Hash>(message.as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dlc_messages::{CetAdaptorSignatures, FundingSignatures};
+
+    fn sign_message(
+        secp: &Secp256k1<secp256k1_zkp::All>,
+        secret_key: &SecretKey,
+        contract_id: u8,
+    ) -> DlcMessage {
+        DlcMessage::Sign(SignDlc {
+            protocol_version: 1,
+            contract_id: [contract_id; 32],
+            cet_adaptor_signatures: CetAdaptorSignatures {
+                ecdsa_adaptor_signatures: vec![],
+            },
+            refund_signature: secp.sign_ecdsa(&digest("refund"), secret_key),
+            funding_signatures: FundingSignatures {
+                funding_signatures: vec![],
+            },
+        })
+    }
+
+    #[test]
+    fn opened_envelope_returns_the_message_and_its_sender() {
+        let secp = Secp256k1::new();
+        let secret_key = SecretKey::from_slice(&[1; 32]).expect("a valid key");
+        let envelope =
+            SignedEnvelope::seal(&secp, &sign_message(&secp, &secret_key, 7), &secret_key)
+                .expect("to seal the message");
+
+        let (message, sender) = envelope.open(&secp).expect("a valid envelope");
+
+        assert_eq!(sender, PublicKey::from_secret_key(&secp, &secret_key));
+        match message {
+            DlcMessage::Sign(sign) => assert_eq!(sign.contract_id, [7; 32]),
+            _ => panic!("Expected a sign message"),
+        }
+    }
+
+    #[test]
+    fn tampered_message_is_rejected() {
+        let secp = Secp256k1::new();
+        let secret_key = SecretKey::from_slice(&[1; 32]).expect("a valid key");
+        let mut envelope =
+            SignedEnvelope::seal(&secp, &sign_message(&secp, &secret_key, 7), &secret_key)
+                .expect("to seal the message");
+        let other_message = serde_json::to_string(&EnvelopeMessage::Sign(
+            match sign_message(&secp, &secret_key, 8) {
+                DlcMessage::Sign(sign) => sign,
+                _ => unreachable!(),
+            },
+        ))
+        .expect("to serialize the message");
+        envelope.message = other_message;
+
+        assert!(envelope.open(&secp).is_err());
+    }
+
+    #[test]
+    fn swapped_sender_is_rejected() {
+        let secp = Secp256k1::new();
+        let secret_key = SecretKey::from_slice(&[1; 32]).expect("a valid key");
+        let other_key = SecretKey::from_slice(&[2; 32]).expect("a valid key");
+        let mut envelope =
+            SignedEnvelope::seal(&secp, &sign_message(&secp, &secret_key, 7), &secret_key)
+                .expect("to seal the message");
+        envelope.sender = PublicKey::from_secret_key(&secp, &other_key);
+
+        assert!(envelope.open(&secp).is_err());
+    }
+}
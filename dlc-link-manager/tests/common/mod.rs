@@ -0,0 +1,968 @@
+//! In-memory test doubles for the traits the [`Manager`] depends on, and an
+//! environment with an offering and an accepting party sharing a chain and
+//! an oracle.
+
+use bitcoin::hashes::Hash;
+use bitcoin::{
+    Address, Block, BlockHash, EcdsaSighashType, Network, OutPoint, PackedLockTime, Script,
+    Sequence, Transaction, TxIn, TxOut, Txid, Witness,
+};
+use dlc::{EnumerationPayout, Payout};
+use dlc_link_manager::{
+    AsyncBlockchain, AsyncOracle, AsyncStorage, ContractEvent, ContractMetadata, ContractState,
+    Manager, ManagerConfig, ONE_DAY_IN_SECONDS,
+};
+use dlc_manager::contract::{
+    accepted_contract::AcceptedContract,
+    contract_input::{ContractInput, ContractInputInfo, OracleInput},
+    enum_descriptor::EnumDescriptor,
+    offered_contract::OfferedContract,
+    signed_contract::SignedContract,
+    Contract, ContractDescriptor, PreClosedContract,
+};
+use dlc_manager::error::Error;
+use dlc_manager::{Blockchain, ContractId, Signer, Time, Utxo, Wallet};
+use dlc_messages::oracle_msgs::{
+    EnumEventDescriptor, EventDescriptor, OracleAnnouncement, OracleAttestation, OracleEvent,
+};
+use dlc_messages::{AcceptDlc, Message as DlcMessage, OfferDlc};
+use lightning::util::ser::Writeable;
+use secp256k1_zkp::rand::thread_rng;
+use secp256k1_zkp::{All, KeyPair, Message, PublicKey, Secp256k1, SecretKey, XOnlyPublicKey};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+pub const OUTCOME_OFFER_WINS: &str = "offer_wins";
+pub const OUTCOME_ACCEPT_WINS: &str = "accept_wins";
+pub const ACCEPT_COLLATERAL: u64 = 100_000;
+pub const START_TIME: u64 = 1_700_000_000;
+const WALLET_FUNDS: u64 = 1_000_000;
+
+#[derive(Default)]
+struct ChainState {
+    /// Hashes of the blocks of the best chain, by height.
+    blocks: Vec<BlockHash>,
+    /// Height of the block each confirmed transaction is in.
+    confirmed: HashMap<Txid, usize>,
+    mempool: Vec<Txid>,
+    transactions: HashMap<Txid, Transaction>,
+    /// Number of blocks ever mined, so that blocks mined after a
+    /// reorganisation get new hashes.
+    mined: u64,
+    /// Transaction lookups fail, as when the chain source times out.
+    unavailable: bool,
+    /// Confirmation lookups never complete, as when the chain source hangs.
+    stalled: bool,
+}
+
+impl ChainState {
+    fn is_live(&self, txid: &Txid) -> bool {
+        self.confirmed.contains_key(txid) || self.mempool.contains(txid)
+    }
+
+    fn spending_txid(&self, outpoint: &OutPoint) -> Option<Txid> {
+        self.transactions
+            .iter()
+            .filter(|(txid, _)| self.is_live(txid))
+            .find(|(_, tx)| tx.input.iter().any(|i| i.previous_output == *outpoint))
+            .map(|(txid, _)| *txid)
+    }
+
+    fn confirmations(&self, txid: &Txid) -> u32 {
+        self.confirmed
+            .get(txid)
+            .map(|height| (self.blocks.len() - height) as u32)
+            .unwrap_or(0)
+    }
+
+    fn send(&mut self, tx: &Transaction) -> Result<(), Error> {
+        let txid = tx.txid();
+        if self.is_live(&txid) {
+            return Ok(());
+        }
+        let mut replaced = Vec::new();
+        for input in &tx.input {
+            let previous_output = &input.previous_output;
+            let previous_tx_live = self.is_live(&previous_output.txid)
+                && self.transactions[&previous_output.txid].output.len()
+                    > previous_output.vout as usize;
+            if !previous_tx_live {
+                return Err(Error::BlockchainError(format!(
+                    "Missing input {} of transaction {}",
+                    previous_output, txid
+                )));
+            }
+            if let Some(spending_txid) = self.spending_txid(previous_output) {
+                // Unconfirmed transactions signaling replaceability are
+                // replaced, whatever fee the new one pays.
+                let replaceable = self.mempool.contains(&spending_txid)
+                    && self.transactions[&spending_txid]
+                        .input
+                        .iter()
+                        .all(|i| i.sequence.is_rbf());
+                if !replaceable {
+                    return Err(Error::BlockchainError(format!(
+                        "Input {} of transaction {} already spent by {}",
+                        previous_output, txid, spending_txid
+                    )));
+                }
+                replaced.push(spending_txid);
+            }
+        }
+        self.mempool.retain(|txid| !replaced.contains(txid));
+        self.transactions.insert(txid, tx.clone());
+        self.mempool.push(txid);
+        Ok(())
+    }
+}
+
+/// A chain where blocks are mined on demand, each first block mined after a
+/// broadcast confirming the whole mempool.
+#[derive(Default)]
+pub struct TestChain {
+    state: Mutex<ChainState>,
+}
+
+impl TestChain {
+    fn state(&self) -> std::sync::MutexGuard<'_, ChainState> {
+        self.state.lock().expect("to lock the chain state")
+    }
+
+    /// Mines `nb_blocks` blocks, the first one confirming the mempool.
+    pub fn mine(&self, nb_blocks: usize) {
+        let mut state = self.state();
+        for _ in 0..nb_blocks {
+            state.mined += 1;
+            let hash = BlockHash::hash(&state.mined.to_le_bytes());
+            let height = state.blocks.len();
+            for txid in std::mem::take(&mut state.mempool) {
+                state.confirmed.insert(txid, height);
+            }
+            state.blocks.push(hash);
+        }
+    }
+
+    /// Disconnects the last `nb_blocks` blocks, returning their transactions
+    /// to the mempool.
+    pub fn reorg(&self, nb_blocks: usize) {
+        let mut state = self.state();
+        let new_height = state.blocks.len().saturating_sub(nb_blocks);
+        state.blocks.truncate(new_height);
+        let mut disconnected: Vec<(Txid, usize)> = state
+            .confirmed
+            .iter()
+            .filter(|(_, height)| **height >= new_height)
+            .map(|(txid, height)| (*txid, *height))
+            .collect();
+        disconnected.sort_by_key(|(_, height)| *height);
+        for (txid, _) in disconnected {
+            state.confirmed.remove(&txid);
+            state.mempool.push(txid);
+        }
+    }
+
+    pub fn confirmations(&self, txid: &Txid) -> u32 {
+        self.state().confirmations(txid)
+    }
+
+    /// Makes transaction lookups fail until set back.
+    pub fn set_unavailable(&self, unavailable: bool) {
+        self.state().unavailable = unavailable;
+    }
+
+    /// Makes confirmation lookups hang until set back.
+    pub fn set_stalled(&self, stalled: bool) {
+        self.state().stalled = stalled;
+    }
+
+    /// Drops an unconfirmed transaction from the mempool, as nodes do with
+    /// transactions they evict.
+    pub fn evict(&self, txid: &Txid) {
+        self.state().mempool.retain(|t| t != txid);
+    }
+
+    pub fn spending_txid(&self, outpoint: &OutPoint) -> Option<Txid> {
+        self.state().spending_txid(outpoint)
+    }
+
+    /// Creates a confirmed output of `value` sats paying to `script_pubkey`.
+    fn fund(&self, script_pubkey: Script, value: u64) -> OutPoint {
+        let tx = {
+            let state = self.state();
+            Transaction {
+                version: 2,
+                lock_time: PackedLockTime(state.transactions.len() as u32),
+                input: vec![TxIn {
+                    previous_output: OutPoint::null(),
+                    script_sig: Script::new(),
+                    sequence: Sequence::MAX,
+                    witness: Witness::new(),
+                }],
+                output: vec![TxOut {
+                    value,
+                    script_pubkey,
+                }],
+            }
+        };
+        let txid = tx.txid();
+        {
+            let mut state = self.state();
+            state.transactions.insert(txid, tx);
+            state.mempool.push(txid);
+        }
+        self.mine(1);
+        OutPoint { txid, vout: 0 }
+    }
+
+    fn get_transaction_sync(&self, txid: &Txid) -> Result<Transaction, Error> {
+        let state = self.state();
+        match state.transactions.get(txid) {
+            Some(tx) if state.is_live(txid) => Ok(tx.clone()),
+            _ => Err(Error::BlockchainError(format!("tx not found {}", txid))),
+        }
+    }
+}
+
+impl Blockchain for TestChain {
+    fn send_transaction(&self, transaction: &Transaction) -> Result<(), Error> {
+        self.state().send(transaction)
+    }
+
+    fn get_network(&self) -> Result<Network, Error> {
+        Ok(Network::Regtest)
+    }
+
+    fn get_blockchain_height(&self) -> Result<u64, Error> {
+        Ok(self.state().blocks.len() as u64)
+    }
+
+    fn get_block_at_height(&self, height: u64) -> Result<Block, Error> {
+        Err(Error::BlockchainError(format!(
+            "Block {} not available, blocks are only used for lightning",
+            height
+        )))
+    }
+
+    fn get_transaction(&self, tx_id: &Txid) -> Result<Transaction, Error> {
+        self.get_transaction_sync(tx_id)
+    }
+
+    fn get_transaction_confirmations(&self, tx_id: &Txid) -> Result<u32, Error> {
+        Ok(self.confirmations(tx_id))
+    }
+}
+
+impl AsyncBlockchain for TestChain {
+    async fn get_transaction_confirmations_async(&self, txid: &Txid) -> Result<u32, Error> {
+        let stalled = self.state().stalled;
+        if stalled {
+            futures::future::pending::<()>().await;
+        }
+        Ok(self.confirmations(txid))
+    }
+
+    async fn send_transaction_async(&self, tx: &Transaction) -> Result<(), Error> {
+        self.state().send(tx)
+    }
+
+    async fn get_network_async(&self) -> Result<Network, Error> {
+        Ok(Network::Regtest)
+    }
+
+    async fn get_transaction_async(&self, tx_id: &Txid) -> Result<Transaction, Error> {
+        self.get_transaction_sync(tx_id)
+    }
+
+    async fn find_transaction_async(&self, tx_id: &Txid) -> Result<Option<Transaction>, Error> {
+        let state = self.state();
+        if state.unavailable {
+            return Err(Error::BlockchainError("chain unavailable".to_string()));
+        }
+        match state.transactions.get(tx_id) {
+            Some(tx) if state.is_live(tx_id) => Ok(Some(tx.clone())),
+            _ => Ok(None),
+        }
+    }
+
+    async fn get_output_spending_txid_async(
+        &self,
+        outpoint: &OutPoint,
+    ) -> Result<Option<Txid>, Error> {
+        Ok(self.spending_txid(outpoint))
+    }
+
+    async fn get_transaction_block_hash_async(
+        &self,
+        tx_id: &Txid,
+    ) -> Result<Option<BlockHash>, Error> {
+        let state = self.state();
+        Ok(state
+            .confirmed
+            .get(tx_id)
+            .map(|height| state.blocks[*height]))
+    }
+
+    async fn is_block_in_best_chain_async(&self, block_hash: &BlockHash) -> Result<bool, Error> {
+        Ok(self.state().blocks.contains(block_hash))
+    }
+}
+
+/// A single key P2WPKH wallet.
+pub struct TestWallet {
+    secp: Secp256k1<All>,
+    seckey: SecretKey,
+    address: Address,
+    utxos: Mutex<Vec<Utxo>>,
+}
+
+impl TestWallet {
+    /// Creates a wallet with a confirmed output of `value` sats on the chain.
+    pub fn new(chain: &TestChain, value: u64) -> Self {
+        let secp = Secp256k1::new();
+        let seckey = SecretKey::new(&mut thread_rng());
+        let address = Address::p2wpkh(
+            &bitcoin::PublicKey::new(PublicKey::from_secret_key(&secp, &seckey)),
+            Network::Regtest,
+        )
+        .expect("a compressed key");
+        let outpoint = chain.fund(address.script_pubkey(), value);
+        let utxo = Utxo {
+            tx_out: TxOut {
+                value,
+                script_pubkey: address.script_pubkey(),
+            },
+            outpoint,
+            address: address.clone(),
+            redeem_script: Script::new(),
+            reserved: false,
+        };
+        TestWallet {
+            secp,
+            seckey,
+            address,
+            utxos: Mutex::new(vec![utxo]),
+        }
+    }
+
+    pub fn address(&self) -> &Address {
+        &self.address
+    }
+}
+
+impl Signer for TestWallet {
+    fn sign_tx_input(
+        &self,
+        tx: &mut Transaction,
+        input_index: usize,
+        tx_out: &TxOut,
+        _: Option<Script>,
+    ) -> Result<(), Error> {
+        dlc::util::sign_p2wpkh_input(
+            &self.secp,
+            &self.seckey,
+            tx,
+            input_index,
+            EcdsaSighashType::All,
+            tx_out.value,
+        )?;
+        Ok(())
+    }
+
+    fn get_secret_key_for_pubkey(&self, _pubkey: &PublicKey) -> Result<SecretKey, Error> {
+        Ok(self.seckey)
+    }
+}
+
+impl Wallet for TestWallet {
+    fn get_new_address(&self) -> Result<Address, Error> {
+        Ok(self.address.clone())
+    }
+
+    fn get_new_secret_key(&self) -> Result<SecretKey, Error> {
+        Ok(self.seckey)
+    }
+
+    fn get_utxos_for_amount(
+        &self,
+        amount: u64,
+        _fee_rate: Option<u64>,
+        lock_utxos: bool,
+    ) -> Result<Vec<Utxo>, Error> {
+        let mut utxos = self.utxos.lock().expect("to lock the wallet utxos");
+        let mut selected = Vec::new();
+        let mut selected_amount = 0;
+        for utxo in utxos.iter_mut().filter(|utxo| !utxo.reserved) {
+            if selected_amount >= amount {
+                break;
+            }
+            utxo.reserved = lock_utxos;
+            selected_amount += utxo.tx_out.value;
+            selected.push(utxo.clone());
+        }
+        if selected_amount < amount {
+            return Err(Error::WalletError("Not enough funds".into()));
+        }
+        Ok(selected)
+    }
+
+    fn import_address(&self, _: &Address) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+/// Stores contracts the way the storage api does, accepted and signed
+/// contracts replacing the offer they come from.
+#[derive(Default)]
+pub struct MemoryStorage {
+    contracts: Mutex<HashMap<ContractId, Contract>>,
+    metadata: Mutex<HashMap<ContractId, ContractMetadata>>,
+    event_index: Mutex<HashMap<String, ContractId>>,
+    journal: Mutex<HashMap<String, ContractEvent>>,
+    leases: Mutex<HashMap<ContractId, (String, u64)>>,
+}
+
+impl MemoryStorage {
+    fn contracts_in_state<T>(&self, state: impl Fn(&Contract) -> Option<T>) -> Vec<T> {
+        self.contracts
+            .lock()
+            .expect("to lock the contracts")
+            .values()
+            .filter_map(state)
+            .collect()
+    }
+
+    /// Forgets the contracts indexed by event id, as for contracts stored
+    /// before the index existed.
+    pub fn clear_event_index(&self) {
+        self.event_index
+            .lock()
+            .expect("to lock the event index")
+            .clear();
+    }
+}
+
+impl AsyncStorage for MemoryStorage {
+    async fn get_contract(&self, id: &ContractId) -> Result<Option<Contract>, Error> {
+        Ok(self
+            .contracts
+            .lock()
+            .expect("to lock the contracts")
+            .get(id)
+            .cloned())
+    }
+
+    async fn get_contracts(&self) -> Result<Vec<Contract>, Error> {
+        Ok(self.contracts_in_state(|c| Some(c.clone())))
+    }
+
+    async fn create_contract(&self, contract: &OfferedContract) -> Result<(), Error> {
+        let mut contracts = self.contracts.lock().expect("to lock the contracts");
+        if contracts.contains_key(&contract.id) {
+            return Err(Error::StorageError("Contract already exists".to_string()));
+        }
+        contracts.insert(contract.id, Contract::Offered(contract.clone()));
+        Ok(())
+    }
+
+    async fn delete_contract(&self, id: &ContractId) -> Result<(), Error> {
+        self.contracts
+            .lock()
+            .expect("to lock the contracts")
+            .remove(id);
+        Ok(())
+    }
+
+    async fn update_contract(&self, contract: &Contract) -> Result<(), Error> {
+        let mut contracts = self.contracts.lock().expect("to lock the contracts");
+        if let Contract::Accepted(_) | Contract::Signed(_) = contract {
+            contracts.remove(&contract.get_temporary_id());
+        }
+        contracts.insert(contract.get_id(), contract.clone());
+        Ok(())
+    }
+
+    async fn get_contract_offers(&self) -> Result<Vec<OfferedContract>, Error> {
+        Ok(self.contracts_in_state(|c| match c {
+            Contract::Offered(o) => Some(o.clone()),
+            _ => None,
+        }))
+    }
+
+    async fn get_accepted_contracts(&self) -> Result<Vec<AcceptedContract>, Error> {
+        Ok(self.contracts_in_state(|c| match c {
+            Contract::Accepted(a) => Some(a.clone()),
+            _ => None,
+        }))
+    }
+
+    async fn get_signed_contracts(&self) -> Result<Vec<SignedContract>, Error> {
+        Ok(self.contracts_in_state(|c| match c {
+            Contract::Signed(s) => Some(s.clone()),
+            _ => None,
+        }))
+    }
+
+    async fn get_confirmed_contracts(&self) -> Result<Vec<SignedContract>, Error> {
+        Ok(self.contracts_in_state(|c| match c {
+            Contract::Confirmed(s) => Some(s.clone()),
+            _ => None,
+        }))
+    }
+
+    async fn get_preclosed_contracts(&self) -> Result<Vec<PreClosedContract>, Error> {
+        Ok(self.contracts_in_state(|c| match c {
+            Contract::PreClosed(p) => Some(p.clone()),
+            _ => None,
+        }))
+    }
+
+    async fn get_contract_metadata(
+        &self,
+        id: &ContractId,
+    ) -> Result<Option<ContractMetadata>, Error> {
+        Ok(self
+            .metadata
+            .lock()
+            .expect("to lock the metadata")
+            .get(id)
+            .cloned())
+    }
+
+    async fn upsert_contract_metadata(
+        &self,
+        id: &ContractId,
+        metadata: &ContractMetadata,
+    ) -> Result<(), Error> {
+        self.metadata
+            .lock()
+            .expect("to lock the metadata")
+            .insert(*id, metadata.clone());
+        Ok(())
+    }
+
+    async fn get_contract_id_by_event_id(
+        &self,
+        event_id: &str,
+    ) -> Result<Option<ContractId>, Error> {
+        Ok(self
+            .event_index
+            .lock()
+            .expect("to lock the event index")
+            .get(event_id)
+            .cloned())
+    }
+
+    async fn index_contract_event_id(&self, event_id: &str, id: &ContractId) -> Result<(), Error> {
+        self.event_index
+            .lock()
+            .expect("to lock the event index")
+            .insert(event_id.to_string(), *id);
+        Ok(())
+    }
+
+    async fn journal_contract_event(&self, event: &ContractEvent) -> Result<(), Error> {
+        self.journal
+            .lock()
+            .expect("to lock the journal")
+            .entry(event.journal_id())
+            .or_insert_with(|| event.clone());
+        Ok(())
+    }
+
+    async fn get_journaled_contract_events(&self) -> Result<Vec<ContractEvent>, Error> {
+        Ok(self
+            .journal
+            .lock()
+            .expect("to lock the journal")
+            .values()
+            .cloned()
+            .collect())
+    }
+
+    async fn remove_journaled_contract_event(&self, event: &ContractEvent) -> Result<(), Error> {
+        self.journal
+            .lock()
+            .expect("to lock the journal")
+            .remove(&event.journal_id());
+        Ok(())
+    }
+
+    async fn acquire_contract_lease(
+        &self,
+        id: &ContractId,
+        owner: &str,
+        now: u64,
+        expires_at: u64,
+    ) -> Result<bool, Error> {
+        let mut leases = self.leases.lock().expect("to lock the leases");
+        match leases.get(id) {
+            Some((holder, lease_expires_at)) if holder != owner && *lease_expires_at > now => {
+                Ok(false)
+            }
+            _ => {
+                leases.insert(*id, (owner.to_string(), expires_at));
+                Ok(true)
+            }
+        }
+    }
+
+    async fn release_contract_lease(&self, id: &ContractId, owner: &str) -> Result<(), Error> {
+        let mut leases = self.leases.lock().expect("to lock the leases");
+        if leases.get(id).map(|(holder, _)| holder == owner) == Some(true) {
+            leases.remove(id);
+        }
+        Ok(())
+    }
+}
+
+/// An oracle announcing enum events and attesting them on demand.
+pub struct TestOracle {
+    secp: Secp256k1<All>,
+    key_pair: KeyPair,
+    announcements: Mutex<HashMap<String, (OracleAnnouncement, SecretKey)>>,
+    attestations: Mutex<HashMap<String, OracleAttestation>>,
+}
+
+impl TestOracle {
+    pub fn new() -> Self {
+        let secp = Secp256k1::new();
+        let key_pair = KeyPair::new(&secp, &mut thread_rng());
+        TestOracle {
+            secp,
+            key_pair,
+            announcements: Mutex::new(HashMap::new()),
+            attestations: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn public_key(&self) -> XOnlyPublicKey {
+        XOnlyPublicKey::from_keypair(&self.key_pair).0
+    }
+
+    /// Announces an event with the given outcomes, maturing at `maturity`.
+    pub fn announce(&self, event_id: &str, maturity: u32, outcomes: &[&str]) {
+        let nonce = SecretKey::new(&mut thread_rng());
+        let nonce_key_pair =
+            KeyPair::from_seckey_slice(&self.secp, nonce.as_ref()).expect("a valid nonce");
+        let oracle_event = OracleEvent {
+            oracle_nonces: vec![XOnlyPublicKey::from_keypair(&nonce_key_pair).0],
+            event_maturity_epoch: maturity,
+            event_descriptor: EventDescriptor::EnumEvent(EnumEventDescriptor {
+                outcomes: outcomes.iter().map(|o| o.to_string()).collect(),
+            }),
+            event_id: event_id.to_string(),
+        };
+        let mut event_bytes = Vec::new();
+        oracle_event
+            .write(&mut event_bytes)
+            .expect("to serialize the oracle event");
+        let msg = Message::from_hashed_data::<secp256k1_zkp::hashes::sha256::Hash>(&event_bytes);
+        let announcement = OracleAnnouncement {
+            announcement_signature: self.secp.sign_schnorr(&msg, &self.key_pair),
+            oracle_public_key: self.public_key(),
+            oracle_event,
+        };
+        self.announcements
+            .lock()
+            .expect("to lock the announcements")
+            .insert(event_id.to_string(), (announcement, nonce));
+    }
+
+    /// Attests `outcome` for an announced event.
+    pub fn attest(&self, event_id: &str, outcome: &str) {
+        let nonce = self
+            .announcements
+            .lock()
+            .expect("to lock the announcements")
+            .get(event_id)
+            .map(|(_, nonce)| *nonce)
+            .expect("the event to be announced");
+        let msg =
+            Message::from_hashed_data::<secp256k1_zkp::hashes::sha256::Hash>(outcome.as_bytes());
+        let attestation = OracleAttestation {
+            oracle_public_key: self.public_key(),
+            signatures: vec![dlc::secp_utils::schnorrsig_sign_with_nonce(
+                &self.secp,
+                &msg,
+                &self.key_pair,
+                nonce.as_ref(),
+            )],
+            outcomes: vec![outcome.to_string()],
+        };
+        self.attestations
+            .lock()
+            .expect("to lock the attestations")
+            .insert(event_id.to_string(), attestation);
+    }
+}
+
+impl AsyncOracle for TestOracle {
+    async fn get_public_key(&self) -> XOnlyPublicKey {
+        self.public_key()
+    }
+
+    async fn get_announcement(&self, event_id: &str) -> Result<OracleAnnouncement, Error> {
+        self.announcements
+            .lock()
+            .expect("to lock the announcements")
+            .get(event_id)
+            .map(|(announcement, _)| announcement.clone())
+            .ok_or_else(|| Error::OracleError(format!("Unknown event {}", event_id)))
+    }
+
+    async fn get_attestation(&self, event_id: &str) -> Result<OracleAttestation, Error> {
+        self.attestations
+            .lock()
+            .expect("to lock the attestations")
+            .get(event_id)
+            .cloned()
+            .ok_or_else(|| Error::OracleError(format!("Event {} not attested", event_id)))
+    }
+}
+
+/// A clock set by the tests.
+pub struct TestTime {
+    now: Mutex<u64>,
+}
+
+impl TestTime {
+    pub fn set(&self, now: u64) {
+        *self.now.lock().expect("to lock the time") = now;
+    }
+}
+
+impl Time for TestTime {
+    fn unix_time_now(&self) -> u64 {
+        *self.now.lock().expect("to lock the time")
+    }
+}
+
+pub type TestManager =
+    Manager<Arc<TestWallet>, Arc<TestChain>, Arc<MemoryStorage>, Arc<TestOracle>, Arc<TestTime>>;
+
+fn new_manager(
+    wallet: &Arc<TestWallet>,
+    chain: &Arc<TestChain>,
+    store: &Arc<MemoryStorage>,
+    oracle: &Arc<TestOracle>,
+    time: &Arc<TestTime>,
+    config: ManagerConfig,
+) -> TestManager {
+    let oracles = HashMap::from([(oracle.public_key(), oracle.clone())]);
+    Manager::new(
+        wallet.clone(),
+        chain.clone(),
+        store.clone(),
+        Some(oracles),
+        time.clone(),
+        config,
+    )
+    .expect("a valid manager config")
+}
+
+/// One side of a contract.
+pub struct Party {
+    pub manager: TestManager,
+    pub node_id: PublicKey,
+    pub wallet: Arc<TestWallet>,
+    pub store: Arc<MemoryStorage>,
+}
+
+impl Party {
+    fn new(
+        chain: &Arc<TestChain>,
+        oracle: &Arc<TestOracle>,
+        time: &Arc<TestTime>,
+        config: ManagerConfig,
+    ) -> Self {
+        let secp = Secp256k1::new();
+        let wallet = Arc::new(TestWallet::new(chain, WALLET_FUNDS));
+        let store = Arc::new(MemoryStorage::default());
+        Party {
+            manager: new_manager(&wallet, chain, &store, oracle, time, config),
+            node_id: PublicKey::from_secret_key(&secp, &SecretKey::new(&mut thread_rng())),
+            wallet,
+            store,
+        }
+    }
+
+    pub async fn contract(&self, id: &ContractId) -> Contract {
+        self.store
+            .get_contract(id)
+            .await
+            .expect("the storage to be available")
+            .expect("the contract to be stored")
+    }
+
+    pub async fn state(&self, id: &ContractId) -> ContractState {
+        ContractState::from(&self.contract(id).await)
+    }
+
+    pub async fn metadata(&self, id: &ContractId) -> ContractMetadata {
+        self.store
+            .get_contract_metadata(id)
+            .await
+            .expect("the storage to be available")
+            .unwrap_or_default()
+    }
+
+    /// Runs `periodic_check` and returns the contracts it reported.
+    pub async fn check(&self) -> Vec<ContractId> {
+        self.manager
+            .periodic_check()
+            .await
+            .expect("the periodic check to succeed")
+            .into_iter()
+            .map(|(id, _)| id)
+            .collect()
+    }
+}
+
+/// An offering party, modelled after the router wallet which puts up no
+/// collateral, and an accepting party funding the contract.
+pub struct TestEnv {
+    pub chain: Arc<TestChain>,
+    pub oracle: Arc<TestOracle>,
+    pub time: Arc<TestTime>,
+    pub offerer: Party,
+    pub acceptor: Party,
+}
+
+impl TestEnv {
+    pub fn new() -> Self {
+        Self::with_config(ManagerConfig::default())
+    }
+
+    /// Creates an environment where both parties use `config`.
+    pub fn with_config(config: ManagerConfig) -> Self {
+        let chain = Arc::new(TestChain::default());
+        let oracle = Arc::new(TestOracle::new());
+        let time = Arc::new(TestTime {
+            now: Mutex::new(START_TIME),
+        });
+        let offerer = Party::new(&chain, &oracle, &time, config.clone());
+        let acceptor = Party::new(&chain, &oracle, &time, config);
+        TestEnv {
+            chain,
+            oracle,
+            time,
+            offerer,
+            acceptor,
+        }
+    }
+
+    /// Announces `event_id` and builds a contract on it where the winner of
+    /// the outcome gets all the collateral.
+    pub fn contract_input(&self, event_id: &str) -> ContractInput {
+        self.oracle.announce(
+            event_id,
+            (START_TIME as u32) + ONE_DAY_IN_SECONDS,
+            &[OUTCOME_OFFER_WINS, OUTCOME_ACCEPT_WINS],
+        );
+        ContractInput {
+            offer_collateral: 0,
+            accept_collateral: ACCEPT_COLLATERAL,
+            fee_rate: 2,
+            contract_infos: vec![ContractInputInfo {
+                contract_descriptor: ContractDescriptor::Enum(EnumDescriptor {
+                    outcome_payouts: vec![
+                        EnumerationPayout {
+                            outcome: OUTCOME_OFFER_WINS.to_string(),
+                            payout: Payout {
+                                offer: ACCEPT_COLLATERAL,
+                                accept: 0,
+                            },
+                        },
+                        EnumerationPayout {
+                            outcome: OUTCOME_ACCEPT_WINS.to_string(),
+                            payout: Payout {
+                                offer: 0,
+                                accept: ACCEPT_COLLATERAL,
+                            },
+                        },
+                    ],
+                }),
+                oracles: OracleInput {
+                    public_keys: vec![self.oracle.public_key()],
+                    event_id: event_id.to_string(),
+                    threshold: 1,
+                },
+            }],
+        }
+    }
+
+    /// Another manager working on the contracts of `party`, sharing its
+    /// wallet and storage the way replicas of the router wallet do.
+    pub fn replica(&self, party: &Party, config: ManagerConfig) -> Party {
+        Party {
+            manager: new_manager(
+                &party.wallet,
+                &self.chain,
+                &party.store,
+                &self.oracle,
+                &self.time,
+                config,
+            ),
+            node_id: party.node_id,
+            wallet: party.wallet.clone(),
+            store: party.store.clone(),
+        }
+    }
+
+    /// Sends an offer on `event_id` to the acceptor, who stores it.
+    pub async fn offer_contract(&self, event_id: &str) -> OfferDlc {
+        let offer = self
+            .offerer
+            .manager
+            .send_offer(
+                &self.contract_input(event_id),
+                self.acceptor.node_id,
+                ONE_DAY_IN_SECONDS,
+                0,
+                self.offerer.wallet.address().clone(),
+            )
+            .await
+            .expect("to send the offer");
+
+        self.acceptor
+            .manager
+            .on_dlc_message(&DlcMessage::Offer(offer.clone()), self.offerer.node_id)
+            .await
+            .expect("the offer to be valid");
+        offer
+    }
+
+    /// Has the acceptor accept an offer it received, returning the id of the
+    /// contract and the accept message for the offerer.
+    pub async fn accept_offer(&self, offer: &OfferDlc) -> (ContractId, AcceptDlc) {
+        let (contract_id, _, accept) = self
+            .acceptor
+            .manager
+            .accept_contract_offer(&offer.temporary_contract_id)
+            .await
+            .expect("to accept the offer");
+        (contract_id, accept)
+    }
+
+    /// Goes through offer, accept and sign, leaving the funding transaction
+    /// in the mempool. Returns the id of the contract.
+    pub async fn sign_contract(&self, event_id: &str) -> ContractId {
+        let offer = self.offer_contract(event_id).await;
+        let (contract_id, accept) = self.accept_offer(&offer).await;
+
+        let sign = self
+            .offerer
+            .manager
+            .on_dlc_message(&DlcMessage::Accept(accept), self.acceptor.node_id)
+            .await
+            .expect("the accept to be valid")
+            .expect("a sign message");
+        self.acceptor
+            .manager
+            .on_dlc_message(&sign, self.offerer.node_id)
+            .await
+            .expect("the sign to be valid");
+
+        contract_id
+    }
+}
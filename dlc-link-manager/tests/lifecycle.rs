@@ -0,0 +1,774 @@
+#![feature(async_fn_in_trait)]
+
+mod common;
+
+use bitcoin::{PackedLockTime, Script, Sequence, Transaction, TxIn, TxOut, Witness};
+use common::{TestEnv, ACCEPT_COLLATERAL, OUTCOME_ACCEPT_WINS, OUTCOME_OFFER_WINS, START_TIME};
+use dlc_link_manager::{
+    AsyncStorage, ContractLeaseConfig, ContractState, FeeBumpConfig, ManagerConfig,
+    NB_CONFIRMATIONS,
+};
+use dlc_manager::contract::Contract;
+use dlc_manager::{Blockchain, ContractId};
+use dlc_messages::Message as DlcMessage;
+use futures::executor::block_on;
+use secp256k1_zkp::rand::thread_rng;
+use secp256k1_zkp::{Message, Secp256k1, SecretKey};
+use std::time::Duration;
+
+#[test]
+fn contract_closes_with_attested_outcome() {
+    block_on(async {
+        let env = TestEnv::new();
+        let mut events = env.offerer.manager.subscribe();
+        let contract_id = env.sign_contract("btc-usd-close").await;
+        assert_eq!(env.offerer.state(&contract_id).await, ContractState::Signed);
+        assert_eq!(
+            env.acceptor.state(&contract_id).await,
+            ContractState::Signed
+        );
+
+        env.chain.mine(NB_CONFIRMATIONS as usize - 1);
+        assert!(env.offerer.check().await.is_empty());
+        env.chain.mine(1);
+        assert_eq!(env.offerer.check().await, vec![contract_id]);
+        assert_eq!(env.acceptor.check().await, vec![contract_id]);
+        assert_eq!(
+            env.offerer.state(&contract_id).await,
+            ContractState::Confirmed
+        );
+
+        // Nothing happens until the oracle attests.
+        assert!(env.offerer.check().await.is_empty());
+        env.oracle.attest("btc-usd-close", OUTCOME_ACCEPT_WINS);
+        assert_eq!(env.offerer.check().await, vec![contract_id]);
+        assert_eq!(
+            env.offerer.state(&contract_id).await,
+            ContractState::PreClosed
+        );
+
+        // The acceptor finds the CET broadcast by the offerer.
+        assert_eq!(env.acceptor.check().await, vec![contract_id]);
+        let cet_txid = match env.acceptor.contract(&contract_id).await {
+            Contract::PreClosed(p) => p.signed_cet.txid(),
+            c => panic!("Unexpected contract {:?}", c),
+        };
+
+        env.chain.mine(NB_CONFIRMATIONS as usize);
+        assert_eq!(env.offerer.check().await, vec![contract_id]);
+        assert_eq!(env.acceptor.check().await, vec![contract_id]);
+        for party in [&env.offerer, &env.acceptor] {
+            match party.contract(&contract_id).await {
+                Contract::Closed(c) => {
+                    assert_eq!(c.signed_cet.map(|cet| cet.txid()), Some(cet_txid))
+                }
+                c => panic!("Unexpected contract {:?}", c),
+            }
+        }
+        assert!(env.chain.confirmations(&cet_txid) >= NB_CONFIRMATIONS);
+
+        let mut states = Vec::new();
+        while let Ok(Some(event)) = events.try_next() {
+            states.push(event.to);
+        }
+        assert_eq!(
+            states,
+            vec![
+                ContractState::Offered,
+                ContractState::Signed,
+                ContractState::Confirmed,
+                ContractState::PreClosed,
+                ContractState::Closed,
+            ]
+        );
+    });
+}
+
+#[test]
+fn subscribers_receive_the_transitions_made_after_they_subscribed() {
+    block_on(async {
+        let env = TestEnv::new();
+        let mut acceptor_events = env.acceptor.manager.subscribe();
+        let dropped = env.offerer.manager.subscribe();
+        drop(dropped);
+        let contract_id = env.sign_contract("btc-usd-events").await;
+
+        let mut transitions = Vec::new();
+        while let Ok(Some(event)) = acceptor_events.try_next() {
+            assert_eq!(event.oracle_event_id.as_deref(), Some("btc-usd-events"));
+            assert_eq!(event.oracle_event_ids, vec!["btc-usd-events".to_string()]);
+            transitions.push((event.from, event.to));
+        }
+        assert_eq!(
+            transitions,
+            vec![
+                (None, ContractState::Offered),
+                (Some(ContractState::Offered), ContractState::Accepted),
+                (Some(ContractState::Accepted), ContractState::Signed),
+            ]
+        );
+
+        // A subscriber only gets the transitions from its subscription on,
+        // and one whose receiver was dropped doesn't stop the others.
+        let mut offerer_events = env.offerer.manager.subscribe();
+        env.chain.mine(NB_CONFIRMATIONS as usize);
+        assert_eq!(env.offerer.check().await, vec![contract_id]);
+        let event = offerer_events
+            .try_next()
+            .expect("an event")
+            .expect("an open channel");
+        assert_eq!(event.contract_id, contract_id);
+        assert_eq!(event.from, Some(ContractState::Signed));
+        assert_eq!(event.to, ContractState::Confirmed);
+        assert!(event.fund_txid.is_some());
+        assert!(!event.after_reorg);
+        assert!(offerer_events.try_next().is_err());
+    });
+}
+
+#[test]
+fn contract_is_refunded_without_attestation() {
+    block_on(async {
+        let env = TestEnv::new();
+        let contract_id = env.sign_contract("btc-usd-refund").await;
+        env.chain.mine(NB_CONFIRMATIONS as usize);
+        env.offerer.check().await;
+        env.acceptor.check().await;
+
+        let refund = match env.offerer.contract(&contract_id).await {
+            Contract::Confirmed(c) => c.accepted_contract.dlc_transactions.refund,
+            c => panic!("Unexpected contract {:?}", c),
+        };
+        env.time.set(refund.lock_time.0 as u64 + 1);
+        assert!(env.offerer.check().await.is_empty());
+        assert!(env
+            .offerer
+            .metadata(&contract_id)
+            .await
+            .refund_broadcast
+            .is_some());
+
+        env.chain.mine(NB_CONFIRMATIONS as usize);
+        assert_eq!(env.offerer.check().await, vec![contract_id]);
+        assert_eq!(env.acceptor.check().await, vec![contract_id]);
+        assert_eq!(
+            env.offerer.state(&contract_id).await,
+            ContractState::Refunded
+        );
+        assert_eq!(
+            env.acceptor.state(&contract_id).await,
+            ContractState::Refunded
+        );
+
+        // Attesting after the refund changes nothing.
+        env.oracle.attest("btc-usd-refund", OUTCOME_OFFER_WINS);
+        assert!(env.offerer.check().await.is_empty());
+        assert_eq!(
+            env.offerer.state(&contract_id).await,
+            ContractState::Refunded
+        );
+    });
+}
+
+#[test]
+fn refund_is_broadcast_again_only_once_dropped_from_the_mempool() {
+    block_on(async {
+        let env = TestEnv::new();
+        let contract_id = env.sign_contract("btc-usd-refund-drop").await;
+        env.chain.mine(NB_CONFIRMATIONS as usize);
+        env.offerer.check().await;
+
+        let refund = match env.offerer.contract(&contract_id).await {
+            Contract::Confirmed(c) => c.accepted_contract.dlc_transactions.refund,
+            c => panic!("Unexpected contract {:?}", c),
+        };
+        env.time.set(refund.lock_time.0 as u64 + 1);
+        env.offerer.check().await;
+        assert_eq!(refund_broadcast_count(&env, &contract_id).await, 1);
+
+        // A failed lookup is not mistaken for a dropped refund.
+        env.chain.evict(&refund.txid());
+        env.chain.set_unavailable(true);
+        env.offerer.check().await;
+        assert_eq!(refund_broadcast_count(&env, &contract_id).await, 1);
+
+        env.chain.set_unavailable(false);
+        env.offerer.check().await;
+        assert_eq!(refund_broadcast_count(&env, &contract_id).await, 2);
+        env.chain.mine(NB_CONFIRMATIONS as usize);
+        assert_eq!(env.offerer.check().await, vec![contract_id]);
+        assert_eq!(
+            env.offerer.state(&contract_id).await,
+            ContractState::Refunded
+        );
+    });
+}
+
+#[test]
+fn refund_confirmed_before_the_check_is_not_counted_as_broadcast() {
+    block_on(async {
+        let env = TestEnv::new();
+        let contract_id = env.sign_contract("btc-usd-refund-peer").await;
+        env.chain.mine(NB_CONFIRMATIONS as usize);
+        env.offerer.check().await;
+        env.acceptor.check().await;
+
+        let refund = match env.offerer.contract(&contract_id).await {
+            Contract::Confirmed(c) => c.accepted_contract.dlc_transactions.refund,
+            c => panic!("Unexpected contract {:?}", c),
+        };
+        env.time.set(refund.lock_time.0 as u64 + 1);
+        // The acceptor broadcasts the refund, mined before the offerer checks.
+        env.acceptor.check().await;
+        env.chain.mine(1);
+        env.offerer.check().await;
+        let refund_broadcast = env
+            .offerer
+            .metadata(&contract_id)
+            .await
+            .refund_broadcast
+            .expect("a refund broadcast");
+        assert_eq!(refund_broadcast.txid, refund.txid());
+        assert_eq!(refund_broadcast.broadcast_count, 0);
+    });
+}
+
+#[test]
+fn cet_broadcast_by_the_counterparty_is_detected() {
+    block_on(async {
+        let env = TestEnv::new();
+        let contract_id = env.sign_contract("btc-usd-peer-cet").await;
+        env.chain.mine(NB_CONFIRMATIONS as usize);
+        env.offerer.check().await;
+        env.acceptor.check().await;
+
+        env.oracle.attest("btc-usd-peer-cet", OUTCOME_OFFER_WINS);
+        assert_eq!(env.offerer.check().await, vec![contract_id]);
+        let cet_txid = match env.offerer.contract(&contract_id).await {
+            Contract::PreClosed(p) => p.signed_cet.txid(),
+            c => panic!("Unexpected contract {:?}", c),
+        };
+
+        // The acceptor finds the CET spending the funding output before
+        // looking at the attestations itself.
+        assert_eq!(env.acceptor.check().await, vec![contract_id]);
+        match env.acceptor.contract(&contract_id).await {
+            Contract::PreClosed(p) => {
+                assert_eq!(p.signed_cet.txid(), cet_txid);
+                assert!(p.attestations.is_none());
+            }
+            c => panic!("Unexpected contract {:?}", c),
+        }
+        assert!(env
+            .acceptor
+            .metadata(&contract_id)
+            .await
+            .suspected_fraud
+            .is_none());
+    });
+}
+
+#[test]
+fn unknown_spend_of_the_funding_output_is_flagged_once() {
+    block_on(async {
+        let env = TestEnv::new();
+        let contract_id = env.sign_contract("btc-usd-fraud").await;
+        env.chain.mine(NB_CONFIRMATIONS as usize);
+        env.offerer.check().await;
+
+        let fund_outpoint = match env.offerer.contract(&contract_id).await {
+            Contract::Confirmed(c) => c.accepted_contract.dlc_transactions.get_fund_outpoint(),
+            c => panic!("Unexpected contract {:?}", c),
+        };
+        let spend = Transaction {
+            version: 2,
+            lock_time: PackedLockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: fund_outpoint,
+                script_sig: Script::new(),
+                sequence: Sequence::MAX,
+                witness: Witness::new(),
+            }],
+            output: vec![TxOut {
+                value: ACCEPT_COLLATERAL / 2,
+                script_pubkey: Script::new(),
+            }],
+        };
+        env.chain
+            .send_transaction(&spend)
+            .expect("to broadcast the spend");
+        env.chain.mine(1);
+
+        assert!(env.offerer.check().await.is_empty());
+        let suspected_fraud = env
+            .offerer
+            .metadata(&contract_id)
+            .await
+            .suspected_fraud
+            .expect("a suspected fraud");
+        assert_eq!(suspected_fraud.spending_txid, spend.txid());
+        assert_eq!(suspected_fraud.detected_at, START_TIME);
+        assert_eq!(
+            env.offerer.state(&contract_id).await,
+            ContractState::Confirmed
+        );
+
+        // Later checks keep the time of the first detection.
+        env.time.set(START_TIME + 600);
+        assert!(env.offerer.check().await.is_empty());
+        assert_eq!(
+            env.offerer
+                .metadata(&contract_id)
+                .await
+                .suspected_fraud
+                .map(|fraud| fraud.detected_at),
+            Some(START_TIME)
+        );
+    });
+}
+
+#[test]
+fn reorganised_funding_moves_contract_back_to_signed() {
+    block_on(async {
+        let env = TestEnv::new();
+        let contract_id = env.sign_contract("btc-usd-reorg").await;
+        env.chain.mine(NB_CONFIRMATIONS as usize);
+        env.offerer.check().await;
+        let anchor = env.offerer.metadata(&contract_id).await.funding_anchor;
+        assert!(anchor.is_some());
+
+        env.chain.reorg(NB_CONFIRMATIONS as usize);
+        assert!(env.offerer.check().await.is_empty());
+        assert_eq!(env.offerer.state(&contract_id).await, ContractState::Signed);
+        assert!(env
+            .offerer
+            .metadata(&contract_id)
+            .await
+            .funding_anchor
+            .is_none());
+
+        env.chain.mine(NB_CONFIRMATIONS as usize);
+        assert_eq!(env.offerer.check().await, vec![contract_id]);
+        let new_anchor = env.offerer.metadata(&contract_id).await.funding_anchor;
+        assert!(new_anchor.is_some());
+        assert_ne!(new_anchor, anchor);
+    });
+}
+
+#[test]
+fn acceptor_broadcasts_the_funding_transaction() {
+    block_on(async {
+        let env = TestEnv::new();
+        let contract_id = env.sign_contract("btc-usd-funding").await;
+        let dlc_transactions = match env.acceptor.contract(&contract_id).await {
+            Contract::Signed(c) => c.accepted_contract.dlc_transactions,
+            c => panic!("Unexpected contract {:?}", c),
+        };
+        let fund = &dlc_transactions.fund;
+        let fund_outpoint = dlc_transactions.get_fund_outpoint();
+
+        assert_eq!(env.chain.confirmations(&fund.txid()), 0);
+        assert_eq!(
+            env.chain.spending_txid(&fund.input[0].previous_output),
+            Some(fund.txid())
+        );
+        assert!(fund.output[fund_outpoint.vout as usize].value >= ACCEPT_COLLATERAL);
+        assert_eq!(env.chain.spending_txid(&fund_outpoint), None);
+    });
+}
+
+#[test]
+fn contract_details_are_found_by_event_id_once_closed() {
+    block_on(async {
+        let env = TestEnv::new();
+        let manager = &env.offerer.manager;
+        let contract_id = env.sign_contract("btc-usd-details").await;
+        env.chain.mine(NB_CONFIRMATIONS as usize);
+        env.offerer.check().await;
+
+        let confirmed = manager
+            .find_contract_by_event_id("btc-usd-details")
+            .await
+            .expect("the storage to be available")
+            .expect("the contract to be found");
+        let details = manager
+            .get_contract_details(&confirmed)
+            .await
+            .expect("the details of the contract");
+        assert_eq!(details.state, ContractState::Confirmed);
+        assert_eq!(details.accept_collateral, Some(ACCEPT_COLLATERAL));
+        assert!(details.funding.and_then(|f| f.confirmations) >= Some(NB_CONFIRMATIONS));
+        assert!(details.oracles.iter().all(|o| !o.attested));
+
+        env.oracle.attest("btc-usd-details", OUTCOME_ACCEPT_WINS);
+        env.offerer.check().await;
+        env.chain.mine(NB_CONFIRMATIONS as usize);
+        env.offerer.check().await;
+
+        // Closed contracts no longer hold their offer, the details come from
+        // the summary recorded on closing.
+        let closed = manager
+            .find_contract_by_event_id("btc-usd-details")
+            .await
+            .expect("the storage to be available")
+            .expect("the contract to be found");
+        assert_eq!(closed.get_id(), contract_id);
+        let details = manager
+            .get_contract_details(&closed)
+            .await
+            .expect("the details of the contract");
+        assert_eq!(details.state, ContractState::Closed);
+        assert_eq!(
+            details.oracle_event_ids,
+            vec!["btc-usd-details".to_string()]
+        );
+        assert_eq!(details.accept_collateral, Some(ACCEPT_COLLATERAL));
+        assert!(details.cet.and_then(|c| c.confirmations) >= Some(NB_CONFIRMATIONS));
+        assert!(details.oracles.iter().all(|o| o.attested));
+        assert!(details.pnl.is_some());
+    });
+}
+
+#[test]
+fn contracts_missing_from_the_event_index_are_indexed_again() {
+    block_on(async {
+        let env = TestEnv::new();
+        let manager = &env.offerer.manager;
+        let contract_id = env.sign_contract("btc-usd-index").await;
+        env.offerer.store.clear_event_index();
+        assert!(manager
+            .find_contract_by_event_id("btc-usd-index")
+            .await
+            .expect("the storage to be available")
+            .is_none());
+
+        assert_eq!(
+            manager
+                .index_contract_event_ids()
+                .await
+                .expect("the contracts to be indexed"),
+            1
+        );
+        let contract = manager
+            .find_contract_by_event_id("btc-usd-index")
+            .await
+            .expect("the storage to be available")
+            .expect("the contract to be found");
+        assert_eq!(contract.get_id(), contract_id);
+        assert_eq!(
+            manager
+                .index_contract_event_ids()
+                .await
+                .expect("the contracts to be indexed"),
+            0
+        );
+    });
+}
+
+#[test]
+fn events_stay_in_the_journal_until_removed() {
+    block_on(async {
+        let env = TestEnv::with_config(ManagerConfig {
+            journal_events: true,
+            ..Default::default()
+        });
+        let store = &env.offerer.store;
+        let contract_id = env.sign_contract("btc-usd-journal").await;
+        env.chain.mine(NB_CONFIRMATIONS as usize);
+        env.offerer.check().await;
+
+        let journaled = store
+            .get_journaled_contract_events()
+            .await
+            .expect("the journal to be readable");
+        let funded = journaled
+            .iter()
+            .find(|e| e.from == Some(ContractState::Signed) && e.to == ContractState::Confirmed)
+            .expect("the funding to be journaled");
+        assert_eq!(funded.contract_id, contract_id);
+        assert!(journaled
+            .iter()
+            .any(|e| e.from.is_none() && e.to == ContractState::Offered));
+
+        for event in &journaled {
+            store
+                .remove_journaled_contract_event(event)
+                .await
+                .expect("the event to be removed");
+        }
+        assert!(store
+            .get_journaled_contract_events()
+            .await
+            .expect("the journal to be readable")
+            .is_empty());
+    });
+}
+
+#[test]
+fn unsigned_offer_expires() {
+    block_on(async {
+        let env = TestEnv::with_config(ManagerConfig {
+            offer_ttl: Some(3600),
+            ..Default::default()
+        });
+        let offer = env.offer_contract("btc-usd-expiry").await;
+        let offer_id = offer.temporary_contract_id;
+
+        env.time.set(START_TIME + 3599);
+        assert!(env.offerer.check().await.is_empty());
+        assert_eq!(env.offerer.state(&offer_id).await, ContractState::Offered);
+
+        env.time.set(START_TIME + 3600);
+        assert_eq!(env.offerer.check().await, vec![offer_id]);
+        assert_eq!(env.acceptor.check().await, vec![offer_id]);
+        for party in [&env.offerer, &env.acceptor] {
+            assert_eq!(party.state(&offer_id).await, ContractState::Rejected);
+            assert_eq!(
+                party.metadata(&offer_id).await.expired_at,
+                Some(START_TIME + 3600)
+            );
+        }
+        assert!(env.offerer.check().await.is_empty());
+    });
+}
+
+#[test]
+fn accepted_but_unsigned_offer_expires_from_the_offer_time() {
+    block_on(async {
+        let env = TestEnv::with_config(ManagerConfig {
+            offer_ttl: Some(3600),
+            ..Default::default()
+        });
+        let offer = env.offer_contract("btc-usd-accepted-expiry").await;
+        let offer_id = offer.temporary_contract_id;
+
+        // The accept never reaches the offerer.
+        env.time.set(START_TIME + 3000);
+        let (contract_id, _) = env.accept_offer(&offer).await;
+        assert_eq!(
+            env.acceptor.state(&contract_id).await,
+            ContractState::Accepted
+        );
+        assert_eq!(
+            env.acceptor.metadata(&contract_id).await.offered_at,
+            Some(START_TIME)
+        );
+
+        env.time.set(START_TIME + 3599);
+        assert!(env.acceptor.check().await.is_empty());
+        env.time.set(START_TIME + 3600);
+        assert_eq!(env.acceptor.check().await, vec![offer_id]);
+        assert_eq!(env.acceptor.state(&offer_id).await, ContractState::Rejected);
+        assert_eq!(
+            env.acceptor.metadata(&offer_id).await.expired_at,
+            Some(START_TIME + 3600)
+        );
+    });
+}
+
+#[test]
+fn failed_accept_is_retried_up_to_the_maximum() {
+    block_on(async {
+        let env = TestEnv::with_config(ManagerConfig {
+            max_retries: 2,
+            ..Default::default()
+        });
+        let offer = env.offer_contract("btc-usd-retry").await;
+        let offer_id = offer.temporary_contract_id;
+        let (_, mut accept) = env.accept_offer(&offer).await;
+
+        // A refund signature from another key fails the verification.
+        let secp = Secp256k1::new();
+        accept.refund_signature = secp.sign_ecdsa(
+            &Message::from_slice(&[1; 32]).expect("a valid message"),
+            &SecretKey::new(&mut thread_rng()),
+        );
+        assert!(env
+            .offerer
+            .manager
+            .on_dlc_message(&DlcMessage::Accept(accept), env.acceptor.node_id)
+            .await
+            .is_err());
+        assert_eq!(
+            env.offerer.state(&offer_id).await,
+            ContractState::FailedAccept
+        );
+
+        for _ in 0..2 {
+            assert!(env
+                .offerer
+                .manager
+                .retry_failed_contract(&offer_id)
+                .await
+                .is_err());
+        }
+        let metadata = env.offerer.metadata(&offer_id).await;
+        assert_eq!(metadata.retry_count(), 2);
+        assert!(metadata.retries.iter().all(|retry| retry.error.is_some()));
+
+        let rejected = env.offerer.manager.retry_failed_contract(&offer_id).await;
+        assert!(matches!(rejected, Err(e) if e.to_string().contains("the maximum")));
+        assert_eq!(env.offerer.metadata(&offer_id).await.retry_count(), 2);
+        assert_eq!(
+            env.offerer.state(&offer_id).await,
+            ContractState::FailedAccept
+        );
+    });
+}
+
+#[test]
+fn stuck_funding_transaction_fee_is_bumped_again_while_stuck() {
+    block_on(async {
+        let env = TestEnv::with_config(ManagerConfig {
+            fee_bump: Some(FeeBumpConfig {
+                stuck_after: 600,
+                target_fee_rate: 20,
+            }),
+            ..Default::default()
+        });
+        let contract_id = env.sign_contract("btc-usd-cpfp").await;
+        let fund = match env.acceptor.contract(&contract_id).await {
+            Contract::Signed(c) => c.accepted_contract.dlc_transactions.fund,
+            c => panic!("Unexpected contract {:?}", c),
+        };
+        let change_vout = fund
+            .output
+            .iter()
+            .position(|o| o.script_pubkey == env.acceptor.wallet.address().script_pubkey())
+            .expect("a change output of the acceptor");
+        let change = bitcoin::OutPoint {
+            txid: fund.txid(),
+            vout: change_vout as u32,
+        };
+
+        // The first check only records when the transaction was seen.
+        env.acceptor.check().await;
+        env.time.set(START_TIME + 599);
+        env.acceptor.check().await;
+        assert!(env
+            .acceptor
+            .metadata(&contract_id)
+            .await
+            .fee_bumps
+            .is_empty());
+
+        env.time.set(START_TIME + 600);
+        env.acceptor.check().await;
+        let fee_bumps = env.acceptor.metadata(&contract_id).await.fee_bumps;
+        assert_eq!(fee_bumps.len(), 1);
+        assert_eq!(fee_bumps[0].parent_txid, fund.txid());
+        assert_eq!(fee_bumps[0].fee_rate, 20);
+        assert_eq!(
+            env.chain.spending_txid(&change),
+            Some(fee_bumps[0].child_txid)
+        );
+
+        // Still stuck as long after the bump, a child paying more replaces
+        // the first one.
+        env.time.set(START_TIME + 1199);
+        env.acceptor.check().await;
+        assert_eq!(env.acceptor.metadata(&contract_id).await.fee_bumps.len(), 1);
+        env.time.set(START_TIME + 1200);
+        env.acceptor.check().await;
+        let fee_bumps = env.acceptor.metadata(&contract_id).await.fee_bumps;
+        assert_eq!(fee_bumps.len(), 2);
+        assert_eq!(fee_bumps[1].fee_rate, 40);
+        assert!(fee_bumps[1].fee > fee_bumps[0].fee);
+        assert_eq!(
+            env.chain.spending_txid(&change),
+            Some(fee_bumps[1].child_txid)
+        );
+
+        env.chain.mine(NB_CONFIRMATIONS as usize);
+        assert_eq!(env.acceptor.check().await, vec![contract_id]);
+        assert_eq!(env.acceptor.metadata(&contract_id).await.fee_bumps.len(), 2);
+    });
+}
+
+#[test]
+fn contract_leased_by_another_replica_is_skipped_until_the_lease_expires() {
+    block_on(async {
+        let lease_config = |owner: &str| ManagerConfig {
+            contract_lease: Some(ContractLeaseConfig {
+                owner: owner.to_string(),
+                duration: 60,
+            }),
+            ..Default::default()
+        };
+        let env = TestEnv::with_config(lease_config("replica-a"));
+        let replica = env.replica(&env.offerer, lease_config("replica-b"));
+        let contract_id = env.sign_contract("btc-usd-lease").await;
+        env.chain.mine(NB_CONFIRMATIONS as usize);
+
+        // The first replica stopped while holding the lease.
+        assert!(env
+            .offerer
+            .store
+            .acquire_contract_lease(&contract_id, "replica-a", START_TIME, START_TIME + 60)
+            .await
+            .expect("the storage to be available"));
+        assert!(replica.check().await.is_empty());
+        assert_eq!(replica.state(&contract_id).await, ContractState::Signed);
+
+        env.time.set(START_TIME + 60);
+        assert_eq!(replica.check().await, vec![contract_id]);
+        assert_eq!(replica.state(&contract_id).await, ContractState::Confirmed);
+
+        // The lease was released once the check was done.
+        assert!(env
+            .offerer
+            .store
+            .acquire_contract_lease(&contract_id, "replica-a", START_TIME + 60, START_TIME + 120)
+            .await
+            .expect("the storage to be available"));
+    });
+}
+
+#[test]
+fn timed_out_check_releases_the_contract_lease() {
+    block_on(async {
+        let env = TestEnv::with_config(ManagerConfig {
+            contract_lease: Some(ContractLeaseConfig {
+                owner: "replica-a".to_string(),
+                duration: 60,
+            }),
+            contract_check_timeout: Some(Duration::from_millis(50)),
+            ..Default::default()
+        });
+        let contract_id = env.sign_contract("btc-usd-timeout").await;
+        env.chain.mine(NB_CONFIRMATIONS as usize);
+
+        env.chain.set_stalled(true);
+        assert!(env.offerer.check().await.is_empty());
+        assert_eq!(env.offerer.state(&contract_id).await, ContractState::Signed);
+
+        // Neither the lease nor the lock outlive the timed out check.
+        assert!(env
+            .offerer
+            .store
+            .acquire_contract_lease(&contract_id, "replica-b", START_TIME, START_TIME + 60)
+            .await
+            .expect("the storage to be available"));
+        env.offerer
+            .store
+            .release_contract_lease(&contract_id, "replica-b")
+            .await
+            .expect("the storage to be available");
+
+        env.chain.set_stalled(false);
+        assert_eq!(env.offerer.check().await, vec![contract_id]);
+        assert_eq!(
+            env.offerer.state(&contract_id).await,
+            ContractState::Confirmed
+        );
+    });
+}
+
+async fn refund_broadcast_count(env: &TestEnv, contract_id: &ContractId) -> u32 {
+    env.offerer
+        .metadata(contract_id)
+        .await
+        .refund_broadcast
+        .expect("a refund broadcast")
+        .broadcast_count
+}
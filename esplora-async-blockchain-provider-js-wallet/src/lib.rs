@@ -5,13 +5,13 @@
 use bdk::esplora_client::TxStatus;
 use bdk::esplora_client::{AsyncClient, Builder};
 use bitcoin::consensus::Decodable;
-use bitcoin::{Address, Block, Network, OutPoint, Script, Transaction, TxOut, Txid};
-use dlc_link_manager::AsyncBlockchain;
+use bitcoin::{Address, Block, BlockHash, Network, OutPoint, Script, Transaction, TxOut, Txid};
+use dlc_link_manager::{estimate_sat_per_1000_weight, AsyncBlockchain, FeeRateBounds};
 use dlc_manager::{error::Error, Blockchain, Utxo};
 
 use js_interface_wallet::WalletBlockchainProvider;
-use lightning::chain::chaininterface::FeeEstimator;
-use reqwest::Response;
+use lightning::chain::chaininterface::{ConfirmationTarget, FeeEstimator};
+use reqwest::{Response, StatusCode};
 
 use serde::{Deserialize, Serialize};
 
@@ -54,11 +54,29 @@ struct UTXOSpent {
     spent: bool,
 }
 
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct OutspendResp {
+    spent: bool,
+    txid: Option<Txid>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct BlockResp {
+    mediantime: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct BlockStatusResp {
+    in_best_chain: bool,
+}
+
 pub struct EsploraAsyncBlockchainProviderJsWallet {
     host: String,
     pub blockchain: EsploraBlockchain,
     chain_data: Arc<Mutex<ChainCacheData>>,
     network: Network,
+    // Bounds of the rates returned as a `FeeEstimator`
+    fee_bounds: FeeRateBounds,
 }
 
 #[derive(Debug)]
@@ -66,6 +84,8 @@ struct ChainCacheData {
     utxos: RefCell<Option<Vec<Utxo>>>,
     txs: RefCell<Option<HashMap<String, Transaction>>>,
     height: RefCell<Option<u64>>,
+    // Fee rates by confirmation target, in sats/vbyte
+    fee_estimates: RefCell<HashMap<u16, f64>>,
 }
 
 impl PartialEq for ChainCacheData {
@@ -86,7 +106,7 @@ impl PartialEq for ChainCacheData {
 }
 
 impl EsploraAsyncBlockchainProviderJsWallet {
-    pub fn new(host: String, network: Network) -> Self {
+    pub fn new(host: String, network: Network, fee_bounds: FeeRateBounds) -> Self {
         let client_builder = Builder::new(&host).timeout(REQWEST_TIMEOUT);
         let url_client = AsyncClient::from_builder(client_builder)
             .expect("To be able to create a bdk esplora client ");
@@ -99,8 +119,10 @@ impl EsploraAsyncBlockchainProviderJsWallet {
                 utxos: Some(vec![]).into(),
                 txs: Some(HashMap::new()).into(),
                 height: Some(0).into(),
+                fee_estimates: HashMap::new().into(),
             })),
             network,
+            fee_bounds,
         }
     }
 
@@ -165,6 +187,16 @@ impl EsploraAsyncBlockchainProviderJsWallet {
             }
         };
 
+        // Only the fee estimator uses them, keeping the previous ones if the
+        // fetch fails
+        match self.get_fee_estimates().await {
+            Ok(estimates) => match self.chain_data.lock() {
+                Ok(chain_data) => *chain_data.fee_estimates.borrow_mut() = estimates,
+                Err(e) => warn!("Error getting lock on mutex for chain_data: {}", e),
+            },
+            Err(e) => warn!("Error fetching fee estimates: {}", e),
+        }
+
         debug!("fetching utxos from chain for address {}", address);
 
         //This only grabs the utxos for this one address. For the BDk we would need more than that. But for the
@@ -265,6 +297,17 @@ impl EsploraAsyncBlockchainProviderJsWallet {
         Ok(())
     }
 
+    /// Returns the fee rates estimated by Esplora, in sats/vbyte by
+    /// confirmation target.
+    async fn get_fee_estimates(&self) -> Result<HashMap<u16, f64>, Error> {
+        Ok(self
+            .get_from_json::<HashMap<String, f64>>("fee-estimates")
+            .await?
+            .into_iter()
+            .filter_map(|(target, rate)| target.parse().ok().map(|target| (target, rate)))
+            .collect())
+    }
+
     pub fn get_utxos(&self) -> Result<Vec<Utxo>, Error> {
         let cdata = self.chain_data.lock().map_err(|e| {
             Error::BlockchainError(format!("Error getting lock on mutex for chain_data: {}", e))
@@ -316,6 +359,69 @@ impl AsyncBlockchain for EsploraAsyncBlockchainProviderJsWallet {
         Transaction::consensus_decode(&mut std::io::Cursor::new(&*raw_tx))
             .map_err(|e| Error::BlockchainError(e.to_string()))
     }
+
+    async fn find_transaction_async(&self, tx_id: &Txid) -> Result<Option<Transaction>, Error> {
+        let response = self.get(&format!("tx/{tx_id}/raw")).await?;
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        let raw_tx = response
+            .error_for_status()
+            .map_err(|e| Error::BlockchainError(e.to_string()))?
+            .bytes()
+            .await
+            .map_err(|e| Error::BlockchainError(e.to_string()))?;
+        Transaction::consensus_decode(&mut std::io::Cursor::new(&*raw_tx))
+            .map(Some)
+            .map_err(|e| Error::BlockchainError(e.to_string()))
+    }
+
+    async fn get_output_spending_txid_async(
+        &self,
+        outpoint: &OutPoint,
+    ) -> Result<Option<Txid>, Error> {
+        let outspend = self
+            .get_from_json::<OutspendResp>(&format!(
+                "tx/{}/outspend/{}",
+                outpoint.txid, outpoint.vout
+            ))
+            .await?;
+        if outspend.spent {
+            Ok(outspend.txid)
+        } else {
+            Ok(None)
+        }
+    }
+
+    async fn get_median_time_past_async(&self) -> Result<Option<u64>, Error> {
+        let tip_hash = self
+            .get("blocks/tip/hash")
+            .await?
+            .text()
+            .await
+            .map_err(|e| Error::BlockchainError(e.to_string()))?;
+        let block = self
+            .get_from_json::<BlockResp>(&format!("block/{}", tip_hash.trim()))
+            .await?;
+        Ok(Some(block.mediantime))
+    }
+
+    async fn get_transaction_block_hash_async(
+        &self,
+        tx_id: &Txid,
+    ) -> Result<Option<BlockHash>, Error> {
+        let tx_status = self
+            .get_from_json::<TxStatus>(&format!("tx/{tx_id}/status"))
+            .await?;
+        Ok(tx_status.block_hash.filter(|_| tx_status.confirmed))
+    }
+
+    async fn is_block_in_best_chain_async(&self, block_hash: &BlockHash) -> Result<bool, Error> {
+        let block_status = self
+            .get_from_json::<BlockStatusResp>(&format!("block/{block_hash}/status"))
+            .await?;
+        Ok(block_status.in_best_chain)
+    }
 }
 
 impl Blockchain for EsploraAsyncBlockchainProviderJsWallet {
@@ -381,11 +487,19 @@ impl WalletBlockchainProvider for EsploraAsyncBlockchainProviderJsWallet {
 }
 
 impl FeeEstimator for EsploraAsyncBlockchainProviderJsWallet {
-    fn get_est_sat_per_1000_weight(
-        &self,
-        _confirmation_target: lightning::chain::chaininterface::ConfirmationTarget,
-    ) -> u32 {
-        unimplemented!()
+    /// Uses the estimates of the last `refresh_chain_data`, the fallback rate
+    /// until they are fetched.
+    fn get_est_sat_per_1000_weight(&self, confirmation_target: ConfirmationTarget) -> u32 {
+        match self.chain_data.lock() {
+            Ok(chain_data) => estimate_sat_per_1000_weight(
+                &chain_data.fee_estimates.borrow(),
+                confirmation_target,
+                &self.fee_bounds,
+            ),
+            Err(_) => {
+                estimate_sat_per_1000_weight(&HashMap::new(), confirmation_target, &self.fee_bounds)
+            }
+        }
     }
 }
 
@@ -399,6 +513,7 @@ mod tests {
         EsploraAsyncBlockchainProviderJsWallet::new(
             "esplora_url".to_string(),
             bitcoin::Network::Regtest,
+            FeeRateBounds::for_network(bitcoin::Network::Regtest),
         )
     }
 
@@ -416,8 +531,33 @@ mod tests {
                 utxos: Some(vec![]).into(),
                 txs: Some(HashMap::new()).into(),
                 height: Some(0).into(),
+                fee_estimates: HashMap::new().into(),
             },
             chain_data.deref()
         );
     }
+
+    #[test]
+    fn fee_estimator_falls_back_until_estimates_are_fetched() {
+        let provider = get_esplora_provider_for_js_wallet();
+        assert_eq!(
+            provider.get_est_sat_per_1000_weight(ConfirmationTarget::Normal),
+            lightning::chain::chaininterface::FEERATE_FLOOR_SATS_PER_KW
+        );
+
+        *provider
+            .chain_data
+            .lock()
+            .expect("To be able to unwrap the mutex")
+            .fee_estimates
+            .borrow_mut() = HashMap::from([(6, 20.0), (144, 2.0)]);
+        assert_eq!(
+            provider.get_est_sat_per_1000_weight(ConfirmationTarget::HighPriority),
+            5000
+        );
+        assert_eq!(
+            provider.get_est_sat_per_1000_weight(ConfirmationTarget::Background),
+            500
+        );
+    }
 }
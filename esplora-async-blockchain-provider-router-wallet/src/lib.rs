@@ -5,16 +5,21 @@
 use bdk::esplora_client::TxStatus;
 use bdk::esplora_client::{AsyncClient, Builder};
 use bitcoin::consensus::Decodable;
-use bitcoin::{Block, Network, Transaction, Txid};
-use dlc_link_manager::AsyncBlockchain;
+use bitcoin::{Block, BlockHash, Network, OutPoint, Transaction, Txid};
+use dlc_link_manager::{
+    estimate_sat_per_1000_weight, select_fee_rate, AsyncBlockchain, FeeRateBounds,
+};
 use dlc_manager::{error::Error, Blockchain, Utxo};
 
 use js_interface_wallet::WalletBlockchainProvider;
-use lightning::chain::chaininterface::FeeEstimator;
-use reqwest::Response;
+use lightning::chain::chaininterface::{ConfirmationTarget, FeeEstimator};
+use reqwest::{Response, StatusCode};
 
 use serde::{Deserialize, Serialize};
 
+use std::collections::HashMap;
+use std::sync::RwLock;
+
 use bdk::blockchain::esplora::EsploraBlockchain;
 
 const REQWEST_TIMEOUT: u64 = 30;
@@ -46,14 +51,34 @@ struct UTXOSpent {
     spent: bool,
 }
 
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct OutspendResp {
+    spent: bool,
+    txid: Option<Txid>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct BlockResp {
+    mediantime: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct BlockStatusResp {
+    in_best_chain: bool,
+}
+
 pub struct EsploraAsyncBlockchainProviderRouterWallet {
     host: String,
     pub blockchain: EsploraBlockchain,
     network: Network,
+    // Fee rates by confirmation target of the last `fee-estimates` fetch
+    fee_estimates: RwLock<HashMap<u16, f64>>,
+    // Bounds of the rates returned as a `FeeEstimator`
+    fee_bounds: FeeRateBounds,
 }
 
 impl EsploraAsyncBlockchainProviderRouterWallet {
-    pub fn new(host: String, network: Network) -> Self {
+    pub fn new(host: String, network: Network, fee_bounds: FeeRateBounds) -> Self {
         let client_builder = Builder::new(&host).timeout(REQWEST_TIMEOUT);
         let url_client = AsyncClient::from_builder(client_builder)
             .expect("To be able to create a reqwest client");
@@ -64,6 +89,8 @@ impl EsploraAsyncBlockchainProviderRouterWallet {
             host,
             blockchain,
             network,
+            fee_estimates: RwLock::new(HashMap::new()),
+            fee_bounds,
         }
     }
 
@@ -99,6 +126,31 @@ impl EsploraAsyncBlockchainProviderRouterWallet {
             .map_err(|e| Error::BlockchainError(e.to_string()))
     }
 
+    /// Returns the fee rate estimated for a confirmation within
+    /// `confirmation_target` blocks, in sats/vbyte. When Esplora has no
+    /// estimate for that target, the estimate of the closest longer target
+    /// is used, or of the longest one.
+    pub async fn get_fee_rate_estimate(&self, confirmation_target: u16) -> Result<f64, Error> {
+        let estimates = self.refresh_fee_estimates().await?;
+        select_fee_rate(&estimates, confirmation_target)
+            .ok_or_else(|| Error::BlockchainError("No fee estimates available".to_string()))
+    }
+
+    /// Fetches the fee rates estimated by Esplora, in sats/vbyte by
+    /// confirmation target. They are kept for the `FeeEstimator`.
+    pub async fn refresh_fee_estimates(&self) -> Result<HashMap<u16, f64>, Error> {
+        let estimates: HashMap<u16, f64> = self
+            .get_from_json::<HashMap<String, f64>>("fee-estimates")
+            .await?
+            .into_iter()
+            .filter_map(|(target, rate)| target.parse().ok().map(|target| (target, rate)))
+            .collect();
+        if let Ok(mut fee_estimates) = self.fee_estimates.write() {
+            *fee_estimates = estimates.clone();
+        }
+        Ok(estimates)
+    }
+
     async fn get_bytes(&self, sub_url: &str) -> Result<Vec<u8>, Error> {
         let bytes = self.get(sub_url).await?.bytes().await;
         Ok(bytes
@@ -143,6 +195,69 @@ impl AsyncBlockchain for EsploraAsyncBlockchainProviderRouterWallet {
         Transaction::consensus_decode(&mut std::io::Cursor::new(&*raw_tx))
             .map_err(|e| Error::BlockchainError(e.to_string()))
     }
+
+    async fn find_transaction_async(&self, tx_id: &Txid) -> Result<Option<Transaction>, Error> {
+        let response = self.get(&format!("tx/{tx_id}/raw")).await?;
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        let raw_tx = response
+            .error_for_status()
+            .map_err(|e| Error::BlockchainError(e.to_string()))?
+            .bytes()
+            .await
+            .map_err(|e| Error::BlockchainError(e.to_string()))?;
+        Transaction::consensus_decode(&mut std::io::Cursor::new(&*raw_tx))
+            .map(Some)
+            .map_err(|e| Error::BlockchainError(e.to_string()))
+    }
+
+    async fn get_output_spending_txid_async(
+        &self,
+        outpoint: &OutPoint,
+    ) -> Result<Option<Txid>, Error> {
+        let outspend = self
+            .get_from_json::<OutspendResp>(&format!(
+                "tx/{}/outspend/{}",
+                outpoint.txid, outpoint.vout
+            ))
+            .await?;
+        if outspend.spent {
+            Ok(outspend.txid)
+        } else {
+            Ok(None)
+        }
+    }
+
+    async fn get_median_time_past_async(&self) -> Result<Option<u64>, Error> {
+        let tip_hash = self
+            .get("blocks/tip/hash")
+            .await?
+            .text()
+            .await
+            .map_err(|e| Error::BlockchainError(e.to_string()))?;
+        let block = self
+            .get_from_json::<BlockResp>(&format!("block/{}", tip_hash.trim()))
+            .await?;
+        Ok(Some(block.mediantime))
+    }
+
+    async fn get_transaction_block_hash_async(
+        &self,
+        tx_id: &Txid,
+    ) -> Result<Option<BlockHash>, Error> {
+        let tx_status = self
+            .get_from_json::<TxStatus>(&format!("tx/{tx_id}/status"))
+            .await?;
+        Ok(tx_status.block_hash.filter(|_| tx_status.confirmed))
+    }
+
+    async fn is_block_in_best_chain_async(&self, block_hash: &BlockHash) -> Result<bool, Error> {
+        let block_status = self
+            .get_from_json::<BlockStatusResp>(&format!("block/{block_hash}/status"))
+            .await?;
+        Ok(block_status.in_best_chain)
+    }
 }
 
 impl Blockchain for EsploraAsyncBlockchainProviderRouterWallet {
@@ -188,10 +303,55 @@ impl WalletBlockchainProvider for EsploraAsyncBlockchainProviderRouterWallet {
 }
 
 impl FeeEstimator for EsploraAsyncBlockchainProviderRouterWallet {
-    fn get_est_sat_per_1000_weight(
-        &self,
-        _confirmation_target: lightning::chain::chaininterface::ConfirmationTarget,
-    ) -> u32 {
-        unimplemented!()
+    /// Uses the estimates of the last `refresh_fee_estimates`, the fallback
+    /// rate until they are fetched.
+    fn get_est_sat_per_1000_weight(&self, confirmation_target: ConfirmationTarget) -> u32 {
+        match self.fee_estimates.read() {
+            Ok(estimates) => {
+                estimate_sat_per_1000_weight(&estimates, confirmation_target, &self.fee_bounds)
+            }
+            Err(_) => {
+                estimate_sat_per_1000_weight(&HashMap::new(), confirmation_target, &self.fee_bounds)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn estimates() -> HashMap<u16, f64> {
+        HashMap::from([(1, 50.5), (6, 20.0), (144, 2.0), (1008, 0.5)])
+    }
+
+    #[test]
+    fn fee_estimator_uses_the_fetched_estimates() {
+        let provider = EsploraAsyncBlockchainProviderRouterWallet::new(
+            "esplora_url".to_string(),
+            Network::Testnet,
+            FeeRateBounds::for_network(Network::Testnet),
+        );
+        assert_eq!(
+            provider.get_est_sat_per_1000_weight(ConfirmationTarget::Normal),
+            400 * 250
+        );
+
+        *provider
+            .fee_estimates
+            .write()
+            .expect("to lock the fee estimates") = estimates();
+        assert_eq!(
+            provider.get_est_sat_per_1000_weight(ConfirmationTarget::HighPriority),
+            5000
+        );
+        assert_eq!(
+            provider.get_est_sat_per_1000_weight(ConfirmationTarget::Normal),
+            500
+        );
+        assert_eq!(
+            provider.get_est_sat_per_1000_weight(ConfirmationTarget::Background),
+            500
+        );
     }
 }
@@ -60,10 +60,12 @@ async function attest(attestorURL, uuid, outcome) {
   }
 }
 
-async function fetchOfferFromProtocolWallet(uuid, overrides = {}) {
+async function fetchOfferFromProtocolWallet(dlcManager, uuid, overrides = {}) {
   let body = {
     uuid,
+    counterpartyPublicKey: dlcManager.get_public_key(),
     acceptCollateral,
+    totalOutcomes: 100,
     refundDelay: 86400 * 7,
     btcFeeRecipient: btcFeeRecipient,
     btcFeeBasisPoints: btcFeeBasisPoints,
@@ -179,10 +181,10 @@ async function setupDLC(dlcManager, uuid, time, overrides = {}) {
 
   //Fetching Offer
   console.log('Fetching Offer from Protocol Wallet');
-  const offerResponse = await fetchOfferFromProtocolWallet(uuid, { ...overrides });
+  const offerResponse = await fetchOfferFromProtocolWallet(dlcManager, uuid, { ...overrides });
 
   //Check if the offer is valid
-  if (!offerResponse.temporaryContractId) {
+  if (!offerResponse.message || !JSON.parse(offerResponse.message).dlcMessage.temporaryContractId) {
     console.error('[IT] Error fetching offer from protocol wallet: ', offerResponse);
     process.exit(1);
   }
@@ -192,7 +194,7 @@ async function setupDLC(dlcManager, uuid, time, overrides = {}) {
   const parsedResponse = JSON.parse(acceptedContract);
 
   //Check if the accepted contract is valid
-  if (!parsedResponse.protocolVersion) {
+  if (!parsedResponse.signature) {
     console.log('[IT] Error accepting offer: ', parsedResponse);
     process.exit(1);
   }
@@ -201,11 +203,11 @@ async function setupDLC(dlcManager, uuid, time, overrides = {}) {
   const signedContract = await sendAcceptedOfferToProtocolWallet(acceptedContract);
 
   //Check if the signed contract is valid
-  if (!signedContract.contractId) {
+  if (!signedContract.message || !JSON.parse(signedContract.message).dlcMessage.contractId) {
     console.log('[IT] Error signing offer: ', signedContract);
     process.exit(1);
   }
-  const contractID = signedContract.contractId;
+  const contractID = JSON.parse(signedContract.message).dlcMessage.contractId;
 
   //Check if the contract is in the Signed state
   assert(
@@ -329,12 +331,16 @@ async function verify_refund_tx(dlcManager, contractID) {
 
 async function main() {
   //Creating DLC Manager Interface
+  const routerWalletInfo = await (await fetch(`${protocolWalletURL}/info`)).json();
   const dlcManager = await JsDLCInterface.new(
     testWalletPrivateKey,
     testWalletAddress,
     bitcoinNetwork,
     bitcoinNetworkURL,
-    storageApiUrl
+    storageApiUrl,
+    routerWalletInfo.wallet.publicKey,
+    undefined,
+    process.env.ORACLE_API
   );
 
   await checkBalance(dlcManager, '[STARTING BALANCE]');
@@ -2,7 +2,9 @@ use crate::DbPool;
 use actix_web::web;
 use actix_web::web::{Data, Json, Path};
 use actix_web::{delete, get, post, put, HttpResponse, Responder};
-use dlc_storage_common::models::{DeleteEvent, EventRequestParams, NewEvent, UpdateEvent};
+use dlc_storage_common::models::{
+    DeleteEvent, EventRequestParams, NewEvent, SwapEvent, UpdateEvent,
+};
 use log::warn;
 use serde_json::json;
 
@@ -42,6 +44,24 @@ pub async fn update_event(pool: Data<DbPool>, event: Json<UpdateEvent>) -> impl
     }
 }
 
+// Compare and swap of the content of an event, a conflict means the event
+// changed since it was read, or no longer exists.
+#[put("/events/swap")]
+pub async fn swap_event(pool: Data<DbPool>, event: Json<SwapEvent>) -> impl Responder {
+    let mut conn = pool.get().expect("couldn't get db connection from pool");
+    let num_updated = match dlc_storage_writer::swap_event(&mut conn, event.into_inner()) {
+        Ok(num_updated) => num_updated,
+        Err(e) => {
+            warn!("Error swapping event: {:?}", e);
+            return HttpResponse::BadRequest().body(e.to_string());
+        }
+    };
+    match num_updated {
+        0 => HttpResponse::Conflict().body("No event found with the expected content"),
+        _ => HttpResponse::Ok().json(json!({ "effected_num": num_updated })),
+    }
+}
+
 #[delete("/event")]
 pub async fn delete_event(pool: Data<DbPool>, event: Json<DeleteEvent>) -> impl Responder {
     let mut conn = pool.get().expect("couldn't get db connection from pool");
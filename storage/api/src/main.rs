@@ -120,6 +120,7 @@ async fn main() -> std::io::Result<()> {
             .service(get_events)
             .service(create_event)
             .service(update_event)
+            .service(swap_event)
             .service(delete_event)
             .service(delete_events)
     })
@@ -132,6 +132,22 @@ pub fn update_event(
     }
 }
 
+pub fn swap_event(
+    conn: &mut PgConnection,
+    event: SwapEvent,
+) -> Result<usize, diesel::result::Error> {
+    use crate::schema::events::dsl::*;
+    let num_updated = diesel::update(
+        events
+            .filter(event_id.eq(event.event_id))
+            .filter(key.eq(event.key))
+            .filter(content.eq(event.expected_content)),
+    )
+    .set(content.eq(event.content))
+    .execute(conn)?;
+    Ok(num_updated)
+}
+
 pub fn get_events(
     conn: &mut PgConnection,
     event: EventRequestParams,
@@ -144,6 +160,15 @@ pub fn get_events(
         query = query.filter(event_id.eq(cevent_id));
     }
 
+    if let Some(prefix) = event.event_id_prefix {
+        // The wildcards of LIKE are escaped with the default escape character
+        let pattern = prefix
+            .replace('\\', "\\\\")
+            .replace('%', "\\%")
+            .replace('_', "\\_");
+        query = query.filter(event_id.like(format!("{}%", pattern)));
+    }
+
     let results = query.load::<Event>(conn)?;
     Ok(results)
 }
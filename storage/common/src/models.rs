@@ -67,6 +67,16 @@ pub struct UpdateEvent {
     pub key: String,
 }
 
+/// An update of the content of an event, applied only while the event still
+/// holds `expected_content`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SwapEvent {
+    pub event_id: String,
+    pub content: String,
+    pub expected_content: String,
+    pub key: String,
+}
+
 #[derive(Serialize, Deserialize, AsChangeset, Debug, Clone)]
 #[diesel(table_name = events)]
 pub struct DeleteEvent {
@@ -78,4 +88,6 @@ pub struct DeleteEvent {
 pub struct EventRequestParams {
     pub key: String,
     pub event_id: Option<String>,
+    /// Only the events whose id starts with the prefix are returned.
+    pub event_id_prefix: Option<String>,
 }
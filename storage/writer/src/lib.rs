@@ -1,6 +1,6 @@
 use diesel::PgConnection;
 use dlc_storage_common::models::{
-    Contract, DeleteContract, DeleteEvent, Event, NewContract, NewEvent, UpdateContract,
+    Contract, DeleteContract, DeleteEvent, Event, NewContract, NewEvent, SwapEvent, UpdateContract,
     UpdateEvent,
 };
 
@@ -50,6 +50,13 @@ pub fn update_event(
     dlc_storage_common::update_event(conn, event)
 }
 
+pub fn swap_event(
+    conn: &mut PgConnection,
+    event: SwapEvent,
+) -> Result<usize, diesel::result::Error> {
+    dlc_storage_common::swap_event(conn, event)
+}
+
 pub fn delete_event(
     conn: &mut PgConnection,
     event: DeleteEvent,
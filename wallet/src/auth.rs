@@ -0,0 +1,398 @@
+//! Authentication of the requests to the router wallet, and the CORS headers
+//! of its responses.
+//!
+//! Callers authenticate with an API key in the `X-API-Key` header, or sign
+//! their requests with a shared HMAC secret. A signed request carries the id
+//! of the secret in `X-Key-Id`, the unix time in `X-Timestamp`, and in
+//! `X-Signature` the hex HMAC-SHA256 of
+//! `"{timestamp}\n{method}\n{path and query}\n{body}"`.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use bitcoin::hashes::hmac::{Hmac, HmacEngine};
+use bitcoin::hashes::{sha256, Hash, HashEngine};
+use http::request::Parts;
+use hyper::header::{self, HeaderMap, HeaderValue};
+use hyper::{Method, StatusCode};
+
+pub const API_KEY_HEADER: &str = "x-api-key";
+const KEY_ID_HEADER: &str = "x-key-id";
+const TIMESTAMP_HEADER: &str = "x-timestamp";
+const SIGNATURE_HEADER: &str = "x-signature";
+
+/// How far the timestamp of a signed request may be from our clock, which
+/// bounds the time a captured request can be replayed.
+const MAX_SIGNATURE_AGE_SECONDS: u64 = 300;
+
+/// Authentication settings of the HTTP server.
+#[derive(Clone, Debug, Default)]
+pub struct AuthConfig {
+    /// API keys of the dapp backends creating offers.
+    pub api_keys: Vec<String>,
+    /// API keys allowed to use the operational endpoints, as well as the
+    /// others.
+    pub admin_api_keys: Vec<String>,
+    /// HMAC secrets of the dapp backends creating offers, by key id.
+    pub hmac_keys: HashMap<String, String>,
+    /// Origins browsers may call the wallet from, `*` for any.
+    pub cors_allowed_origins: Vec<String>,
+}
+
+impl AuthConfig {
+    /// Authentication is only enforced once a key is configured.
+    pub fn is_enabled(&self) -> bool {
+        !self.api_keys.is_empty() || !self.admin_api_keys.is_empty() || !self.hmac_keys.is_empty()
+    }
+}
+
+/// Who may call an endpoint.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Scope {
+    Public,
+    Client,
+    Admin,
+}
+
+impl Scope {
+    pub fn of(method: &Method, path: &str) -> Scope {
+        match (method, path) {
+            (&Method::OPTIONS, _) => Scope::Public,
+            (&Method::GET, "/health") => Scope::Public,
+            (&Method::GET, path) if path.starts_with("/get_chain/") => Scope::Public,
+            // The acceptor is authenticated by the signature of its message
+            (&Method::PUT, "/offer/accept") => Scope::Public,
+            (&Method::POST, "/offer") => Scope::Client,
+            _ => Scope::Admin,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum AuthError {
+    /// No valid credentials were given.
+    Unauthenticated(String),
+    /// The credentials don't give access to the endpoint.
+    Forbidden,
+}
+
+impl AuthError {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            AuthError::Unauthenticated(_) => StatusCode::UNAUTHORIZED,
+            AuthError::Forbidden => StatusCode::FORBIDDEN,
+        }
+    }
+}
+
+impl fmt::Display for AuthError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AuthError::Unauthenticated(reason) => write!(f, "Unauthenticated: {}", reason),
+            AuthError::Forbidden => write!(f, "Forbidden: admin credentials are required"),
+        }
+    }
+}
+
+/// The signature of a request whose headers passed [`Auth::authorize`],
+/// still to be checked against the body.
+pub struct PendingSignature {
+    secret: String,
+    timestamp: u64,
+    signature: Vec<u8>,
+}
+
+pub struct Auth {
+    config: AuthConfig,
+}
+
+impl Auth {
+    pub fn new(config: AuthConfig) -> Self {
+        Auth { config }
+    }
+
+    /// Checks the credentials of a request against the scope of its
+    /// endpoint, from its headers only so that unauthenticated requests are
+    /// rejected before their body is read. The signature of a signed request
+    /// covers the body, it is checked by [`Auth::verify_signature`] once the
+    /// body is read.
+    pub fn authorize(&self, parts: &Parts) -> Result<Option<PendingSignature>, AuthError> {
+        let required = Scope::of(&parts.method, parts.uri.path());
+        if required == Scope::Public || !self.config.is_enabled() {
+            return Ok(None);
+        }
+        let headers = &parts.headers;
+        if let Some(api_key) = header_str(headers, API_KEY_HEADER)? {
+            let granted = if contains_key(&self.config.admin_api_keys, api_key) {
+                Scope::Admin
+            } else if contains_key(&self.config.api_keys, api_key) {
+                Scope::Client
+            } else {
+                return Err(AuthError::Unauthenticated("unknown API key".to_string()));
+            };
+            if granted < required {
+                return Err(AuthError::Forbidden);
+            }
+            return Ok(None);
+        }
+
+        let key_id = header_str(headers, KEY_ID_HEADER)?.ok_or_else(|| {
+            AuthError::Unauthenticated("an API key or a signature is required".to_string())
+        })?;
+        let secret = self
+            .config
+            .hmac_keys
+            .get(key_id)
+            .ok_or_else(|| AuthError::Unauthenticated("unknown key id".to_string()))?;
+        // Signed requests are only granted the client scope.
+        if Scope::Client < required {
+            return Err(AuthError::Forbidden);
+        }
+        let timestamp: u64 = header_str(headers, TIMESTAMP_HEADER)?
+            .and_then(|t| t.parse().ok())
+            .ok_or_else(|| AuthError::Unauthenticated("invalid timestamp".to_string()))?;
+        if unix_time_now().abs_diff(timestamp) > MAX_SIGNATURE_AGE_SECONDS {
+            return Err(AuthError::Unauthenticated(
+                "timestamp is too far from the server time".to_string(),
+            ));
+        }
+        let signature = header_str(headers, SIGNATURE_HEADER)?
+            .and_then(|s| hex::decode(s).ok())
+            .ok_or_else(|| AuthError::Unauthenticated("invalid signature".to_string()))?;
+        Ok(Some(PendingSignature {
+            secret: secret.clone(),
+            timestamp,
+            signature,
+        }))
+    }
+
+    /// Checks the signature of a signed request against its body.
+    pub fn verify_signature(
+        &self,
+        parts: &Parts,
+        body: &[u8],
+        pending: PendingSignature,
+    ) -> Result<(), AuthError> {
+        let path = parts
+            .uri
+            .path_and_query()
+            .map(|p| p.as_str())
+            .unwrap_or_else(|| parts.uri.path());
+        let mut engine = HmacEngine::<sha256::Hash>::new(pending.secret.as_bytes());
+        engine.input(format!("{}\n{}\n{}\n", pending.timestamp, parts.method, path).as_bytes());
+        engine.input(body);
+        let expected = Hmac::<sha256::Hash>::from_engine(engine);
+        if !constant_time_eq(&expected.into_inner(), &pending.signature) {
+            return Err(AuthError::Unauthenticated("invalid signature".to_string()));
+        }
+        Ok(())
+    }
+
+    /// Sets the CORS headers of a response to a request from `origin`.
+    pub fn apply_cors(&self, origin: Option<&HeaderValue>, headers: &mut HeaderMap) {
+        let allowed = &self.config.cors_allowed_origins;
+        let allow_origin = if allowed.iter().any(|o| o == "*") {
+            HeaderValue::from_static("*")
+        } else {
+            match origin {
+                Some(origin) if allowed.iter().any(|o| origin == o.as_str()) => {
+                    headers.insert(header::VARY, HeaderValue::from_static("Origin"));
+                    origin.clone()
+                }
+                _ => return,
+            }
+        };
+        headers.insert(header::ACCESS_CONTROL_ALLOW_ORIGIN, allow_origin);
+        headers.insert(
+            header::ACCESS_CONTROL_ALLOW_METHODS,
+            HeaderValue::from_static("GET, POST, PUT, OPTIONS"),
+        );
+        headers.insert(
+            header::ACCESS_CONTROL_ALLOW_HEADERS,
+            HeaderValue::from_static("content-type, x-api-key, x-key-id, x-timestamp, x-signature"),
+        );
+    }
+}
+
+fn header_str<'a>(headers: &'a HeaderMap, name: &str) -> Result<Option<&'a str>, AuthError> {
+    headers
+        .get(name)
+        .map(|value| {
+            value
+                .to_str()
+                .map_err(|_| AuthError::Unauthenticated(format!("invalid {} header", name)))
+        })
+        .transpose()
+}
+
+fn contains_key(keys: &[String], candidate: &str) -> bool {
+    keys.iter()
+        .any(|key| constant_time_eq(key.as_bytes(), candidate.as_bytes()))
+}
+
+/// Compares secrets without leaking how long their common prefix is.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+fn unix_time_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECRET: &str = "backend-secret";
+
+    fn auth() -> Auth {
+        Auth::new(AuthConfig {
+            api_keys: vec!["client-key".to_string()],
+            admin_api_keys: vec!["admin-key".to_string()],
+            hmac_keys: HashMap::from([("backend-1".to_string(), SECRET.to_string())]),
+            cors_allowed_origins: vec!["https://app.example".to_string()],
+        })
+    }
+
+    fn request(method: Method, uri: &str, headers: &[(&str, String)]) -> Parts {
+        let mut builder = http::Request::builder().method(method).uri(uri);
+        for (name, value) in headers {
+            builder = builder.header(*name, value.as_str());
+        }
+        builder.body(()).expect("a valid request").into_parts().0
+    }
+
+    fn sign(timestamp: u64, method: &str, path: &str, body: &str) -> String {
+        let mut engine = HmacEngine::<sha256::Hash>::new(SECRET.as_bytes());
+        engine.input(format!("{}\n{}\n{}\n{}", timestamp, method, path, body).as_bytes());
+        hex::encode(Hmac::<sha256::Hash>::from_engine(engine).into_inner())
+    }
+
+    fn signed_request(method: Method, uri: &str, timestamp: u64, signature: String) -> Parts {
+        request(
+            method,
+            uri,
+            &[
+                (KEY_ID_HEADER, "backend-1".to_string()),
+                (TIMESTAMP_HEADER, timestamp.to_string()),
+                (SIGNATURE_HEADER, signature),
+            ],
+        )
+    }
+
+    #[test]
+    fn endpoints_are_mapped_to_their_scope() {
+        assert_eq!(Scope::of(&Method::GET, "/health"), Scope::Public);
+        assert_eq!(Scope::of(&Method::OPTIONS, "/offer"), Scope::Public);
+        assert_eq!(Scope::of(&Method::GET, "/get_chain/uuid"), Scope::Public);
+        assert_eq!(Scope::of(&Method::PUT, "/offer/accept"), Scope::Public);
+        assert_eq!(Scope::of(&Method::POST, "/offer"), Scope::Client);
+        assert_eq!(Scope::of(&Method::GET, "/info"), Scope::Admin);
+        assert_eq!(Scope::of(&Method::GET, "/periodic_check"), Scope::Admin);
+        assert_eq!(Scope::of(&Method::POST, "/periodic_check"), Scope::Admin);
+        assert_eq!(Scope::of(&Method::GET, "/notifications"), Scope::Admin);
+        assert_eq!(Scope::of(&Method::POST, "/notifications"), Scope::Admin);
+        assert_eq!(Scope::of(&Method::PUT, "/contracts/id/retry"), Scope::Admin);
+    }
+
+    #[test]
+    fn client_api_key_is_forbidden_on_admin_endpoints() {
+        let auth = auth();
+        let client = [(API_KEY_HEADER, "client-key".to_string())];
+        let admin = [(API_KEY_HEADER, "admin-key".to_string())];
+
+        assert!(auth
+            .authorize(&request(Method::POST, "/offer", &client))
+            .is_ok());
+        assert!(matches!(
+            auth.authorize(&request(Method::GET, "/info", &client)),
+            Err(AuthError::Forbidden)
+        ));
+        assert!(auth
+            .authorize(&request(Method::GET, "/info", &admin))
+            .is_ok());
+        assert!(matches!(
+            auth.authorize(&request(
+                Method::GET,
+                "/info",
+                &[(API_KEY_HEADER, "unknown".to_string())]
+            )),
+            Err(AuthError::Unauthenticated(_))
+        ));
+    }
+
+    #[test]
+    fn signature_covers_the_body_path_and_timestamp() {
+        let auth = auth();
+        let now = unix_time_now();
+        let body = r#"{"uuid":"abc"}"#;
+        let signature = sign(now, "POST", "/offer?x=1", body);
+        let verify = |parts: &Parts, body: &str| {
+            let pending = auth
+                .authorize(parts)
+                .expect("valid headers")
+                .expect("a signed request");
+            auth.verify_signature(parts, body.as_bytes(), pending)
+        };
+
+        let parts = signed_request(Method::POST, "/offer?x=1", now, signature.clone());
+        assert!(verify(&parts, body).is_ok());
+        assert!(verify(&parts, r#"{"uuid":"abd"}"#).is_err());
+
+        let other_path = signed_request(Method::POST, "/offer?x=2", now, signature.clone());
+        assert!(verify(&other_path, body).is_err());
+
+        let other_timestamp = signed_request(Method::POST, "/offer?x=1", now - 1, signature);
+        assert!(verify(&other_timestamp, body).is_err());
+    }
+
+    #[test]
+    fn stale_timestamp_is_rejected() {
+        let auth = auth();
+        let timestamp = unix_time_now() - MAX_SIGNATURE_AGE_SECONDS - 10;
+        let parts = signed_request(
+            Method::POST,
+            "/offer",
+            timestamp,
+            sign(timestamp, "POST", "/offer", ""),
+        );
+
+        assert!(matches!(
+            auth.authorize(&parts),
+            Err(AuthError::Unauthenticated(_))
+        ));
+    }
+
+    #[test]
+    fn cors_only_echoes_allowed_origins() {
+        let auth = auth();
+
+        let mut headers = HeaderMap::new();
+        let allowed = HeaderValue::from_static("https://app.example");
+        auth.apply_cors(Some(&allowed), &mut headers);
+        assert_eq!(
+            headers.get(header::ACCESS_CONTROL_ALLOW_ORIGIN),
+            Some(&allowed)
+        );
+
+        let mut headers = HeaderMap::new();
+        let other = HeaderValue::from_static("https://evil.example");
+        auth.apply_cors(Some(&other), &mut headers);
+        assert!(headers.get(header::ACCESS_CONTROL_ALLOW_ORIGIN).is_none());
+
+        let mut headers = HeaderMap::new();
+        Auth::new(AuthConfig {
+            cors_allowed_origins: vec!["*".to_string()],
+            ..Default::default()
+        })
+        .apply_cors(Some(&other), &mut headers);
+        assert_eq!(
+            headers.get(header::ACCESS_CONTROL_ALLOW_ORIGIN),
+            Some(&HeaderValue::from_static("*"))
+        );
+    }
+}
@@ -4,17 +4,20 @@ use std::{env, str::FromStr};
 
 use serde_json::json;
 
+#[path = "../network.rs"]
+mod network;
+
 fn main() {
     // Setup Blockchain Connection Object
-    let network = match env::var("BITCOIN_NETWORK").as_deref() {
-        Ok("bitcoin") => bitcoin::Network::Bitcoin,
-        Ok("testnet") => bitcoin::Network::Testnet,
-        Ok("signet") => bitcoin::Network::Signet,
-        Ok("regtest") => bitcoin::Network::Regtest,
-        _ => panic!(
-            "Unknown Bitcoin Network, make sure to set BITCOIN_NETWORK in your env variables"
-        ),
-    };
+    let network = env::var("BITCOIN_NETWORK")
+        .ok()
+        .and_then(|name| network::parse_network(&name))
+        .unwrap_or_else(|| {
+            panic!(
+                "Unknown Bitcoin Network, make sure to set BITCOIN_NETWORK to {} in your env variables",
+                network::NETWORK_NAMES
+            )
+        });
 
     let xpriv_str = env::var("XPKEY").expect("XPKEY env variable not set");
     let xpriv = ExtendedPrivKey::from_str(&xpriv_str).expect("Unable to decode xpriv env variable");
@@ -10,17 +10,20 @@ use secp256k1_zkp::Secp256k1;
 
 use serde_json::json;
 
+#[path = "../network.rs"]
+mod network;
+
 fn main() {
     // Setup Blockchain Connection Object
-    let network = match env::var("BITCOIN_NETWORK").as_deref() {
-        Ok("bitcoin") => bitcoin::Network::Bitcoin,
-        Ok("testnet") => bitcoin::Network::Testnet,
-        Ok("signet") => bitcoin::Network::Signet,
-        Ok("regtest") => bitcoin::Network::Regtest,
-        _ => panic!(
-            "Unknown Bitcoin Network, make sure to set BITCOIN_NETWORK in your env variables"
-        ),
-    };
+    let network = env::var("BITCOIN_NETWORK")
+        .ok()
+        .and_then(|name| network::parse_network(&name))
+        .unwrap_or_else(|| {
+            panic!(
+                "Unknown Bitcoin Network, make sure to set BITCOIN_NETWORK to {} in your env variables",
+                network::NETWORK_NAMES
+            )
+        });
 
     let secp = Secp256k1::new();
     let mnemonic: GeneratedKey<_, Segwitv0> =
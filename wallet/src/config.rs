@@ -0,0 +1,684 @@
+//! Router wallet settings. They are read from the `router-wallet` section of
+//! a TOML or YAML config file, and each of them can be overridden by the
+//! environment variable of the same name, e.g. `check-concurrency` by
+//! `CHECK_CONCURRENCY`.
+
+use std::collections::HashMap;
+use std::net::Ipv4Addr;
+use std::path::Path;
+use std::str::FromStr;
+use std::time::Duration;
+use std::{env, fmt, fs};
+
+use attestor_client::OracleApi;
+use bitcoin::secp256k1::Secp256k1;
+use bitcoin::util::bip32::ExtendedPrivKey;
+use bitcoin::{Address, Network, XOnlyPublicKey};
+use dlc_link_manager::{
+    CollateralConfirmationDepths, ConfirmationDepths, ContractLeaseConfig, FeeBumpConfig,
+    FeeRateBounds, ManagerConfig, OfferAcceptancePolicy, DEFAULT_CHECK_CONCURRENCY,
+    DEFAULT_MAX_RETRIES,
+};
+use serde::Deserialize;
+
+use crate::auth::AuthConfig;
+use crate::fees::FeeConfig;
+use crate::network::{parse_network, NETWORK_NAMES};
+use crate::validation::{parse_address, OfferLimits};
+
+/// Env variable pointing to the config file, as for the wallet blockchain
+/// interface.
+const CONFIG_PATH_ENV: &str = "CONFIG_LOCATION";
+/// Config file read when [`CONFIG_PATH_ENV`] is not set, if it exists. It is
+/// shared with the wallet blockchain interface.
+const DEFAULT_CONFIG_PATH: &str = "config.yaml";
+
+#[derive(Debug)]
+pub struct ConfigError(String);
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Config Error: {}", self.0)
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+/// Value of an environment variable, `None` when it is not set.
+pub type EnvLookup<'a> = &'a dyn Fn(&str) -> Option<String>;
+
+/// The config file, only the `router-wallet` section is ours, the other
+/// sections belong to the wallet blockchain interface.
+#[derive(Deserialize)]
+struct ConfigFile {
+    #[serde(default, rename = "router-wallet")]
+    router_wallet: FileConfig,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+struct FileConfig {
+    bitcoin_network: Option<String>,
+    blockchain_interface_url: Option<String>,
+    electrum_api_url: Option<String>,
+    storage_api_endpoint: Option<String>,
+    xpriv: Option<String>,
+    fingerprint: Option<String>,
+    wallet_ip: Option<Ipv4Addr>,
+    wallet_backend_port: Option<u16>,
+    bitcoin_check_interval_seconds: Option<u64>,
+    attestor_threshold: Option<u16>,
+    oracle_api: Option<String>,
+    fee_confirmation_target: Option<u16>,
+    min_fee_rate: Option<u64>,
+    max_fee_rate: Option<u64>,
+    fallback_fee_rate: Option<u64>,
+    default_fee_recipient: Option<String>,
+    offer_min_accept_collateral: Option<u64>,
+    offer_max_total_collateral: Option<u64>,
+    offer_max_fee_basis_points: Option<u64>,
+    offer_max_refund_delay_seconds: Option<u32>,
+    funding_confirmations: Option<u32>,
+    cet_confirmations: Option<u32>,
+    refund_confirmations: Option<u32>,
+    collateral_confirmation_overrides: Option<Vec<FileCollateralOverride>>,
+    contract_lease_owner: Option<String>,
+    contract_lease_duration_seconds: Option<u64>,
+    check_concurrency: Option<usize>,
+    contract_check_timeout_seconds: Option<u64>,
+    offer_ttl_seconds: Option<u64>,
+    fee_bump_target_rate: Option<u64>,
+    fee_bump_after_seconds: Option<u64>,
+    max_retries: Option<u32>,
+    allowed_oracle_keys: Option<Vec<String>>,
+    min_oracle_threshold: Option<usize>,
+    max_total_collateral: Option<u64>,
+    min_refund_delay_seconds: Option<u32>,
+    max_refund_delay_seconds: Option<u32>,
+    max_protocol_fee_basis_points: Option<u64>,
+    api_keys: Option<Vec<String>>,
+    admin_api_keys: Option<Vec<String>>,
+    hmac_keys: Option<HashMap<String, String>>,
+    cors_allowed_origins: Option<Vec<String>>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+struct FileCollateralOverride {
+    min_total_collateral: u64,
+    funding: u32,
+    cet: u32,
+    refund: u32,
+}
+
+/// Validated settings of the router wallet.
+pub struct RouterConfig {
+    pub network: Network,
+    pub blockchain_interface_url: String,
+    pub electrum_api_url: String,
+    pub storage_api_endpoint: String,
+    pub xpriv: ExtendedPrivKey,
+    pub wallet_ip: Ipv4Addr,
+    pub wallet_backend_port: u16,
+    pub check_interval: Duration,
+    /// Number of attestors needed to close a contract, all of them if not set.
+    pub attestor_threshold: Option<u16>,
+    /// API spoken by the attestors, detected from each of them if not set.
+    pub oracle_api: Option<OracleApi>,
+    /// Fee rate of the offered contracts.
+    pub fees: FeeConfig,
+    /// Recipient of the fee output of offers without protocol fee, the router
+    /// wallet if not set.
+    pub default_fee_recipient: Option<Address>,
+    /// Limits of the offered contracts.
+    pub offer_limits: OfferLimits,
+    pub manager: ManagerConfig,
+    pub auth: AuthConfig,
+}
+
+impl RouterConfig {
+    /// Reads the config file and the environment, and validates the result.
+    pub fn load() -> Result<Self, ConfigError> {
+        Self::load_with(&|name| env::var(name).ok())
+    }
+
+    /// As [`RouterConfig::load`], with the environment variables read from
+    /// `lookup`.
+    pub fn load_with(lookup: EnvLookup) -> Result<Self, ConfigError> {
+        let file = match lookup(CONFIG_PATH_ENV) {
+            Some(path) => read_config_file(Path::new(&path))?,
+            None if Path::new(DEFAULT_CONFIG_PATH).exists() => {
+                read_config_file(Path::new(DEFAULT_CONFIG_PATH))?
+            }
+            None => FileConfig::default(),
+        };
+        let config = Self::from_file_config(file, lookup)?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn from_file_config(file: FileConfig, lookup: EnvLookup) -> Result<Self, ConfigError> {
+        let manager = manager_config(&file, lookup)?;
+        let auth = auth_config(&file, lookup)?;
+        let network = parse_network(&required::<String>(
+            lookup,
+            "BITCOIN_NETWORK",
+            file.bitcoin_network,
+        )?)
+        .ok_or_else(|| ConfigError(format!("BITCOIN_NETWORK must be {}", NETWORK_NAMES)))?;
+        let xpriv: ExtendedPrivKey = parse_required(lookup, "XPRIV", file.xpriv)?;
+        let fingerprint: String = required(lookup, "FINGERPRINT", file.fingerprint)?;
+        if fingerprint != xpriv.fingerprint(&Secp256k1::new()).to_string() {
+            return Err(ConfigError(
+                "FINGERPRINT does not match the XPRIV fingerprint, make sure both come from the same `just generate-key` output".to_string(),
+            ));
+        }
+
+        let fee_defaults = FeeRateBounds::for_network(network);
+        let fees = FeeConfig {
+            confirmation_target: setting(
+                lookup,
+                "FEE_CONFIRMATION_TARGET",
+                file.fee_confirmation_target,
+            )?
+            .unwrap_or(6),
+            min_fee_rate: setting(lookup, "MIN_FEE_RATE", file.min_fee_rate)?
+                .unwrap_or(fee_defaults.min_fee_rate),
+            max_fee_rate: setting(lookup, "MAX_FEE_RATE", file.max_fee_rate)?
+                .unwrap_or(fee_defaults.max_fee_rate),
+            fallback_fee_rate: setting(lookup, "FALLBACK_FEE_RATE", file.fallback_fee_rate)?
+                .unwrap_or(fee_defaults.fallback_fee_rate),
+        };
+
+        let default_fee_recipient =
+            match setting(lookup, "DEFAULT_FEE_RECIPIENT", file.default_fee_recipient)? {
+                Some(address) => Some(
+                    parse_address("DEFAULT_FEE_RECIPIENT", &address, network)
+                        .map_err(|e| ConfigError(e.to_string()))?,
+                ),
+                None => None,
+            };
+
+        let oracle_api = match setting(lookup, "ORACLE_API", file.oracle_api)? {
+            Some(api) => Some(api.parse::<OracleApi>().map_err(|_| {
+                ConfigError(
+                    "ORACLE_API must be dlclink or p2pderivatives, or unset to detect it"
+                        .to_string(),
+                )
+            })?),
+            None => None,
+        };
+
+        let limit_defaults = OfferLimits::default();
+        let offer_limits = OfferLimits {
+            min_accept_collateral: setting(
+                lookup,
+                "OFFER_MIN_ACCEPT_COLLATERAL",
+                file.offer_min_accept_collateral,
+            )?
+            .unwrap_or(limit_defaults.min_accept_collateral),
+            max_total_collateral: setting(
+                lookup,
+                "OFFER_MAX_TOTAL_COLLATERAL",
+                file.offer_max_total_collateral,
+            )?,
+            max_fee_basis_points: setting(
+                lookup,
+                "OFFER_MAX_FEE_BASIS_POINTS",
+                file.offer_max_fee_basis_points,
+            )?
+            .unwrap_or(limit_defaults.max_fee_basis_points),
+            max_refund_delay: setting(
+                lookup,
+                "OFFER_MAX_REFUND_DELAY_SECONDS",
+                file.offer_max_refund_delay_seconds,
+            )?
+            .unwrap_or(limit_defaults.max_refund_delay),
+        };
+
+        Ok(RouterConfig {
+            network,
+            blockchain_interface_url: required(
+                lookup,
+                "BLOCKCHAIN_INTERFACE_URL",
+                file.blockchain_interface_url,
+            )?,
+            electrum_api_url: required(lookup, "ELECTRUM_API_URL", file.electrum_api_url)?,
+            storage_api_endpoint: required(
+                lookup,
+                "STORAGE_API_ENDPOINT",
+                file.storage_api_endpoint,
+            )?,
+            xpriv,
+            wallet_ip: setting(lookup, "WALLET_IP", file.wallet_ip)?.unwrap_or(Ipv4Addr::LOCALHOST),
+            wallet_backend_port: setting(lookup, "WALLET_BACKEND_PORT", file.wallet_backend_port)?
+                .unwrap_or(8085),
+            check_interval: Duration::from_secs(
+                setting(
+                    lookup,
+                    "BITCOIN_CHECK_INTERVAL_SECONDS",
+                    file.bitcoin_check_interval_seconds,
+                )?
+                .unwrap_or(60),
+            ),
+            attestor_threshold: setting(lookup, "ATTESTOR_THRESHOLD", file.attestor_threshold)?,
+            oracle_api,
+            fees,
+            default_fee_recipient,
+            offer_limits,
+            manager,
+            auth,
+        })
+    }
+
+    fn validate(&self) -> Result<(), ConfigError> {
+        if self.check_interval.is_zero() {
+            return Err(ConfigError(
+                "BITCOIN_CHECK_INTERVAL_SECONDS must be at least 1".to_string(),
+            ));
+        }
+        if self.attestor_threshold == Some(0) {
+            return Err(ConfigError(
+                "ATTESTOR_THRESHOLD must be at least 1".to_string(),
+            ));
+        }
+        // The periodic check loop calls the admin endpoint with the first key
+        if self.auth.is_enabled() && self.auth.admin_api_keys.is_empty() {
+            return Err(ConfigError(
+                "ADMIN_API_KEYS must be set when authentication is enabled".to_string(),
+            ));
+        }
+        let fees = &self.fees;
+        if fees.confirmation_target == 0 {
+            return Err(ConfigError(
+                "FEE_CONFIRMATION_TARGET must be at least 1".to_string(),
+            ));
+        }
+        if fees.min_fee_rate == 0 || fees.min_fee_rate > fees.max_fee_rate {
+            return Err(ConfigError(
+                "MIN_FEE_RATE must be at least 1 and at most MAX_FEE_RATE".to_string(),
+            ));
+        }
+        if fees.fallback_fee_rate < fees.min_fee_rate || fees.fallback_fee_rate > fees.max_fee_rate
+        {
+            return Err(ConfigError(
+                "FALLBACK_FEE_RATE must be between MIN_FEE_RATE and MAX_FEE_RATE".to_string(),
+            ));
+        }
+        let limits = &self.offer_limits;
+        if limits.min_accept_collateral == 0 {
+            return Err(ConfigError(
+                "OFFER_MIN_ACCEPT_COLLATERAL must be at least 1".to_string(),
+            ));
+        }
+        if limits.max_fee_basis_points > 10_000 {
+            return Err(ConfigError(
+                "OFFER_MAX_FEE_BASIS_POINTS must be at most 10000".to_string(),
+            ));
+        }
+        if limits.max_refund_delay == 0 {
+            return Err(ConfigError(
+                "OFFER_MAX_REFUND_DELAY_SECONDS must be at least 1".to_string(),
+            ));
+        }
+        self.manager
+            .validate()
+            .map_err(|e| ConfigError(e.to_string()))
+    }
+}
+
+fn read_config_file(path: &Path) -> Result<FileConfig, ConfigError> {
+    let contents = fs::read_to_string(path)
+        .map_err(|e| ConfigError(format!("Unable to read {}: {}", path.display(), e)))?;
+    let file: ConfigFile = match path.extension().and_then(|e| e.to_str()) {
+        Some("toml") => toml::from_str(&contents)
+            .map_err(|e| ConfigError(format!("Invalid config file {}: {}", path.display(), e)))?,
+        Some("yaml") | Some("yml") => serde_yaml::from_str(&contents)
+            .map_err(|e| ConfigError(format!("Invalid config file {}: {}", path.display(), e)))?,
+        _ => {
+            return Err(ConfigError(format!(
+                "Config file {} should be a .toml, .yaml or .yml file",
+                path.display()
+            )))
+        }
+    };
+    Ok(file.router_wallet)
+}
+
+// Confirmation depths default to 6 for every transaction. Overrides for large
+// contracts are listed in the file, or set in COLLATERAL_CONFIRMATION_OVERRIDES
+// as comma separated `min_total_collateral:funding:cet:refund` entries.
+fn manager_config(file: &FileConfig, lookup: EnvLookup) -> Result<ManagerConfig, ConfigError> {
+    let defaults = ConfirmationDepths::default();
+    let confirmations = ConfirmationDepths {
+        funding: setting(lookup, "FUNDING_CONFIRMATIONS", file.funding_confirmations)?
+            .unwrap_or(defaults.funding),
+        cet: setting(lookup, "CET_CONFIRMATIONS", file.cet_confirmations)?.unwrap_or(defaults.cet),
+        refund: setting(lookup, "REFUND_CONFIRMATIONS", file.refund_confirmations)?
+            .unwrap_or(defaults.refund),
+    };
+
+    let collateral_overrides = match lookup("COLLATERAL_CONFIRMATION_OVERRIDES") {
+        Some(overrides) => split_list(&overrides)
+            .map(parse_collateral_override)
+            .collect::<Result<_, _>>()?,
+        None => file
+            .collateral_confirmation_overrides
+            .iter()
+            .flatten()
+            .map(|o| CollateralConfirmationDepths {
+                min_total_collateral: o.min_total_collateral,
+                confirmations: ConfirmationDepths {
+                    funding: o.funding,
+                    cet: o.cet,
+                    refund: o.refund,
+                },
+            })
+            .collect(),
+    };
+
+    // Replicas sharing a storage api each need their own CONTRACT_LEASE_OWNER
+    let lease_owner = setting(
+        lookup,
+        "CONTRACT_LEASE_OWNER",
+        file.contract_lease_owner.clone(),
+    )?;
+    let contract_lease = match lease_owner {
+        Some(owner) => Some(ContractLeaseConfig {
+            owner,
+            duration: setting(
+                lookup,
+                "CONTRACT_LEASE_DURATION_SECONDS",
+                file.contract_lease_duration_seconds,
+            )?
+            .unwrap_or(300),
+        }),
+        None => None,
+    };
+
+    // Stuck transactions are only bumped when a target fee rate is set
+    let fee_bump = match setting(lookup, "FEE_BUMP_TARGET_RATE", file.fee_bump_target_rate)? {
+        Some(target_fee_rate) => Some(FeeBumpConfig {
+            stuck_after: setting(
+                lookup,
+                "FEE_BUMP_AFTER_SECONDS",
+                file.fee_bump_after_seconds,
+            )?
+            .unwrap_or(6 * 3600),
+            target_fee_rate,
+        }),
+        None => None,
+    };
+
+    // Offers may only use the attestors we know about unless keys are listed
+    let policy_defaults = OfferAcceptancePolicy::default();
+    let allowed_oracle_keys = match lookup("ALLOWED_ORACLE_KEYS") {
+        Some(keys) => split_list(&keys).map(String::from).collect(),
+        None => file.allowed_oracle_keys.clone().unwrap_or_default(),
+    }
+    .iter()
+    .map(|k| {
+        XOnlyPublicKey::from_str(k).map_err(|_| {
+            ConfigError(format!(
+                "ALLOWED_ORACLE_KEYS entry {} is not an x-only public key",
+                k
+            ))
+        })
+    })
+    .collect::<Result<_, _>>()?;
+    let offer_policy = OfferAcceptancePolicy {
+        allowed_oracle_keys,
+        min_threshold: setting(lookup, "MIN_ORACLE_THRESHOLD", file.min_oracle_threshold)?
+            .unwrap_or(policy_defaults.min_threshold),
+        max_total_collateral: setting(lookup, "MAX_TOTAL_COLLATERAL", file.max_total_collateral)?,
+        min_refund_delay: setting(
+            lookup,
+            "MIN_REFUND_DELAY_SECONDS",
+            file.min_refund_delay_seconds,
+        )?
+        .unwrap_or(policy_defaults.min_refund_delay),
+        max_refund_delay: setting(
+            lookup,
+            "MAX_REFUND_DELAY_SECONDS",
+            file.max_refund_delay_seconds,
+        )?
+        .unwrap_or(policy_defaults.max_refund_delay),
+        max_protocol_fee_basis_points: setting(
+            lookup,
+            "MAX_PROTOCOL_FEE_BASIS_POINTS",
+            file.max_protocol_fee_basis_points,
+        )?,
+    };
+
+    Ok(ManagerConfig {
+        confirmations,
+        collateral_overrides,
+        contract_lease,
+        check_concurrency: setting(lookup, "CHECK_CONCURRENCY", file.check_concurrency)?
+            .unwrap_or(DEFAULT_CHECK_CONCURRENCY),
+        contract_check_timeout: setting(
+            lookup,
+            "CONTRACT_CHECK_TIMEOUT_SECONDS",
+            file.contract_check_timeout_seconds,
+        )?
+        .map(Duration::from_secs),
+        // Offers that are not signed in time are abandoned, never if not set
+        offer_ttl: setting(lookup, "OFFER_TTL_SECONDS", file.offer_ttl_seconds)?,
+        offer_policy,
+        fee_bump,
+        max_retries: setting(lookup, "MAX_RETRIES", file.max_retries)?
+            .unwrap_or(DEFAULT_MAX_RETRIES),
+        // The notifications of the events the process stopped before
+        // handling are recovered from the journal
+        journal_events: true,
+    })
+}
+
+// Authentication is disabled until a key is set. Keys are set in the
+// environment as comma separated lists, HMAC_KEYS as `key_id:secret` entries.
+fn auth_config(file: &FileConfig, lookup: EnvLookup) -> Result<AuthConfig, ConfigError> {
+    let list = |env_name: &str, file_value: &Option<Vec<String>>| match lookup(env_name) {
+        Some(list) => split_list(&list).map(String::from).collect(),
+        None => file_value.clone().unwrap_or_default(),
+    };
+    let hmac_keys = match lookup("HMAC_KEYS") {
+        Some(keys) => split_list(&keys)
+            .map(|entry| match entry.split_once(':') {
+                Some((key_id, secret)) if !key_id.is_empty() && !secret.is_empty() => {
+                    Ok((key_id.to_string(), secret.to_string()))
+                }
+                _ => Err(ConfigError(
+                    "Invalid HMAC_KEYS entry, expected key_id:secret".to_string(),
+                )),
+            })
+            .collect::<Result<_, _>>()?,
+        None => file.hmac_keys.clone().unwrap_or_default(),
+    };
+    let cors_allowed_origins = match lookup("CORS_ALLOWED_ORIGINS") {
+        Some(origins) => split_list(&origins).map(String::from).collect(),
+        None => file
+            .cors_allowed_origins
+            .clone()
+            .unwrap_or_else(|| vec!["*".to_string()]),
+    };
+    Ok(AuthConfig {
+        api_keys: list("API_KEYS", &file.api_keys),
+        admin_api_keys: list("ADMIN_API_KEYS", &file.admin_api_keys),
+        hmac_keys,
+        cors_allowed_origins,
+    })
+}
+
+fn parse_collateral_override(entry: &str) -> Result<CollateralConfirmationDepths, ConfigError> {
+    let invalid = || {
+        ConfigError(format!(
+            "Invalid COLLATERAL_CONFIRMATION_OVERRIDES entry {}, expected min_total_collateral:funding:cet:refund",
+            entry
+        ))
+    };
+    match entry.split(':').collect::<Vec<_>>().as_slice() {
+        [min_total_collateral, funding, cet, refund] => Ok(CollateralConfirmationDepths {
+            min_total_collateral: min_total_collateral.parse().map_err(|_| invalid())?,
+            confirmations: ConfirmationDepths {
+                funding: funding.parse().map_err(|_| invalid())?,
+                cet: cet.parse().map_err(|_| invalid())?,
+                refund: refund.parse().map_err(|_| invalid())?,
+            },
+        }),
+        _ => Err(invalid()),
+    }
+}
+
+fn split_list(list: &str) -> impl Iterator<Item = &str> {
+    list.split(',').map(str::trim).filter(|e| !e.is_empty())
+}
+
+/// Name of the config file key overridden by an env variable.
+fn file_key(env_name: &str) -> String {
+    env_name.to_lowercase().replace('_', "-")
+}
+
+/// The value of the env variable if set, the value from the config file
+/// otherwise.
+fn setting<T: FromStr>(
+    lookup: EnvLookup,
+    env_name: &str,
+    file_value: Option<T>,
+) -> Result<Option<T>, ConfigError> {
+    match lookup(env_name) {
+        // Values are not echoed back as some of them are secrets
+        Some(value) => value.parse::<T>().map(Some).map_err(|_| {
+            ConfigError(format!(
+                "Invalid value for {} environment variable",
+                env_name
+            ))
+        }),
+        None => Ok(file_value),
+    }
+}
+
+fn required<T: FromStr>(
+    lookup: EnvLookup,
+    env_name: &str,
+    file_value: Option<T>,
+) -> Result<T, ConfigError> {
+    setting(lookup, env_name, file_value)?.ok_or_else(|| {
+        ConfigError(format!(
+            "{} is not set, set it in the environment or as {} in the router-wallet section of the config file",
+            env_name,
+            file_key(env_name)
+        ))
+    })
+}
+
+/// A required setting given as a string in the config file.
+fn parse_required<T: FromStr>(
+    lookup: EnvLookup,
+    env_name: &str,
+    file_value: Option<String>,
+) -> Result<T, ConfigError> {
+    required::<String>(lookup, env_name, file_value)?
+        .parse()
+        .map_err(|_| {
+            ConfigError(format!(
+                "Invalid value for {} ({} in the config file)",
+                env_name,
+                file_key(env_name)
+            ))
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn collateral_override_is_parsed() {
+        let depths = parse_collateral_override("100000000:12:6:3").expect("a valid override");
+        assert_eq!(depths.min_total_collateral, 100_000_000);
+        assert_eq!(
+            depths.confirmations,
+            ConfirmationDepths {
+                funding: 12,
+                cet: 6,
+                refund: 3,
+            }
+        );
+    }
+
+    #[test]
+    fn malformed_collateral_override_is_rejected() {
+        for entry in [
+            "",
+            "100000000:12:6",
+            "100000000:12:6:3:1",
+            "a:12:6:3",
+            "1:-1:6:3",
+        ] {
+            assert!(parse_collateral_override(entry).is_err(), "{}", entry);
+        }
+    }
+
+    #[test]
+    fn environment_overrides_the_config_file() {
+        let lookup = |name: &str| (name == "OFFER_TTL_SECONDS").then(|| "600".to_string());
+        let file = FileConfig {
+            offer_ttl_seconds: Some(3600),
+            check_concurrency: Some(3),
+            ..Default::default()
+        };
+        let config = manager_config(&file, &lookup).expect("a valid config");
+
+        assert_eq!(config.offer_ttl, Some(600));
+        // Settings missing from the environment come from the file.
+        assert_eq!(config.check_concurrency, 3);
+    }
+
+    #[test]
+    fn unknown_config_file_keys_are_rejected() {
+        let file: Result<ConfigFile, _> =
+            serde_yaml::from_str("router-wallet:\n    bitcoin-network: regtest\n");
+        assert_eq!(
+            file.expect("a valid config file")
+                .router_wallet
+                .bitcoin_network
+                .as_deref(),
+            Some("regtest")
+        );
+
+        let file: Result<ConfigFile, _> =
+            serde_yaml::from_str("router-wallet:\n    bitcoin-netwrok: regtest\n");
+        assert!(file.is_err());
+    }
+
+    #[test]
+    fn settings_missing_from_the_file_are_read_from_the_lookup() {
+        let path = env::temp_dir().join(format!("router-wallet-{}.yaml", std::process::id()));
+        fs::write(&path, "router-wallet:\n    bitcoin-network: regtest\n")
+            .expect("to write the config file");
+        let config_path = path.to_string_lossy().to_string();
+        let lookup = |name: &str| match name {
+            "CONFIG_LOCATION" => Some(config_path.clone()),
+            "XPRIV" => Some("not an xpriv".to_string()),
+            _ => None,
+        };
+
+        let error = RouterConfig::load_with(&lookup)
+            .err()
+            .expect("an invalid config");
+        fs::remove_file(&path).expect("to remove the config file");
+
+        assert!(
+            error.to_string().contains("Invalid value for XPRIV"),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn network_names_are_parsed() {
+        assert_eq!(parse_network("bitcoin"), Some(Network::Bitcoin));
+        assert_eq!(parse_network("regtest"), Some(Network::Regtest));
+        assert_eq!(parse_network("mainnet"), None);
+    }
+}
@@ -0,0 +1,79 @@
+//! Fee rate of the offered contracts, estimated by Esplora for a
+//! confirmation target and kept within configured bounds.
+
+use std::sync::Arc;
+
+use dlc_link_manager::FeeRateBounds;
+use esplora_async_blockchain_provider_router_wallet::EsploraAsyncBlockchainProviderRouterWallet;
+use tracing::{debug, warn};
+
+/// Fee rate settings, in sats/vbyte.
+#[derive(Clone, Copy, Debug)]
+pub struct FeeConfig {
+    /// Number of blocks the funding transaction should confirm within.
+    pub confirmation_target: u16,
+    /// Estimates are raised to this rate.
+    pub min_fee_rate: u64,
+    /// Estimates are lowered to this rate.
+    pub max_fee_rate: u64,
+    /// Rate used when the estimation fails.
+    pub fallback_fee_rate: u64,
+}
+
+impl FeeConfig {
+    /// Bounds of the fee rates the blockchain provider estimates for the
+    /// DLC manager.
+    pub fn bounds(&self) -> FeeRateBounds {
+        FeeRateBounds {
+            min_fee_rate: self.min_fee_rate,
+            max_fee_rate: self.max_fee_rate,
+            fallback_fee_rate: self.fallback_fee_rate,
+        }
+    }
+}
+
+pub struct FeeRateEstimator {
+    blockchain: Arc<EsploraAsyncBlockchainProviderRouterWallet>,
+    config: FeeConfig,
+}
+
+impl FeeRateEstimator {
+    pub fn new(
+        blockchain: Arc<EsploraAsyncBlockchainProviderRouterWallet>,
+        config: FeeConfig,
+    ) -> Self {
+        FeeRateEstimator { blockchain, config }
+    }
+
+    /// Returns the fee rate of a new offer. A rate requested by the caller is
+    /// used as is, the offer validation checks it is within the bounds.
+    pub async fn offer_fee_rate(&self, requested: Option<u64>) -> u64 {
+        let config = &self.config;
+        if let Some(fee_rate) = requested {
+            return fee_rate;
+        }
+
+        match self
+            .blockchain
+            .get_fee_rate_estimate(config.confirmation_target)
+            .await
+        {
+            Ok(estimate) => {
+                let fee_rate =
+                    (estimate.ceil() as u64).clamp(config.min_fee_rate, config.max_fee_rate);
+                debug!(
+                    "Estimated fee rate of {} sats/vbyte for {} blocks, using {}",
+                    estimate, config.confirmation_target, fee_rate
+                );
+                fee_rate
+            }
+            Err(e) => {
+                warn!(
+                    "Fee rate estimation failed, using the fallback of {} sats/vbyte: {}",
+                    config.fallback_fee_rate, e
+                );
+                config.fallback_fee_rate
+            }
+        }
+    }
+}
@@ -5,11 +5,13 @@
 #![allow(clippy::too_many_arguments)]
 
 use bitcoin::util::bip32::{ChildNumber, DerivationPath, ExtendedPrivKey, ExtendedPubKey};
-use bytes::Buf;
+use bytes::{Buf, Bytes};
 
 use futures_util::future::join_all;
+use futures_util::{stream, Stream, StreamExt};
+use hyper::body::HttpBody;
 use hyper::service::{make_service_fn, service_fn};
-use hyper::{header, Body, Method, Response, Server, StatusCode};
+use hyper::{header, Body, Method, Request, Response, Server, StatusCode};
 
 use bdk::descriptor;
 use secp256k1_zkp::SecretKey;
@@ -17,33 +19,49 @@ use serde::{Deserialize, Serialize};
 use tokio::{task, time};
 
 use core::panic;
-use std::net::Ipv4Addr;
 use std::time::Duration;
-use std::{collections::HashMap, env, str::FromStr, sync::Arc};
+use std::{collections::HashMap, str::FromStr, sync::Arc};
 
-use bitcoin::{Address, PublicKey, XOnlyPublicKey};
+use bitcoin::{PublicKey, Txid, XOnlyPublicKey};
 
-use dlc_link_manager::{AsyncOracle, AsyncStorage, Manager, ONE_DAY_IN_SECONDS};
+use dlc_link_manager::{
+    AsyncOracle, AsyncStorage, ContractEvent, ContractState, Manager, SignedEnvelope,
+};
 use dlc_manager::{
     contract::{
         contract_input::{ContractInput, ContractInputInfo, OracleInput},
         Contract,
     },
-    SystemTimeProvider,
+    ContractId, SystemTimeProvider,
 };
-use dlc_messages::{AcceptDlc, Message};
+use dlc_messages::Message;
 use dlc_wallet::DlcWallet;
 use esplora_async_blockchain_provider_router_wallet::EsploraAsyncBlockchainProviderRouterWallet;
 use tracing::{debug, error, info, warn};
 
-use attestor_client::AttestorClient;
+use attestor_client::{AttestorClient, OracleApi};
 use dlc_clients::async_storage_provider::AsyncStorageApiProvider;
 use serde_json::json;
 use std::fmt::{self, Write as _};
 
+use auth::{Auth, AuthError, API_KEY_HEADER};
+use config::RouterConfig;
+use fees::FeeRateEstimator;
+use outbox::{NotificationKind, Outbox};
+use reservation::OfferReservations;
 use utils::get_numerical_contract_info;
+use validation::{
+    Adjustment, FieldErrors, OfferRequest, OfferValidator, UpstreamError, ValidOffer,
+};
 
+mod auth;
+mod config;
+mod fees;
+mod network;
+mod outbox;
+mod reservation;
 mod utils;
+mod validation;
 #[macro_use]
 mod macros;
 
@@ -66,17 +84,19 @@ type DlcManager<'a> = Manager<
     Arc<SystemTimeProvider>,
 >;
 
-// The contracts in dlc-manager expect a node id, but web extensions often don't have this, so hardcode it for now. Should not have any ramifications.
-const STATIC_COUNTERPARTY_NODE_ID: &str =
-    "02fc8e97419286cf05e5d133f41ff6d51f691dda039e9dc007245a421e2c7ec61c";
-
 const REQWEST_TIMEOUT: Duration = Duration::from_secs(30);
+/// Largest request body read, accept messages of contracts with many CETs
+/// being the largest legitimate ones.
+const MAX_REQUEST_BODY_BYTES: usize = 8 * 1024 * 1024;
 
 #[derive(Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 struct ErrorResponse {
     message: String,
     code: Option<u64>,
+    /// The request field the error is about.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    field: Option<String>,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -86,6 +106,16 @@ struct ErrorsResponse {
     status: u64,
 }
 
+/// The signed offer, along with the values of the request that were adjusted
+/// rather than rejected.
+#[derive(Serialize)]
+struct OfferResponse {
+    #[serde(flatten)]
+    envelope: SignedEnvelope,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    adjustments: Vec<Adjustment>,
+}
+
 pub fn to_oracle_error<T>(e: T) -> dlc_manager::error::Error
 where
     T: std::fmt::Display,
@@ -119,8 +149,13 @@ async fn get_chain_from_attestors(
     attestors: HashMap<XOnlyPublicKey, Arc<AttestorClient>>,
     uuid: String,
 ) -> Result<String, GenericError> {
-    let attestors_with_uuid: Vec<((&XOnlyPublicKey, &Arc<AttestorClient>), String)> =
-        attestors.iter().map(|x| (x, uuid.clone())).collect();
+    // Only DLC.Link attestors know which chain an event belongs to, public
+    // p2pderivatives oracles mixed into the contract are left out.
+    let attestors_with_uuid: Vec<((&XOnlyPublicKey, &Arc<AttestorClient>), String)> = attestors
+        .iter()
+        .filter(|(_k, v)| v.api() == OracleApi::DlcLink)
+        .map(|x| (x, uuid.clone()))
+        .collect();
     let chains = join_all(
         attestors_with_uuid
             .iter()
@@ -153,12 +188,13 @@ async fn get_chain_from_attestors(
 
 async fn generate_attestor_client(
     attestor_urls: Vec<String>,
+    oracle_api: Option<OracleApi>,
 ) -> HashMap<XOnlyPublicKey, Arc<AttestorClient>> {
     let mut attestor_clients = HashMap::new();
 
     for url in attestor_urls.iter() {
         let p2p_client = match retry!(
-            AttestorClient::new(url).await,
+            AttestorClient::new_with_api(url, oracle_api).await,
             10,
             "attestor client creation",
             6
@@ -177,26 +213,28 @@ fn build_success_response(message: String) -> Result<Response<Body>, GenericErro
     Ok(Response::builder()
         .status(StatusCode::OK)
         .header(header::CONTENT_TYPE, "application/json")
-        .header(header::ACCESS_CONTROL_ALLOW_ORIGIN, "*")
-        .header(header::ACCESS_CONTROL_ALLOW_METHODS, "*")
-        .header(header::ACCESS_CONTROL_ALLOW_HEADERS, "*")
         .body(Body::from(message.to_string()))?)
 }
 
 fn build_error_response(message: String) -> Result<Response<Body>, GenericError> {
+    build_error_response_with_status(StatusCode::BAD_REQUEST, message)
+}
+
+fn build_error_response_with_status(
+    status: StatusCode,
+    message: String,
+) -> Result<Response<Body>, GenericError> {
     Ok(Response::builder()
-        .status(StatusCode::BAD_REQUEST)
-        .header(header::ACCESS_CONTROL_ALLOW_ORIGIN, "*")
-        .header(header::ACCESS_CONTROL_ALLOW_METHODS, "*")
-        .header(header::ACCESS_CONTROL_ALLOW_HEADERS, "*")
+        .status(status)
         .header(header::CONTENT_TYPE, "application/json")
         .body(Body::from(
             json!(
                 {
-                    "status": StatusCode::BAD_REQUEST.as_u16(),
+                    "status": status.as_u16(),
                     "errors": vec![ErrorResponse {
                         message: message.to_string(),
                         code: None,
+                        field: None,
                     }],
                 }
             )
@@ -204,19 +242,110 @@ fn build_error_response(message: String) -> Result<Response<Body>, GenericError>
         ))?)
 }
 
+fn build_field_errors_response(errors: &FieldErrors) -> Result<Response<Body>, GenericError> {
+    let response = ErrorsResponse {
+        errors: errors
+            .0
+            .iter()
+            .map(|error| ErrorResponse {
+                message: error.message.clone(),
+                code: None,
+                field: Some(error.field.clone()),
+            })
+            .collect(),
+        status: StatusCode::BAD_REQUEST.as_u16() as u64,
+    };
+    Ok(Response::builder()
+        .status(StatusCode::BAD_REQUEST)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(serde_json::to_string(&response)?))?)
+}
+
+// Checks the credentials of the request before handing it to
+// `process_request`, and sets the CORS headers of the response.
+async fn handle_request(
+    req: Request<Body>,
+    auth: Arc<Auth>,
+    manager: Arc<DlcManager<'_>>,
+    dlc_store: Arc<AsyncStorageApiProvider>,
+    wallet: Arc<DlcWallet>,
+    outbox: Arc<Outbox>,
+    offer_validator: Arc<OfferValidator>,
+    fee_rates: Arc<FeeRateEstimator>,
+    node_secret_key: SecretKey,
+) -> Result<Response<Body>, GenericError> {
+    let origin = req.headers().get(header::ORIGIN).cloned();
+    let (parts, body) = req.into_parts();
+    let (method, path) = (parts.method.clone(), parts.uri.path().to_string());
+    let reject = |e: AuthError| {
+        warn!("Rejected {} {} - {}", method, path, e);
+        build_error_response_with_status(e.status(), e.to_string())
+    };
+    // The headers are checked before the body is read, so that callers
+    // without credentials cannot make us buffer it.
+    let mut response = match auth.authorize(&parts) {
+        Err(e) => reject(e)?,
+        Ok(pending_signature) => match read_body(body, MAX_REQUEST_BODY_BYTES).await? {
+            None => build_error_response_with_status(
+                StatusCode::PAYLOAD_TOO_LARGE,
+                format!("Request body exceeds {} bytes", MAX_REQUEST_BODY_BYTES),
+            )?,
+            // Signed requests cover the body
+            Some(body) => match pending_signature
+                .map(|pending| auth.verify_signature(&parts, &body, pending))
+            {
+                Some(Err(e)) => reject(e)?,
+                _ => {
+                    process_request(
+                        Request::from_parts(parts, Body::from(body)),
+                        manager,
+                        dlc_store,
+                        wallet,
+                        outbox,
+                        offer_validator,
+                        fee_rates,
+                        node_secret_key,
+                    )
+                    .await?
+                }
+            },
+        },
+    };
+    auth.apply_cors(origin.as_ref(), response.headers_mut());
+    Ok(response)
+}
+
+/// Reads a request body, `None` if it is larger than `limit` bytes.
+async fn read_body(mut body: Body, limit: usize) -> Result<Option<Bytes>, GenericError> {
+    if body.size_hint().lower() > limit as u64 {
+        return Ok(None);
+    }
+    let mut bytes = Vec::new();
+    while let Some(chunk) = body.data().await {
+        let chunk = chunk?;
+        if bytes.len() + chunk.len() > limit {
+            return Ok(None);
+        }
+        bytes.extend_from_slice(&chunk);
+    }
+    Ok(Some(Bytes::from(bytes)))
+}
+
 async fn process_request(
-    req: hyper::Request<hyper::Body>,
+    req: Request<Body>,
     manager: Arc<DlcManager<'_>>,
     dlc_store: Arc<AsyncStorageApiProvider>,
     wallet: Arc<DlcWallet>,
-    active_network: String,
-    blockchain_interface_url: String,
+    outbox: Arc<Outbox>,
+    offer_validator: Arc<OfferValidator>,
+    fee_rates: Arc<FeeRateEstimator>,
+    node_secret_key: SecretKey,
 ) -> Result<Response<Body>, GenericError> {
     match (req.method(), req.uri().path()) {
         (&Method::GET, "/health") => build_success_response(
             json!({"data": [{"status": "healthy", "message": ""}]}).to_string(),
         ),
-        (&Method::GET, "/info") => get_wallet_info(dlc_store, wallet).await,
+        (&Method::GET, "/info") => get_wallet_info(dlc_store, wallet, node_secret_key).await,
         (&Method::GET, path) if path.starts_with("/get_chain/") => {
             let event_id = path.trim_start_matches("/get_chain/").to_string();
             info!("Getting chain for event id {}", event_id);
@@ -237,8 +366,7 @@ async fn process_request(
             }
         }
         (&Method::GET, "/periodic_check") => {
-            let result =
-                async { periodic_check(manager, dlc_store, blockchain_interface_url).await };
+            let result = async { periodic_check(manager).await };
             match result.await {
                 Ok(_) => (),
                 Err(e) => {
@@ -248,19 +376,63 @@ async fn process_request(
             };
             build_success_response("Periodic check complete".to_string())
         }
+        (&Method::GET, path) if path.starts_with("/contracts/") => {
+            let id = path.trim_start_matches("/contracts/");
+            match get_contract_details(id, manager).await {
+                Ok(Some(details)) => build_success_response(details),
+                Ok(None) => build_error_response_with_status(
+                    StatusCode::NOT_FOUND,
+                    format!("No contract found for {}", id),
+                ),
+                Err(e) => {
+                    warn!("Error getting contract {} - {}", id, e);
+                    build_error_response(e.to_string())
+                }
+            }
+        }
+        (&Method::POST, path) if path.starts_with("/contracts/") && path.ends_with("/retry") => {
+            let contract_id = path
+                .trim_start_matches("/contracts/")
+                .trim_end_matches("/retry")
+                .to_string();
+            info!("Retrying failed contract {}", contract_id);
+            match retry_failed_contract(&contract_id, manager, node_secret_key).await {
+                Ok(message) => build_success_response(message),
+                Err(e) => {
+                    warn!("Error retrying contract {} - {}", contract_id, e);
+                    build_error_response(e.to_string())
+                }
+            }
+        }
+        (&Method::GET, "/notifications") => match outbox.list().await {
+            Ok(notifications) => build_success_response(serde_json::to_string(&notifications)?),
+            Err(e) => {
+                warn!("Error listing notifications - {}", e);
+                build_error_response(e.to_string())
+            }
+        },
+        (&Method::POST, path)
+            if path.starts_with("/notifications/") && path.ends_with("/replay") =>
+        {
+            let id = path
+                .trim_start_matches("/notifications/")
+                .trim_end_matches("/replay");
+            match outbox.replay(id).await {
+                Ok(Some(notification)) => {
+                    build_success_response(serde_json::to_string(&notification)?)
+                }
+                Ok(None) => build_error_response_with_status(
+                    StatusCode::NOT_FOUND,
+                    format!("No notification found for {}", id),
+                ),
+                Err(e) => {
+                    warn!("Error replaying notification {} - {}", id, e);
+                    build_error_response(e.to_string())
+                }
+            }
+        }
         (&Method::OPTIONS, "/offer") => build_success_response("".to_string()),
         (&Method::POST, "/offer") => {
-            #[derive(Deserialize)]
-            #[serde(rename_all = "camelCase")]
-            struct OfferRequest {
-                uuid: String,
-                accept_collateral: u64,
-                offer_collateral: u64,
-                total_outcomes: u64,
-                refund_delay: u32,
-                btc_fee_recipient: String,
-                btc_fee_basis_points: u64,
-            }
             let result = async {
                 let attestors: HashMap<XOnlyPublicKey, Arc<AttestorClient>> = manager
                     .oracles
@@ -279,25 +451,43 @@ async fn process_request(
                         ))
                     })?;
 
-                create_new_offer(
-                    manager,
-                    attestors,
-                    active_network,
-                    req.uuid,
-                    req.accept_collateral,
-                    req.offer_collateral,
-                    req.total_outcomes,
-                    req.refund_delay,
-                    req.btc_fee_recipient,
-                    req.btc_fee_basis_points,
-                )
-                .await
+                let (offer, adjustments) = offer_validator.validate(req, &manager).await?;
+                for adjustment in &adjustments {
+                    info!(
+                        "Offer for {} adjusted {} from {} to {}",
+                        offer.uuid, adjustment.field, adjustment.requested, adjustment.applied
+                    );
+                }
+                let fee_rate = fee_rates.offer_fee_rate(offer.fee_rate).await;
+
+                let uuid = offer.uuid.clone();
+                let envelope =
+                    match create_new_offer(manager, attestors, node_secret_key, offer, fee_rate)
+                        .await
+                    {
+                        Ok(envelope) => envelope,
+                        Err(e) => {
+                            offer_validator.release(&uuid).await;
+                            return Err(e.into());
+                        }
+                    };
+                let response = OfferResponse {
+                    envelope,
+                    adjustments,
+                };
+                Ok::<_, GenericError>(serde_json::to_string(&response)?)
             };
             match result.await {
                 Ok(offer_message) => build_success_response(offer_message),
                 Err(e) => {
                     warn!("Error generating offer - {}", e);
-                    build_error_response(e.to_string())
+                    if let Some(field_errors) = e.downcast_ref::<FieldErrors>() {
+                        build_field_errors_response(field_errors)
+                    } else if e.is::<UpstreamError>() {
+                        build_error_response_with_status(StatusCode::BAD_GATEWAY, e.to_string())
+                    } else {
+                        build_error_response(e.to_string())
+                    }
                 }
             }
         }
@@ -314,8 +504,8 @@ async fn process_request(
                     accept_message: String,
                 }
                 let data: AcceptOfferRequest = serde_json::from_reader(whole_body.reader())?;
-                let accept_dlc: AcceptDlc = serde_json::from_str(&data.accept_message)?;
-                accept_offer(accept_dlc, manager).await
+                let envelope: SignedEnvelope = serde_json::from_str(&data.accept_message)?;
+                accept_offer(envelope, manager, node_secret_key).await
             };
             match result.await {
                 Ok(sign_message) => build_success_response(sign_message),
@@ -338,35 +528,35 @@ async fn process_request(
 async fn main() -> Result<(), GenericError> {
     tracing_subscriber::fmt::init();
 
-    let wallet_backend_port: String = env::var("WALLET_BACKEND_PORT").unwrap_or("8085".to_string());
-    let wallet_ip: Ipv4Addr = env::var("WALLET_IP")
-        .unwrap_or("127.0.0.1".to_string())
-        .parse()
-        .unwrap_or(Ipv4Addr::new(127, 0, 0, 1));
+    let config = match RouterConfig::load() {
+        Ok(config) => config,
+        Err(e) => {
+            error!("{}\n\nExiting...", e);
+            return Err(e.into());
+        }
+    };
+    let wallet_ip = config.wallet_ip;
+    let wallet_backend_port = config.wallet_backend_port;
+    let check_interval = config.check_interval;
     debug!("Wallet IP: {}", wallet_ip);
 
-    task::spawn(async {
-        let wallet_backend_port: String =
-            env::var("WALLET_BACKEND_PORT").unwrap_or("8085".to_string());
-        let wallet_ip: Ipv4Addr = env::var("WALLET_IP")
-            .unwrap_or("127.0.0.1".to_string())
-            .parse()
-            .unwrap_or(Ipv4Addr::new(127, 0, 0, 1));
-        let bitcoin_check_interval_seconds: u64 = env::var("BITCOIN_CHECK_INTERVAL_SECONDS")
-            .unwrap_or("60".to_string())
-            .parse::<u64>()
-            .unwrap_or(60);
+    if !config.auth.is_enabled() {
+        warn!("No API keys are configured, the HTTP endpoints are not authenticated");
+    }
+    let admin_api_key = config.auth.admin_api_keys.first().cloned();
+    let auth = Arc::new(Auth::new(config.auth));
+
+    task::spawn(async move {
         loop {
-            time::sleep(Duration::from_secs(bitcoin_check_interval_seconds)).await;
-            match reqwest::Client::new()
-                .get(format!(
-                    "http://{}:{}/periodic_check",
-                    wallet_ip, wallet_backend_port
-                ))
-                .timeout(REQWEST_TIMEOUT)
-                .send()
-                .await
-            {
+            time::sleep(check_interval).await;
+            let mut request = reqwest::Client::new().get(format!(
+                "http://{}:{}/periodic_check",
+                wallet_ip, wallet_backend_port
+            ));
+            if let Some(admin_api_key) = &admin_api_key {
+                request = request.header(API_KEY_HEADER, admin_api_key);
+            }
+            match request.timeout(REQWEST_TIMEOUT).send().await {
                 Ok(_) => (),
                 Err(e) => {
                     warn!("Error running periodic check: {}, will retry", e);
@@ -375,43 +565,22 @@ async fn main() -> Result<(), GenericError> {
         }
     });
 
-    let blockchain_interface_url = env::var("BLOCKCHAIN_INTERFACE_URL")
-        .expect("BLOCKCHAIN_INTERFACE_URL environment variable not set, couldn't get attestors");
+    let blockchain_interface_url = config.blockchain_interface_url;
     debug!("Blockchain interface url: {}", blockchain_interface_url);
-    let xpriv_str = env::var("XPRIV")
-    .expect("XPRIV environment variable not set, please run `just generate-key`, securely backup the output, and set this env_var accordingly");
-    let xpriv = ExtendedPrivKey::from_str(&xpriv_str).expect("Unable to decode xpriv env variable");
-    let fingerprint = env::var("FINGERPRINT")
-    .expect("FINGERPRINT environment variable not set, please run `just generate-key`, securely backup the output, and set this env_var accordingly");
-    if fingerprint
-        != xpriv
-            .fingerprint(&bitcoin::secp256k1::Secp256k1::new())
-            .to_string()
-    {
-        error!("Fingerprint does not match xpriv fingerprint! Please make sure you have the correct xpriv and fingerprint set in your env variables\n\nExiting...");
-        return Err(GenericError::from("Fingerprint does not match xpriv fingerprint! Please make sure you have the correct xpriv and fingerprint set in your env variables\n\nExiting..."));
-    }
-
-    let storage_api_url = env::var("STORAGE_API_ENDPOINT")
-        .expect("STORAGE_API_ENDPOINT environment variable not set");
-    let electrs_host =
-        env::var("ELECTRUM_API_URL").expect("ELECTRUM_API_URL environment variable not set"); // Set up Blockchain Connection Object
-    let active_network: bitcoin::Network = match env::var("BITCOIN_NETWORK").as_deref() {
-        Ok("bitcoin") => bitcoin::Network::Bitcoin,
-        Ok("testnet") => bitcoin::Network::Testnet,
-        Ok("signet") => bitcoin::Network::Signet,
-        Ok("regtest") => bitcoin::Network::Regtest,
-        _ => panic!(
-            "Unknown Bitcoin Network, make sure to set BITCOIN_NETWORK in your env variables"
-        ),
-    };
+    let active_network = config.network;
 
     // ELECTRUM / ELECTRS
     let blockchain = Arc::new(EsploraAsyncBlockchainProviderRouterWallet::new(
-        electrs_host.to_string(),
+        config.electrum_api_url,
         active_network,
+        config.fees.bounds(),
     ));
-    let (pubkey, wallet, secret_key) = setup_wallets(xpriv, active_network);
+    let (pubkey, wallet, secret_key) = setup_wallets(config.xpriv, active_network);
+    // The fee output of offers without protocol fee goes to our own wallet
+    // unless configured otherwise
+    let default_fee_recipient = config
+        .default_fee_recipient
+        .unwrap_or_else(|| wallet.address.clone());
 
     // Set up Attestor Clients
     let attestor_urls: Vec<String> = match retry!(
@@ -425,7 +594,37 @@ async fn main() -> Result<(), GenericError> {
             panic!("Error getting attestors: {}", e);
         }
     };
-    let protocol_wallet_attestors = generate_attestor_client(attestor_urls.clone()).await;
+    let protocol_wallet_attestors =
+        generate_attestor_client(attestor_urls.clone(), config.oracle_api).await;
+
+    let attestor_threshold = config.attestor_threshold;
+    if let Some(threshold) = attestor_threshold {
+        if threshold as usize > protocol_wallet_attestors.len() {
+            panic!(
+                "ATTESTOR_THRESHOLD of {} is invalid for {} attestors",
+                threshold,
+                protocol_wallet_attestors.len()
+            );
+        }
+    }
+
+    let fee_rates = Arc::new(FeeRateEstimator::new(blockchain.clone(), config.fees));
+    let offer_validator = Arc::new(OfferValidator::new(
+        config.offer_limits,
+        config.fees,
+        active_network,
+        default_fee_recipient,
+        attestor_threshold,
+        OfferReservations::new(
+            config.storage_api_endpoint.clone(),
+            pubkey.to_string(),
+            secret_key,
+        ),
+    ));
+    info!("Offer limits: {:?}", config.offer_limits);
+    info!("Offer fee rates: {:?}", config.fees);
+    let manager_config = config.manager;
+    info!("Confirmation depths: {:?}", manager_config);
 
     match retry!(
         blockchain.blockchain.get_height().await,
@@ -445,7 +644,7 @@ async fn main() -> Result<(), GenericError> {
     let dlc_store = Arc::new(AsyncStorageApiProvider::new(
         pubkey.to_string(),
         secret_key,
-        storage_api_url,
+        config.storage_api_endpoint.clone(),
     ));
 
     // Set up time provider
@@ -456,35 +655,75 @@ async fn main() -> Result<(), GenericError> {
         dlc_store.clone(),
         Some(protocol_wallet_attestors),
         Arc::new(time_provider),
+        manager_config,
     )?);
 
+    // Contracts stored before they were indexed by event id, or whose index
+    // failed to be written, are indexed before serving lookups by uuid.
+    match manager.index_contract_event_ids().await {
+        Ok(0) => {}
+        Ok(count) => info!("Indexed {} contract event ids", count),
+        Err(e) => warn!("Error indexing the contracts by event id: {}", e),
+    }
+
+    let outbox = Arc::new(Outbox::new(
+        config.storage_api_endpoint,
+        pubkey.to_string(),
+        secret_key,
+        blockchain_interface_url,
+        manager.oracles.clone().unwrap_or_default(),
+    ));
+    task::spawn(outbox.clone().run(check_interval));
+    // Subscribed to before reading the journal, so that no event is missed
+    // in between. Events both in the journal and received are queued once.
+    let events = manager.subscribe();
+    let journaled_events = match dlc_store.get_journaled_contract_events().await {
+        Ok(journaled_events) => journaled_events,
+        Err(e) => {
+            warn!("Error reading the journal of contract events: {}", e);
+            Vec::new()
+        }
+    };
+    if !journaled_events.is_empty() {
+        info!(
+            "Handling {} contract events left in the journal",
+            journaled_events.len()
+        );
+    }
+    task::spawn(notify_contract_events(
+        stream::iter(journaled_events).chain(events),
+        outbox.clone(),
+        dlc_store.clone(),
+    ));
+
     let new_service = make_service_fn(move |_| {
         // For each connection, clone the counter to use in our service...
         let manager = manager.clone();
         let dlc_store = dlc_store.clone();
         let wallet = wallet.clone();
-        let blockchain_interface_url = blockchain_interface_url.clone();
-        let active_network = active_network.to_string();
+        let outbox = outbox.clone();
+        let fee_rates = fee_rates.clone();
+        let offer_validator = offer_validator.clone();
+        let auth = auth.clone();
 
         async move {
             Ok::<_, GenericError>(service_fn(move |req| {
-                process_request(
+                handle_request(
                     req,
+                    auth.to_owned(),
                     manager.to_owned(),
                     dlc_store.to_owned(),
                     wallet.to_owned(),
-                    active_network.to_owned(),
-                    blockchain_interface_url.to_owned(),
+                    outbox.to_owned(),
+                    offer_validator.to_owned(),
+                    fee_rates.to_owned(),
+                    secret_key,
                 )
             }))
         }
     });
 
-    let addr = (
-        wallet_ip,
-        wallet_backend_port.parse().expect("Correct port value"),
-    )
-        .into();
+    let addr = (wallet_ip, wallet_backend_port).into();
 
     let server = Server::bind(&addr).serve(new_service);
 
@@ -551,110 +790,138 @@ fn setup_wallets(
 async fn create_new_offer(
     manager: Arc<DlcManager<'_>>,
     attestors: HashMap<XOnlyPublicKey, Arc<AttestorClient>>,
-    active_network: String,
-    event_id: String,
-    accept_collateral: u64,
-    offer_collateral: u64,
-    total_outcomes: u64,
-    refund_delay: u32,
-    btc_fee_recipient: String,
-    btc_fee_basis_points: u64,
-) -> Result<String, WalletError> {
-    let active_network = bitcoin::Network::from_str(&active_network)
-        .map_err(|e| WalletError(format!("Unknown Network in offer creation: {}", e)))?;
+    node_secret_key: SecretKey,
+    offer: ValidOffer,
+    fee_rate: u64,
+) -> Result<SignedEnvelope, WalletError> {
     let (_event_descriptor, descriptor) = get_numerical_contract_info(
-        accept_collateral,
-        offer_collateral,
-        total_outcomes,
+        offer.accept_collateral,
+        offer.offer_collateral,
+        offer.total_outcomes,
         attestors.len(),
+        offer.threshold,
     )
     .map_err(|e| WalletError(e.to_string()))?;
     info!(
-        "Creating new offer with event id: {}, accept collateral: {}, offer_collateral: {}",
-        event_id.clone(),
-        accept_collateral,
-        offer_collateral
+        "Creating new offer with event id: {}, accept collateral: {}, offer_collateral: {}, threshold: {}/{}, fee rate: {}",
+        offer.uuid,
+        offer.accept_collateral,
+        offer.offer_collateral,
+        offer.threshold,
+        attestors.len(),
+        fee_rate
     );
 
-    let public_keys = attestors.clone().into_keys().collect();
+    let public_keys = attestors.into_keys().collect();
     let contract_info = ContractInputInfo {
         oracles: OracleInput {
             public_keys,
-            event_id: event_id.clone(),
-            threshold: attestors.len() as u16,
+            event_id: offer.uuid,
+            threshold: offer.threshold,
         },
         contract_descriptor: descriptor,
     };
 
-    // Some regtest networks have an unreliable fee estimation service
-    let fee_rate = match active_network {
-        bitcoin::Network::Regtest => 1,
-        _ => 400,
-    };
-
     let contract_input = ContractInput {
-        offer_collateral,
-        accept_collateral,
+        offer_collateral: offer.offer_collateral,
+        accept_collateral: offer.accept_collateral,
         fee_rate,
         contract_infos: vec![contract_info],
     };
 
-    const TEN_DAYS: u32 = ONE_DAY_IN_SECONDS * 10;
-    let adjusted_refund_delay = match refund_delay {
-        // 0 => FIFTY_YEARS - ONE_DAY_IN_SECONDS,
-        0 => TEN_DAYS,
-        TEN_DAYS..=u32::MAX => TEN_DAYS,
-        _ => refund_delay,
-    };
-
-    let fee_address_string = if btc_fee_basis_points > 0 {
-        btc_fee_recipient
-    } else {
-        "bcrt1qvgkz8m4m73kly4xhm28pcnv46n6u045lfq9ta3".to_string()
-    };
-
-    let fee_address = Address::from_str(&fee_address_string)
-        .map_err(|e| WalletError(format!("Error parsing fee address: {}", e)))?;
-
-    let man = manager;
-
-    let offer = man
+    let offer_message = manager
         .send_offer(
             &contract_input,
-            STATIC_COUNTERPARTY_NODE_ID
-                .parse()
-                .expect("To be able to parse the static counterparty id to a pubkey"),
-            adjusted_refund_delay,
-            btc_fee_basis_points,
-            fee_address,
+            offer.counter_party,
+            offer.refund_delay,
+            offer.fee_basis_points,
+            offer.fee_recipient,
         )
         .await
         .map_err(|e| WalletError(e.to_string()))?;
-    serde_json::to_string(&offer).map_err(|e| WalletError(e.to_string()))
+    SignedEnvelope::seal(
+        &secp256k1_zkp::Secp256k1::signing_only(),
+        &Message::Offer(offer_message),
+        &node_secret_key,
+    )
+    .map_err(|e| WalletError(e.to_string()))
 }
 
 async fn accept_offer(
-    accept_dlc: AcceptDlc,
+    envelope: SignedEnvelope,
     manager: Arc<DlcManager<'_>>,
+    node_secret_key: SecretKey,
 ) -> Result<String, GenericError> {
-    let dlc = manager
-        .on_dlc_message(
-            &Message::Accept(accept_dlc),
-            STATIC_COUNTERPARTY_NODE_ID
-                .parse()
-                .expect("To be able to parse the static counterparty id to a pubkey"),
-        )
-        .await?;
+    // Only the counterparty the offer was made to can accept it.
+    let dlc = manager.on_signed_dlc_message(&envelope).await?;
 
     match dlc {
-        Some(Message::Sign(sign)) => serde_json::to_string(&sign).map_err(|e| e.into()),
+        Some(sign @ Message::Sign(_)) => {
+            let envelope = SignedEnvelope::seal(
+                &secp256k1_zkp::Secp256k1::signing_only(),
+                &sign,
+                &node_secret_key,
+            )?;
+            serde_json::to_string(&envelope).map_err(|e| e.into())
+        }
         _ => Err("Error: invalid Sign message for accept_offer function".into()),
     }
 }
 
+fn parse_contract_id(contract_id: &str) -> Result<ContractId, GenericError> {
+    let bytes = hex::decode(contract_id.trim_start_matches("0x"))?;
+    bytes
+        .try_into()
+        .map_err(|_| WalletError(format!("Invalid contract id: {}", contract_id)).into())
+}
+
+// Looks the contract up by id, then by oracle event uuid, as uuids are hex
+// encoded 32 bytes too.
+async fn get_contract_details(
+    id: &str,
+    manager: Arc<DlcManager<'_>>,
+) -> Result<Option<String>, GenericError> {
+    let mut contract = match parse_contract_id(id) {
+        Ok(contract_id) => manager.get_store().get_contract(&contract_id).await?,
+        Err(_) => None,
+    };
+    if contract.is_none() {
+        contract = manager.find_contract_by_event_id(id).await?;
+    }
+    match contract {
+        Some(contract) => {
+            let details = manager.get_contract_details(&contract).await?;
+            Ok(Some(serde_json::to_string(&details)?))
+        }
+        None => Ok(None),
+    }
+}
+
+async fn retry_failed_contract(
+    contract_id: &str,
+    manager: Arc<DlcManager<'_>>,
+    node_secret_key: SecretKey,
+) -> Result<String, GenericError> {
+    let contract_id = parse_contract_id(contract_id)?;
+    match manager.retry_failed_contract(&contract_id).await? {
+        // The counterparty needs the sign message of a retried accept, in
+        // an envelope like the one answering the accept
+        Some(sign @ Message::Sign(_)) => {
+            let envelope = SignedEnvelope::seal(
+                &secp256k1_zkp::Secp256k1::signing_only(),
+                &sign,
+                &node_secret_key,
+            )?;
+            serde_json::to_string(&envelope).map_err(|e| e.into())
+        }
+        _ => Ok("Contract retried successfully".to_string()),
+    }
+}
+
 async fn get_wallet_info(
     store: Arc<AsyncStorageApiProvider>,
     wallet: Arc<DlcWallet>,
+    node_secret_key: SecretKey,
     // static_address: String,
 ) -> Result<Response<Body>, GenericError> {
     let mut info_response = json!({});
@@ -703,8 +970,15 @@ async fn get_wallet_info(
     contracts_json["Rejected"] = collected_contracts[7].clone().into();
     contracts_json["PreClosed"] = collected_contracts[8].clone().into();
 
+    // The key the messages of the wallet are signed with, which the user
+    // wallets check the offers they receive against
+    let public_key = secp256k1_zkp::PublicKey::from_secret_key(
+        &secp256k1_zkp::Secp256k1::signing_only(),
+        &node_secret_key,
+    );
     info_response["wallet"] = json!({
-        "address": wallet.address
+        "address": wallet.address,
+        "publicKey": public_key.to_string()
     });
     info_response["contracts"] = contracts_json;
 
@@ -716,106 +990,129 @@ async fn get_wallet_info(
     Ok(response)
 }
 
-async fn periodic_check(
-    manager: Arc<DlcManager<'_>>,
-    store: Arc<AsyncStorageApiProvider>,
-    blockchain_interface_url: String,
-) -> Result<String, GenericError> {
-    let funded_url = format!("{}/set-status-funded", blockchain_interface_url);
-    let closed_url = format!("{}/post-close-dlc", blockchain_interface_url);
-
-    let attestors: HashMap<XOnlyPublicKey, Arc<AttestorClient>> = match manager.oracles.clone() {
-        Some(oracles) => oracles,
-        None => {
-            return Err("No attestors from manager".into());
+async fn periodic_check(manager: Arc<DlcManager<'_>>) -> Result<String, GenericError> {
+    // The resulting state changes reach the blockchain interface through
+    // notify_contract_events.
+    match manager.periodic_check().await {
+        Ok(updated_contracts) => {
+            debug!(
+                "Periodic check updated {} contracts",
+                updated_contracts.len()
+            )
         }
+        Err(e) => info!("Error in periodic_check, will retry: {}", e.to_string()),
     };
+    Ok("Success running periodic check".to_string())
+}
 
-    let updated_contracts = match manager.periodic_check().await {
-        Ok(updated_contracts) => updated_contracts,
-        Err(e) => {
-            info!("Error in periodic_check, will retry: {}", e.to_string());
-            vec![]
-        }
-    };
-    let mut newly_confirmed_uuids: Vec<(String, bitcoin::Txid)> = vec![];
-    let mut newly_closed_uuids: Vec<(String, bitcoin::Txid)> = vec![];
-
-    for (id, uuid) in updated_contracts {
-        let contract = match store.get_contract(&id).await {
-            Ok(Some(contract)) => contract,
-            Ok(None) => {
-                error!("Error retrieving contract: {:?}", id);
-                continue;
-            }
-            Err(e) => {
-                error!("Error retrieving contract: {}", e.to_string());
-                continue;
-            }
-        };
-        match contract {
-            Contract::Confirmed(c) => {
-                newly_confirmed_uuids
-                    .push((uuid, c.accepted_contract.dlc_transactions.fund.txid()));
-            }
-            Contract::PreClosed(c) => {
-                newly_closed_uuids.push((uuid, c.signed_cet.txid()));
-            }
-            Contract::Closed(_) | Contract::Signed(_) => {
-                debug!(
-                    "Contract is being set to the Closed or Signed state: {}",
-                    uuid
-                );
+// Queues the funding, closing and refund of contracts for the blockchain
+// interface, whichever path of the manager moved them. The outbox delivers
+// them. Handled events are removed from the journal of the storage, those
+// left there when the process stopped are handled again on the next start.
+async fn notify_contract_events(
+    mut events: impl Stream<Item = ContractEvent> + Unpin,
+    outbox: Arc<Outbox>,
+    store: Arc<AsyncStorageApiProvider>,
+) {
+    while let Some(event) = events.next().await {
+        if let Some((kind, txid, uuids)) = contract_notifications(&event) {
+            // The contract state is already stored, so the notification must
+            // reach the outbox for the contract to be reported at all.
+            for uuid in uuids {
+                while let Err(e) = outbox.enqueue(kind, uuid.clone(), txid).await {
+                    error!(
+                        "Failed to queue notification for {}: {}, will retry",
+                        uuid, e
+                    );
+                    time::sleep(REQWEST_TIMEOUT).await;
+                }
             }
-            Contract::Refunded(_) => {
-                debug!("Contract is being set to the Refunded state: {}", uuid);
+            if let Err(e) = outbox.deliver_due().await {
+                warn!("Error delivering notifications: {}, will retry", e);
             }
-            _ => error!(
-                "Error retrieving contract in periodic_check: {}, skipping",
-                uuid
-            ),
-        };
+        }
+        if let Err(e) = store.remove_journaled_contract_event(&event).await {
+            warn!(
+                "Failed to remove event {} from the journal: {}",
+                event.journal_id(),
+                e
+            );
+        }
     }
+}
 
-    for (uuid, txid) in newly_confirmed_uuids {
+// Returns the notifications a contract event calls for, with the uuids they
+// concern.
+fn contract_notifications(
+    event: &ContractEvent,
+) -> Option<(NotificationKind, Option<Txid>, Vec<String>)> {
+    let uuid = match &event.oracle_event_id {
+        Some(uuid) => uuid.clone(),
+        None => {
+            error!("Missing oracle event ID for contract event: {:?}", event);
+            return None;
+        }
+    };
+    // Contracts moving on again after a chain reorganisation moved them
+    // back were already reported.
+    if event.after_reorg {
         debug!(
-            "Contract is funded, setting funded to true: {}, btc tx id: {}",
-            uuid, txid
+            "Contract {} moved from {:?} to {:?} again after a reorganisation",
+            uuid, event.from, event.to
         );
-
-        match get_chain_from_attestors(attestors.clone(), uuid.clone()).await {
-            Ok(chain) => {
-                reqwest::Client::new()
-                    .post(&funded_url)
-                    .timeout(REQWEST_TIMEOUT)
-                    .json(&json!({"uuid": uuid, "btcTxId": txid.to_string(), "chain": chain}))
-                    .send()
-                    .await?
-            }
-            Err(e) => {
-                error!("Failed to get chain from attestors: {}", e);
-                return Err(e);
-            }
-        };
+        return None;
     }
-
-    for (uuid, txid) in newly_closed_uuids {
-        debug!("Contract is closed, firing post-close url: {}", uuid);
-
-        match get_chain_from_attestors(attestors.clone(), uuid.clone()).await {
-            Ok(chain) => {
-                reqwest::Client::new()
-                    .post(&closed_url)
-                    .timeout(REQWEST_TIMEOUT)
-                    .json(&json!({"uuid": uuid, "btcTxId": txid.to_string(), "chain": chain}))
-                    .send()
-                    .await?
+    // Funding, expiry and refund concern every leg of the contract,
+    // closing only the leg of the CET.
+    let notification = match (event.from, event.to, event.fund_txid, event.cet_txid) {
+        (Some(ContractState::Signed), ContractState::Confirmed, Some(txid), _) => {
+            debug!(
+                "Contract is funded, setting funded to true: {:?}, btc tx id: {}",
+                event.oracle_event_ids, txid
+            );
+            (
+                NotificationKind::Funded,
+                Some(txid),
+                event.oracle_event_ids.clone(),
+            )
+        }
+        (_, ContractState::PreClosed, _, Some(txid))
+        | (Some(ContractState::Confirmed), ContractState::Closed, _, Some(txid)) => {
+            debug!("Contract is closed, firing post-close url: {}", uuid);
+            (NotificationKind::Closed, Some(txid), vec![uuid])
+        }
+        (_, ContractState::Rejected, ..) => {
+            debug!(
+                "Offer expired, firing post-abandon url: {:?}",
+                event.oracle_event_ids
+            );
+            (
+                NotificationKind::Abandoned,
+                None,
+                event.oracle_event_ids.clone(),
+            )
+        }
+        (_, ContractState::Refunded, ..) => match event.refund_txid {
+            Some(txid) => {
+                debug!(
+                    "Contract refund is final, firing post-refund url: {:?}, btc tx id: {}",
+                    event.oracle_event_ids, txid
+                );
+                (
+                    NotificationKind::Refunded,
+                    Some(txid),
+                    event.oracle_event_ids.clone(),
+                )
             }
-            Err(e) => {
-                error!("Failed to get chain from attestors: {}", e);
-                return Err(e);
+            None => {
+                error!("Missing refund transaction for refunded contract: {}", uuid);
+                return None;
             }
-        };
-    }
-    Ok("Success running periodic check".to_string())
+        },
+        (from, to, ..) => {
+            debug!("Contract {} moved from {:?} to {:?}", uuid, from, to);
+            return None;
+        }
+    };
+    Some(notification)
 }
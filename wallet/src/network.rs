@@ -0,0 +1,18 @@
+//! The BITCOIN_NETWORK setting, shared by the router wallet and the key
+//! generation binaries.
+
+use bitcoin::Network;
+
+/// Values accepted for BITCOIN_NETWORK.
+pub const NETWORK_NAMES: &str = "bitcoin, testnet, signet or regtest";
+
+/// Parses a BITCOIN_NETWORK value, one of [`NETWORK_NAMES`].
+pub fn parse_network(name: &str) -> Option<Network> {
+    match name {
+        "bitcoin" => Some(Network::Bitcoin),
+        "testnet" => Some(Network::Testnet),
+        "signet" => Some(Network::Signet),
+        "regtest" => Some(Network::Regtest),
+        _ => None,
+    }
+}
@@ -0,0 +1,440 @@
+//! Notifications of the blockchain interface, kept in the storage API until
+//! it acknowledges them.
+//!
+//! A notification is stored as an event of the storage before it is sent.
+//! When the chain of its uuid can't be resolved, or the blockchain interface
+//! doesn't answer with a success, it is retried with an exponential backoff.
+//! Its id is derived from its kind and uuid, so a notification is only queued
+//! once per contract leg.
+//!
+//! Delivered notifications are moved under another prefix, so that delivery
+//! rounds only read the pending ones, and are deleted once
+//! [`DELIVERED_RETENTION_SECONDS`] old.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use bitcoin::{Txid, XOnlyPublicKey};
+use dlc_clients::{
+    EventRequestParams, EventsRequestParams, NewEvent, StorageApiClient, UpdateEvent,
+};
+use secp256k1_zkp::SecretKey;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tokio::sync::Mutex;
+use tracing::{debug, error, info, warn};
+
+use attestor_client::AttestorClient;
+
+use crate::{get_chain_from_attestors, GenericError, REQWEST_TIMEOUT};
+
+/// Prefix of the ids of the storage events holding pending notifications.
+const EVENT_ID_PREFIX: &str = "notification-";
+/// Prefix of the ids of the storage events holding delivered notifications.
+const DELIVERED_EVENT_ID_PREFIX: &str = "delivered-notification-";
+
+/// Time delivered notifications are kept for, to be listed and replayed.
+pub const DELIVERED_RETENTION_SECONDS: u64 = 30 * 24 * 3600;
+/// Delivered notifications are looked for expired ones at most this often.
+const EXPIRY_INTERVAL_SECONDS: u64 = 3600;
+
+/// Delay before the first retry of a notification, doubled on each attempt.
+const MIN_RETRY_DELAY_SECONDS: u64 = 10;
+const MAX_RETRY_DELAY_SECONDS: u64 = 3600;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum NotificationKind {
+    /// The funding transaction of the contract is confirmed.
+    Funded,
+    /// A CET of the contract is broadcast.
+    Closed,
+    /// The offer expired before being signed.
+    Abandoned,
+    /// The refund transaction of the contract is confirmed.
+    Refunded,
+}
+
+impl NotificationKind {
+    fn as_str(&self) -> &'static str {
+        match self {
+            NotificationKind::Funded => "funded",
+            NotificationKind::Closed => "closed",
+            NotificationKind::Abandoned => "abandoned",
+            NotificationKind::Refunded => "refunded",
+        }
+    }
+
+    /// Endpoint of the blockchain interface receiving the notification.
+    fn path(&self) -> &'static str {
+        match self {
+            NotificationKind::Funded => "/set-status-funded",
+            NotificationKind::Closed => "/post-close-dlc",
+            NotificationKind::Abandoned => "/post-abandon-dlc",
+            NotificationKind::Refunded => "/post-refund-dlc",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum NotificationStatus {
+    Pending,
+    Delivered,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Notification {
+    pub id: String,
+    pub kind: NotificationKind,
+    /// Uuid of the DLC.Link contract, the oracle event id of the leg.
+    pub uuid: String,
+    pub btc_tx_id: Option<String>,
+    pub status: NotificationStatus,
+    pub attempts: u32,
+    /// Unix time after which a pending notification is sent again.
+    pub next_attempt_at: u64,
+    pub last_error: Option<String>,
+    pub created_at: u64,
+    pub delivered_at: Option<u64>,
+}
+
+impl Notification {
+    fn new(kind: NotificationKind, uuid: String, txid: Option<Txid>, now: u64) -> Self {
+        Notification {
+            id: format!("{}-{}", kind.as_str(), uuid),
+            kind,
+            uuid,
+            btc_tx_id: txid.map(|txid| txid.to_string()),
+            status: NotificationStatus::Pending,
+            attempts: 0,
+            next_attempt_at: now,
+            last_error: None,
+            created_at: now,
+            delivered_at: None,
+        }
+    }
+}
+
+pub struct Outbox {
+    storage: StorageApiClient,
+    public_key: String,
+    secret_key: SecretKey,
+    blockchain_interface_url: String,
+    attestors: HashMap<XOnlyPublicKey, Arc<AttestorClient>>,
+    // Held while sending, so the delivery loop and a replay don't send the
+    // same notification twice. Holds the last time expired notifications
+    // were deleted.
+    delivery_lock: Mutex<u64>,
+}
+
+impl Outbox {
+    pub fn new(
+        storage_api_endpoint: String,
+        public_key: String,
+        secret_key: SecretKey,
+        blockchain_interface_url: String,
+        attestors: HashMap<XOnlyPublicKey, Arc<AttestorClient>>,
+    ) -> Self {
+        Outbox {
+            storage: StorageApiClient::new(storage_api_endpoint),
+            public_key,
+            secret_key,
+            blockchain_interface_url,
+            attestors,
+            delivery_lock: Mutex::new(0),
+        }
+    }
+
+    /// Stores a notification to send. Queuing a notification already in the
+    /// outbox does nothing.
+    pub async fn enqueue(
+        &self,
+        kind: NotificationKind,
+        uuid: String,
+        txid: Option<Txid>,
+    ) -> Result<(), GenericError> {
+        let notification = Notification::new(kind, uuid, txid, unix_time_now());
+        if self
+            .get_event(delivered_event_id(&notification.id))
+            .await?
+            .is_some()
+        {
+            return Ok(());
+        }
+        let created = self
+            .storage
+            .create_event(
+                NewEvent {
+                    event_id: event_id(&notification.id),
+                    content: serde_json::to_string(&notification)?,
+                    key: self.public_key.clone(),
+                },
+                self.secret_key,
+            )
+            .await;
+        match created {
+            Ok(_) => {
+                debug!("Queued notification {}", notification.id);
+                Ok(())
+            }
+            // The storage rejects a second event with the same id
+            Err(e) => match self.get(&notification.id).await? {
+                Some(_) => Ok(()),
+                None => Err(e.into()),
+            },
+        }
+    }
+
+    /// Returns the notification with the given id, pending or delivered.
+    pub async fn get(&self, id: &str) -> Result<Option<Notification>, GenericError> {
+        for event_id in [event_id(id), delivered_event_id(id)] {
+            if let Some(notification) = self.get_event(event_id).await? {
+                return Ok(Some(notification));
+            }
+        }
+        Ok(None)
+    }
+
+    async fn get_event(&self, event_id: String) -> Result<Option<Notification>, GenericError> {
+        let event = self
+            .storage
+            .get_event(
+                EventRequestParams {
+                    key: self.public_key.clone(),
+                    event_id,
+                },
+                self.secret_key,
+            )
+            .await?;
+        match event {
+            Some(event) => Ok(Some(serde_json::from_str(&event.content)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Returns the notifications of the outbox, oldest first.
+    pub async fn list(&self) -> Result<Vec<Notification>, GenericError> {
+        let mut notifications = self.list_prefix(EVENT_ID_PREFIX).await?;
+        notifications.extend(self.list_prefix(DELIVERED_EVENT_ID_PREFIX).await?);
+        notifications.sort_by_key(|n| n.created_at);
+        Ok(notifications)
+    }
+
+    async fn list_prefix(&self, prefix: &str) -> Result<Vec<Notification>, GenericError> {
+        let events = self
+            .storage
+            .get_events(
+                EventsRequestParams {
+                    key: self.public_key.clone(),
+                    event_id: None,
+                    event_id_prefix: Some(prefix.to_string()),
+                },
+                self.secret_key,
+            )
+            .await?;
+        Ok(events
+            .iter()
+            .filter_map(|event| match serde_json::from_str(&event.content) {
+                Ok(notification) => Some(notification),
+                Err(e) => {
+                    warn!("Skipping unreadable notification {}: {}", event.event_id, e);
+                    None
+                }
+            })
+            .collect())
+    }
+
+    /// Sends the pending notifications whose retry delay has passed, and
+    /// deletes the expired delivered ones.
+    pub async fn deliver_due(&self) -> Result<(), GenericError> {
+        let mut last_expiry = self.delivery_lock.lock().await;
+        let now = unix_time_now();
+        let mut pending = self.list_prefix(EVENT_ID_PREFIX).await?;
+        pending.sort_by_key(|n| n.created_at);
+        for notification in pending {
+            match notification.status {
+                // Delivered before being moved, or by an older version
+                NotificationStatus::Delivered => {
+                    if let Err(e) = self.save(&notification, EVENT_ID_PREFIX).await {
+                        error!("Failed to save notification {}: {}", notification.id, e);
+                    }
+                }
+                NotificationStatus::Pending if notification.next_attempt_at <= now => {
+                    self.attempt(notification, EVENT_ID_PREFIX).await;
+                }
+                NotificationStatus::Pending => {}
+            }
+        }
+        if now >= *last_expiry + EXPIRY_INTERVAL_SECONDS {
+            self.delete_expired(now).await?;
+            *last_expiry = now;
+        }
+        Ok(())
+    }
+
+    /// Deletes the notifications delivered more than
+    /// [`DELIVERED_RETENTION_SECONDS`] ago.
+    async fn delete_expired(&self, now: u64) -> Result<(), GenericError> {
+        for notification in self.list_prefix(DELIVERED_EVENT_ID_PREFIX).await? {
+            let delivered_at = notification.delivered_at.unwrap_or(notification.created_at);
+            if delivered_at + DELIVERED_RETENTION_SECONDS <= now {
+                debug!("Deleting expired notification {}", notification.id);
+                self.delete(delivered_event_id(&notification.id)).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Sends a notification again right away, whether it was delivered or
+    /// not. Returns `None` when the outbox doesn't hold it.
+    pub async fn replay(&self, id: &str) -> Result<Option<Notification>, GenericError> {
+        let _guard = self.delivery_lock.lock().await;
+        for prefix in [EVENT_ID_PREFIX, DELIVERED_EVENT_ID_PREFIX] {
+            if let Some(notification) = self.get_event(format!("{}{}", prefix, id)).await? {
+                info!("Replaying notification {}", id);
+                return Ok(Some(self.attempt(notification, prefix).await));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Delivers the pending notifications until the process stops.
+    pub async fn run(self: Arc<Self>, interval: Duration) {
+        loop {
+            if let Err(e) = self.deliver_due().await {
+                warn!("Error delivering notifications: {}, will retry", e);
+            }
+            tokio::time::sleep(interval).await;
+        }
+    }
+
+    /// Sends a notification stored under `prefix`, and stores the outcome.
+    async fn attempt(&self, mut notification: Notification, prefix: &str) -> Notification {
+        let now = unix_time_now();
+        match self.send(&notification).await {
+            Ok(()) => {
+                debug!("Delivered notification {}", notification.id);
+                notification.status = NotificationStatus::Delivered;
+                notification.delivered_at = Some(now);
+                notification.last_error = None;
+            }
+            Err(e) => {
+                notification.attempts += 1;
+                let delay = retry_delay(notification.attempts);
+                warn!(
+                    "Failed to deliver notification {} (attempt {}), retrying in {}s: {}",
+                    notification.id, notification.attempts, delay, e
+                );
+                notification.status = NotificationStatus::Pending;
+                notification.next_attempt_at = now + delay;
+                notification.last_error = Some(e.to_string());
+            }
+        }
+        if let Err(e) = self.save(&notification, prefix).await {
+            // Left as it was in the storage, a delivered notification is sent
+            // again on the next round.
+            error!("Failed to save notification {}: {}", notification.id, e);
+        }
+        notification
+    }
+
+    async fn send(&self, notification: &Notification) -> Result<(), GenericError> {
+        let chain =
+            get_chain_from_attestors(self.attestors.clone(), notification.uuid.clone()).await?;
+        let body = match &notification.btc_tx_id {
+            Some(txid) => json!({"uuid": notification.uuid, "btcTxId": txid, "chain": chain}),
+            None => json!({"uuid": notification.uuid, "chain": chain}),
+        };
+        reqwest::Client::new()
+            .post(format!(
+                "{}{}",
+                self.blockchain_interface_url,
+                notification.kind.path()
+            ))
+            .timeout(REQWEST_TIMEOUT)
+            .json(&body)
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+
+    /// Stores a notification under the prefix of its status, moving it from
+    /// the prefix it was stored under.
+    async fn save(&self, notification: &Notification, prefix: &str) -> Result<(), GenericError> {
+        let content = serde_json::to_string(notification)?;
+        let new_prefix = match notification.status {
+            NotificationStatus::Pending => EVENT_ID_PREFIX,
+            NotificationStatus::Delivered => DELIVERED_EVENT_ID_PREFIX,
+        };
+        let new_event_id = format!("{}{}", new_prefix, notification.id);
+        if new_prefix == prefix {
+            self.storage
+                .update_event(
+                    UpdateEvent {
+                        event_id: new_event_id,
+                        content,
+                        key: self.public_key.clone(),
+                    },
+                    self.secret_key,
+                )
+                .await?;
+            return Ok(());
+        }
+        // Created before the old one is deleted, a notification left under
+        // both prefixes by an interrupted move is moved again once sent.
+        let created = self
+            .storage
+            .create_event(
+                NewEvent {
+                    event_id: new_event_id.clone(),
+                    content,
+                    key: self.public_key.clone(),
+                },
+                self.secret_key,
+            )
+            .await;
+        if let Err(e) = created {
+            if self.get_event(new_event_id).await?.is_none() {
+                return Err(e.into());
+            }
+        }
+        self.delete(format!("{}{}", prefix, notification.id)).await
+    }
+
+    async fn delete(&self, event_id: String) -> Result<(), GenericError> {
+        self.storage
+            .delete_event(
+                EventRequestParams {
+                    key: self.public_key.clone(),
+                    event_id,
+                },
+                self.secret_key,
+            )
+            .await?;
+        Ok(())
+    }
+}
+
+fn event_id(notification_id: &str) -> String {
+    format!("{}{}", EVENT_ID_PREFIX, notification_id)
+}
+
+fn delivered_event_id(notification_id: &str) -> String {
+    format!("{}{}", DELIVERED_EVENT_ID_PREFIX, notification_id)
+}
+
+fn retry_delay(attempts: u32) -> u64 {
+    let doublings = attempts.saturating_sub(1).min(16);
+    (MIN_RETRY_DELAY_SECONDS << doublings).min(MAX_RETRY_DELAY_SECONDS)
+}
+
+fn unix_time_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
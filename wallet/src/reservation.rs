@@ -0,0 +1,199 @@
+//! Reservations of the uuids offers are made for, kept in the storage API.
+//!
+//! The storage rejects a second event with the same id, so of concurrent
+//! offer requests for a uuid only the one creating its reservation goes on.
+//! A reservation is taken over, with a compare-and-swap of its content, when
+//! the offer made for it failed or was rejected, or when no offer was stored
+//! within [`RESERVATION_TIMEOUT_SECONDS`] of it.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use dlc_clients::{EventRequestParams, NewEvent, StorageApiClient, SwapEvent};
+use dlc_manager::contract::Contract;
+use secp256k1_zkp::SecretKey;
+use serde::{Deserialize, Serialize};
+use tracing::{debug, warn};
+
+use crate::validation::UpstreamError;
+use crate::{DlcManager, GenericError};
+
+/// Prefix of the ids of the storage events holding reservations.
+const EVENT_ID_PREFIX: &str = "offer-reservation-";
+
+/// A reservation without stored offer is abandoned after this long, e.g.
+/// when the wallet stopped while making the offer.
+pub const RESERVATION_TIMEOUT_SECONDS: u64 = 300;
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Reservation {
+    reserved_at: u64,
+}
+
+/// Outcome of [`OfferReservations::reserve`].
+#[derive(Debug, PartialEq, Eq)]
+pub enum Reserved {
+    /// The uuid is reserved for the caller.
+    Reserved,
+    /// Another offer was made, or is being made, for the uuid.
+    AlreadyOffered,
+}
+
+pub struct OfferReservations {
+    storage: StorageApiClient,
+    public_key: String,
+    secret_key: SecretKey,
+}
+
+impl OfferReservations {
+    pub fn new(storage_api_endpoint: String, public_key: String, secret_key: SecretKey) -> Self {
+        OfferReservations {
+            storage: StorageApiClient::new(storage_api_endpoint),
+            public_key,
+            secret_key,
+        }
+    }
+
+    /// Reserves `uuid` for an offer. Storage failures are returned as
+    /// [`UpstreamError`].
+    pub async fn reserve(
+        &self,
+        uuid: &str,
+        manager: &DlcManager<'_>,
+    ) -> Result<Reserved, GenericError> {
+        let now = unix_time_now();
+        let content = serde_json::to_string(&Reservation { reserved_at: now })?;
+        let created = self
+            .storage
+            .create_event(
+                NewEvent {
+                    event_id: event_id(uuid),
+                    content: content.clone(),
+                    key: self.public_key.clone(),
+                },
+                self.secret_key,
+            )
+            .await;
+        let existing = match created {
+            // Offers made before the reservations were stored have none
+            Ok(_) => {
+                return match find_contract(uuid, manager).await? {
+                    Some(contract) if !is_offerable_again(&contract) => {
+                        Ok(Reserved::AlreadyOffered)
+                    }
+                    _ => Ok(Reserved::Reserved),
+                };
+            }
+            // The storage rejects a second event with the same id
+            Err(e) => match self.get_event(uuid).await? {
+                Some(existing) => existing,
+                None => {
+                    return Err(UpstreamError::new(
+                        "storage API",
+                        format!("error reserving {}: {}", uuid, e),
+                    )
+                    .into())
+                }
+            },
+        };
+
+        let can_take_over = match find_contract(uuid, manager).await? {
+            Some(contract) => is_offerable_again(&contract),
+            None => match serde_json::from_str::<Reservation>(&existing) {
+                Ok(reservation) => reservation.reserved_at + RESERVATION_TIMEOUT_SECONDS <= now,
+                Err(e) => {
+                    warn!("Invalid reservation of {}: {}", uuid, e);
+                    true
+                }
+            },
+        };
+        if !can_take_over {
+            return Ok(Reserved::AlreadyOffered);
+        }
+        // Only one of the requests taking the reservation over swaps it
+        let swapped = self
+            .storage
+            .swap_event(
+                SwapEvent {
+                    event_id: event_id(uuid),
+                    content,
+                    expected_content: existing,
+                    key: self.public_key.clone(),
+                },
+                self.secret_key,
+            )
+            .await
+            .map_err(|e| {
+                UpstreamError::new("storage API", format!("error reserving {}: {}", uuid, e))
+            })?;
+        if swapped {
+            debug!("Took over the reservation of {}", uuid);
+            Ok(Reserved::Reserved)
+        } else {
+            Ok(Reserved::AlreadyOffered)
+        }
+    }
+
+    /// Releases the reservation of an offer that failed to be made, so that
+    /// it may be requested again right away.
+    pub async fn release(&self, uuid: &str) {
+        let deleted = self
+            .storage
+            .delete_event(
+                EventRequestParams {
+                    key: self.public_key.clone(),
+                    event_id: event_id(uuid),
+                },
+                self.secret_key,
+            )
+            .await;
+        if let Err(e) = deleted {
+            warn!("Error releasing the reservation of {}: {}", uuid, e);
+        }
+    }
+
+    async fn get_event(&self, uuid: &str) -> Result<Option<String>, GenericError> {
+        let event = self
+            .storage
+            .get_event(
+                EventRequestParams {
+                    key: self.public_key.clone(),
+                    event_id: event_id(uuid),
+                },
+                self.secret_key,
+            )
+            .await
+            .map_err(|e| {
+                UpstreamError::new("storage API", format!("error reading reservation: {}", e))
+            })?;
+        Ok(event.map(|event| event.content))
+    }
+}
+
+async fn find_contract(
+    uuid: &str,
+    manager: &DlcManager<'_>,
+) -> Result<Option<Contract>, GenericError> {
+    manager.find_contract_by_event_id(uuid).await.map_err(|e| {
+        UpstreamError::new("storage API", format!("error finding {}: {}", uuid, e)).into()
+    })
+}
+
+/// An expired or failed offer may be made again.
+fn is_offerable_again(contract: &Contract) -> bool {
+    matches!(
+        contract,
+        Contract::Rejected(_) | Contract::FailedAccept(_) | Contract::FailedSign(_)
+    )
+}
+
+fn event_id(uuid: &str) -> String {
+    format!("{}{}", EVENT_ID_PREFIX, uuid)
+}
+
+fn unix_time_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
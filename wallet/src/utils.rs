@@ -16,6 +16,7 @@ pub(crate) fn get_numerical_contract_info(
     offer_collateral: u64,
     total_outcomes: u64,
     nb_attestors: usize,
+    threshold: u16,
 ) -> Result<(EventDescriptor, ContractDescriptor), dlc_manager::error::Error> {
     let event_descriptor =
         EventDescriptor::DigitDecompositionEvent(DigitDecompositionEventDescriptor {
@@ -31,17 +32,28 @@ pub(crate) fn get_numerical_contract_info(
         offer_collateral,
         total_outcomes,
         nb_attestors,
+        threshold,
     )?;
 
     Ok((event_descriptor, descriptor))
 }
 
+/// Builds the numerical descriptor for a `threshold`-of-`nb_attestors` contract.
+/// Every attestor of the contract gets an entry in the `OracleNumericInfo`, so
+/// that any `threshold` sized subset of them can close it.
 pub(crate) fn get_numerical_contract_descriptor(
     accept_collateral: u64,
     offer_collateral: u64,
     total_outcomes: u64,
     nb_attestors: usize,
+    threshold: u16,
 ) -> Result<ContractDescriptor, dlc_manager::error::Error> {
+    if threshold == 0 || threshold as usize > nb_attestors {
+        return Err(dlc_manager::error::Error::InvalidParameters(format!(
+            "Invalid attestor threshold {} for {} attestors",
+            threshold, nb_attestors
+        )));
+    }
     Ok(ContractDescriptor::Numerical(NumericalDescriptor {
         payout_function: PayoutFunction::new(get_polynomial_payout_curve_pieces(
             accept_collateral,
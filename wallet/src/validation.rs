@@ -0,0 +1,681 @@
+//! Checks of the fields of requests, reported to the caller with the name of
+//! the invalid field.
+
+use std::fmt;
+use std::str::FromStr;
+
+use bitcoin::{Address, Network};
+use dlc_link_manager::ONE_DAY_IN_SECONDS;
+use futures_util::future::join_all;
+use secp256k1_zkp::PublicKey;
+use serde::{Deserialize, Serialize};
+
+use crate::fees::FeeConfig;
+use crate::reservation::{OfferReservations, Reserved};
+use crate::utils::max_value;
+use crate::{DlcManager, GenericError};
+
+/// Offers are refundable at most this long after the event maturity by
+/// default.
+pub const DEFAULT_MAX_REFUND_DELAY: u32 = ONE_DAY_IN_SECONDS * 10;
+
+/// Number of sats that will ever exist.
+const MAX_MONEY: u64 = 21_000_000 * 100_000_000;
+
+/// Longest uuid, a 32 bytes hex string with a `0x` prefix.
+const MAX_UUID_LENGTH: usize = 66;
+
+/// An invalid field of a request, answered with a 400 naming the field.
+#[derive(Debug)]
+pub struct FieldError {
+    pub field: String,
+    pub message: String,
+}
+
+impl FieldError {
+    pub fn new(field: &str, message: String) -> Self {
+        FieldError {
+            field: field.to_string(),
+            message,
+        }
+    }
+
+    fn required(field: &str) -> Self {
+        FieldError::new(field, format!("{} is required", field))
+    }
+}
+
+impl fmt::Display for FieldError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Invalid {}: {}", self.field, self.message)
+    }
+}
+
+impl std::error::Error for FieldError {}
+
+/// All the invalid fields of a request.
+#[derive(Debug)]
+pub struct FieldErrors(pub Vec<FieldError>);
+
+impl fmt::Display for FieldErrors {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let errors: Vec<String> = self.0.iter().map(|e| e.to_string()).collect();
+        write!(f, "{}", errors.join(", "))
+    }
+}
+
+impl std::error::Error for FieldErrors {}
+
+impl From<FieldError> for FieldErrors {
+    fn from(error: FieldError) -> Self {
+        FieldErrors(vec![error])
+    }
+}
+
+/// A failure of a service a request is checked against, answered with a 502
+/// rather than blaming the request.
+#[derive(Debug)]
+pub struct UpstreamError {
+    pub service: String,
+    pub message: String,
+}
+
+impl UpstreamError {
+    pub fn new(service: &str, message: String) -> Self {
+        UpstreamError {
+            service: service.to_string(),
+            message,
+        }
+    }
+}
+
+impl fmt::Display for UpstreamError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "The {} failed: {}", self.service, self.message)
+    }
+}
+
+impl std::error::Error for UpstreamError {}
+
+/// Parses a bitcoin address given in `field`, which must belong to `network`.
+pub fn parse_address(field: &str, address: &str, network: Network) -> Result<Address, FieldError> {
+    let address = Address::from_str(address).map_err(|e| {
+        FieldError::new(
+            field,
+            format!("{} is not a bitcoin address: {}", address, e),
+        )
+    })?;
+    if !address.is_valid_for_network(network) {
+        return Err(FieldError::new(
+            field,
+            format!("{} is not an address of the {} network", address, network),
+        ));
+    }
+    Ok(address)
+}
+
+/// Limits of the offers the router wallet makes.
+#[derive(Clone, Copy, Debug)]
+pub struct OfferLimits {
+    /// Minimum collateral of the acceptor, in sats.
+    pub min_accept_collateral: u64,
+    /// Maximum collateral of both parties together, in sats.
+    pub max_total_collateral: Option<u64>,
+    /// Maximum protocol fee, in basis points of the accept collateral.
+    pub max_fee_basis_points: u64,
+    /// Longer refund delays are shortened to this one, in seconds.
+    pub max_refund_delay: u32,
+}
+
+impl Default for OfferLimits {
+    fn default() -> Self {
+        OfferLimits {
+            min_accept_collateral: 10_000,
+            max_total_collateral: None,
+            max_fee_basis_points: 10_000,
+            max_refund_delay: DEFAULT_MAX_REFUND_DELAY,
+        }
+    }
+}
+
+/// Body of a request to create an offer, before validation.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OfferRequest {
+    #[serde(default)]
+    uuid: Option<String>,
+    #[serde(default)]
+    accept_collateral: Option<u64>,
+    #[serde(default)]
+    offer_collateral: u64,
+    #[serde(default)]
+    total_outcomes: Option<u64>,
+    /// 0 for the longest refund delay allowed.
+    #[serde(default)]
+    refund_delay: u32,
+    #[serde(default)]
+    btc_fee_recipient: String,
+    #[serde(default)]
+    btc_fee_basis_points: u64,
+    #[serde(default)]
+    threshold: Option<u16>,
+    /// Overrides the estimated fee rate, in sats/vbyte.
+    #[serde(default)]
+    fee_rate: Option<u64>,
+    /// Key the acceptor signs its messages with.
+    #[serde(default)]
+    counterparty_public_key: Option<String>,
+}
+
+/// An offer request that passed the validation.
+pub struct ValidOffer {
+    pub uuid: String,
+    pub accept_collateral: u64,
+    pub offer_collateral: u64,
+    pub total_outcomes: u64,
+    pub refund_delay: u32,
+    pub fee_recipient: Address,
+    pub fee_basis_points: u64,
+    pub threshold: u16,
+    pub fee_rate: Option<u64>,
+    pub counter_party: PublicKey,
+}
+
+/// A value of the request the validation changed rather than rejected.
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Adjustment {
+    pub field: String,
+    pub requested: u64,
+    pub applied: u64,
+    pub reason: String,
+}
+
+pub struct OfferValidator {
+    limits: OfferLimits,
+    fees: FeeConfig,
+    network: Network,
+    /// Recipient of the fee output of offers without protocol fee.
+    default_fee_recipient: Address,
+    attestor_threshold: Option<u16>,
+    reservations: OfferReservations,
+}
+
+impl OfferValidator {
+    pub fn new(
+        limits: OfferLimits,
+        fees: FeeConfig,
+        network: Network,
+        default_fee_recipient: Address,
+        attestor_threshold: Option<u16>,
+        reservations: OfferReservations,
+    ) -> Self {
+        OfferValidator {
+            limits,
+            fees,
+            network,
+            default_fee_recipient,
+            attestor_threshold,
+            reservations,
+        }
+    }
+
+    /// Checks every field of the request, the invalid ones are returned
+    /// together as [`FieldErrors`]. The uuid is then checked against the
+    /// announcements of the attestors, and reserved for the offer unless one
+    /// was already made for it. Failures of the attestors or the storage are
+    /// returned as [`UpstreamError`].
+    ///
+    /// The reservation of a valid offer that fails to be made must be
+    /// [released](Self::release).
+    pub async fn validate(
+        &self,
+        request: OfferRequest,
+        manager: &DlcManager<'_>,
+    ) -> Result<(ValidOffer, Vec<Adjustment>), GenericError> {
+        let attestors = manager.oracles.clone().unwrap_or_default();
+        let (offer, adjustments) = self
+            .check_fields(request, attestors.len())
+            .map_err(FieldErrors)?;
+
+        let uuid = &offer.uuid;
+        let announcements = join_all(attestors.iter().map(|(public_key, attestor)| async move {
+            (public_key, attestor.find_announcement(uuid).await)
+        }))
+        .await;
+        let mut missing = Vec::new();
+        let mut failed = Vec::new();
+        for (public_key, announcement) in announcements {
+            match announcement {
+                Ok(Some(_)) => (),
+                Ok(None) => missing.push(public_key.to_string()),
+                Err(e) => failed.push(format!("{}: {}", public_key, e)),
+            }
+        }
+        if !failed.is_empty() {
+            return Err(UpstreamError::new(
+                "attestors",
+                format!(
+                    "error getting the announcement for {}: {}",
+                    uuid,
+                    failed.join(", ")
+                ),
+            )
+            .into());
+        }
+        if !missing.is_empty() {
+            return Err(FieldErrors::from(FieldError::new(
+                "uuid",
+                format!(
+                    "no announcement for {} from the attestors {}",
+                    uuid,
+                    missing.join(", ")
+                ),
+            ))
+            .into());
+        }
+
+        // Reserved last, so that a rejected request leaves no reservation
+        if self.reservations.reserve(uuid, manager).await? == Reserved::AlreadyOffered {
+            return Err(FieldErrors::from(FieldError::new(
+                "uuid",
+                format!("an offer was already made for {}", uuid),
+            ))
+            .into());
+        }
+
+        Ok((offer, adjustments))
+    }
+
+    /// Releases the uuid of a valid offer that failed to be made.
+    pub async fn release(&self, uuid: &str) {
+        self.reservations.release(uuid).await
+    }
+
+    fn check_fields(
+        &self,
+        request: OfferRequest,
+        nb_attestors: usize,
+    ) -> Result<(ValidOffer, Vec<Adjustment>), Vec<FieldError>> {
+        let limits = &self.limits;
+        let mut errors = Vec::new();
+        let mut adjustments = Vec::new();
+
+        let uuid = match request.uuid {
+            Some(uuid) if is_valid_uuid(&uuid) => Some(uuid),
+            Some(uuid) => {
+                errors.push(FieldError::new(
+                    "uuid",
+                    format!(
+                        "{} should be at most {} letters and digits",
+                        uuid, MAX_UUID_LENGTH
+                    ),
+                ));
+                None
+            }
+            None => {
+                errors.push(FieldError::required("uuid"));
+                None
+            }
+        };
+
+        let accept_collateral = match request.accept_collateral {
+            Some(collateral) if collateral < limits.min_accept_collateral => {
+                errors.push(FieldError::new(
+                    "acceptCollateral",
+                    format!(
+                        "{} sats is below the minimum of {} sats",
+                        collateral, limits.min_accept_collateral
+                    ),
+                ));
+                None
+            }
+            Some(collateral) => Some(collateral),
+            None => {
+                errors.push(FieldError::required("acceptCollateral"));
+                None
+            }
+        };
+        if let Some(accept_collateral) = accept_collateral {
+            let max_total_collateral = limits.max_total_collateral.unwrap_or(MAX_MONEY);
+            match accept_collateral.checked_add(request.offer_collateral) {
+                Some(total) if total <= max_total_collateral => (),
+                _ => errors.push(FieldError::new(
+                    "acceptCollateral",
+                    format!(
+                        "the total collateral exceeds the maximum of {} sats",
+                        max_total_collateral
+                    ),
+                )),
+            }
+        }
+
+        // Outcomes above the largest value the attestors can sign would be
+        // cut from the payout curve
+        let total_outcomes = match request.total_outcomes {
+            Some(outcomes) if outcomes == 0 || outcomes > max_value() as u64 => {
+                errors.push(FieldError::new(
+                    "totalOutcomes",
+                    format!("should be between 1 and {}", max_value()),
+                ));
+                None
+            }
+            Some(outcomes) => Some(outcomes),
+            None => {
+                errors.push(FieldError::required("totalOutcomes"));
+                None
+            }
+        };
+
+        if request.btc_fee_basis_points > limits.max_fee_basis_points {
+            errors.push(FieldError::new(
+                "btcFeeBasisPoints",
+                format!(
+                    "{} exceeds the maximum of {}",
+                    request.btc_fee_basis_points, limits.max_fee_basis_points
+                ),
+            ));
+        }
+        let fee_recipient = match request.btc_fee_recipient.as_str() {
+            // Without basis points the fee output is empty, and the
+            // recipient may be left out
+            "" if request.btc_fee_basis_points == 0 => Some(self.default_fee_recipient.clone()),
+            "" => {
+                errors.push(FieldError::required("btcFeeRecipient"));
+                None
+            }
+            address => match parse_address("btcFeeRecipient", address, self.network) {
+                Ok(address) => Some(address),
+                Err(e) => {
+                    errors.push(e);
+                    None
+                }
+            },
+        };
+
+        let threshold = request
+            .threshold
+            .or(self.attestor_threshold)
+            .unwrap_or(nb_attestors as u16);
+        if threshold == 0 || threshold as usize > nb_attestors {
+            errors.push(FieldError::new(
+                "threshold",
+                format!("should be between 1 and {}", nb_attestors),
+            ));
+        }
+
+        if let Some(fee_rate) = request.fee_rate {
+            if fee_rate < self.fees.min_fee_rate || fee_rate > self.fees.max_fee_rate {
+                errors.push(FieldError::new(
+                    "feeRate",
+                    format!(
+                        "{} sats/vbyte is outside of the allowed range of {} to {}",
+                        fee_rate, self.fees.min_fee_rate, self.fees.max_fee_rate
+                    ),
+                ));
+            }
+        }
+
+        let counter_party = match request.counterparty_public_key {
+            Some(key) => match PublicKey::from_str(&key) {
+                Ok(key) => Some(key),
+                Err(e) => {
+                    errors.push(FieldError::new(
+                        "counterpartyPublicKey",
+                        format!("{} is not a public key: {}", key, e),
+                    ));
+                    None
+                }
+            },
+            None => {
+                errors.push(FieldError::required("counterpartyPublicKey"));
+                None
+            }
+        };
+
+        let refund_delay = match request.refund_delay {
+            0 => limits.max_refund_delay,
+            delay => delay.min(limits.max_refund_delay),
+        };
+        if refund_delay != request.refund_delay {
+            adjustments.push(Adjustment {
+                field: "refundDelay".to_string(),
+                requested: request.refund_delay as u64,
+                applied: refund_delay as u64,
+                reason: format!(
+                    "refund delays of 0 or above {} seconds use the maximum",
+                    limits.max_refund_delay
+                ),
+            });
+        }
+
+        match (
+            uuid,
+            accept_collateral,
+            total_outcomes,
+            fee_recipient,
+            counter_party,
+        ) {
+            (
+                Some(uuid),
+                Some(accept_collateral),
+                Some(total_outcomes),
+                Some(fee_recipient),
+                Some(counter_party),
+            ) if errors.is_empty() => Ok((
+                ValidOffer {
+                    uuid,
+                    accept_collateral,
+                    offer_collateral: request.offer_collateral,
+                    total_outcomes,
+                    refund_delay,
+                    fee_recipient,
+                    fee_basis_points: request.btc_fee_basis_points,
+                    threshold,
+                    fee_rate: request.fee_rate,
+                    counter_party,
+                },
+                adjustments,
+            )),
+            _ => Err(errors),
+        }
+    }
+}
+
+/// Uuids are hex strings from the smart contracts, test uuids letters and
+/// digits. They are used in the paths of the attestor API.
+fn is_valid_uuid(uuid: &str) -> bool {
+    !uuid.is_empty()
+        && uuid.len() <= MAX_UUID_LENGTH
+        && uuid.chars().all(|c| c.is_ascii_alphanumeric())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use secp256k1_zkp::SecretKey;
+    use serde_json::{json, Value};
+
+    const REGTEST_ADDRESS: &str = "bcrt1qw508d6qejxtdg4y5r3zarvary0c5xw7kygt080";
+    const COUNTERPARTY: &str = "0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798";
+
+    fn validator(limits: OfferLimits) -> OfferValidator {
+        OfferValidator::new(
+            limits,
+            FeeConfig {
+                confirmation_target: 6,
+                min_fee_rate: 1,
+                max_fee_rate: 1000,
+                fallback_fee_rate: 1,
+            },
+            Network::Regtest,
+            Address::from_str(REGTEST_ADDRESS).expect("a valid address"),
+            None,
+            OfferReservations::new(
+                "http://localhost:8100".to_string(),
+                "key".to_string(),
+                SecretKey::from_slice(&[1; 32]).expect("a valid secret key"),
+            ),
+        )
+    }
+
+    fn request(overrides: Value) -> OfferRequest {
+        let mut request = json!({
+            "uuid": "abc123",
+            "acceptCollateral": 100_000,
+            "totalOutcomes": 100,
+            "btcFeeRecipient": REGTEST_ADDRESS,
+            "btcFeeBasisPoints": 100,
+            "counterpartyPublicKey": COUNTERPARTY,
+        });
+        for (key, value) in overrides.as_object().expect("an object") {
+            request[key] = value.clone();
+        }
+        serde_json::from_value(request).expect("a valid request")
+    }
+
+    fn invalid_fields(
+        result: Result<(ValidOffer, Vec<Adjustment>), Vec<FieldError>>,
+    ) -> Vec<String> {
+        match result {
+            Ok(_) => Vec::new(),
+            Err(errors) => errors.into_iter().map(|e| e.field).collect(),
+        }
+    }
+
+    #[test]
+    fn valid_request_is_accepted() {
+        let (offer, adjustments) = validator(OfferLimits::default())
+            .check_fields(request(json!({ "refundDelay": 3600 })), 3)
+            .map_err(FieldErrors)
+            .expect("a valid offer");
+        assert_eq!(offer.uuid, "abc123");
+        assert_eq!(offer.threshold, 3);
+        assert_eq!(offer.refund_delay, 3600);
+        assert!(adjustments.is_empty());
+    }
+
+    #[test]
+    fn total_outcomes_are_bounded() {
+        let validator = validator(OfferLimits::default());
+        for outcomes in [0, max_value() as u64 + 1] {
+            let result = validator.check_fields(request(json!({ "totalOutcomes": outcomes })), 1);
+            assert_eq!(invalid_fields(result), vec!["totalOutcomes"]);
+        }
+        for outcomes in [1, max_value() as u64] {
+            let result = validator.check_fields(request(json!({ "totalOutcomes": outcomes })), 1);
+            assert!(result.is_ok());
+        }
+    }
+
+    #[test]
+    fn overflowing_collateral_is_rejected() {
+        let validator = validator(OfferLimits::default());
+        let result = validator.check_fields(
+            request(json!({ "acceptCollateral": u64::MAX, "offerCollateral": 1 })),
+            1,
+        );
+        assert_eq!(invalid_fields(result), vec!["acceptCollateral"]);
+
+        let validator = validator(OfferLimits {
+            max_total_collateral: Some(150_000),
+            ..OfferLimits::default()
+        });
+        let result = validator.check_fields(
+            request(json!({ "acceptCollateral": 100_000, "offerCollateral": 50_001 })),
+            1,
+        );
+        assert_eq!(invalid_fields(result), vec!["acceptCollateral"]);
+    }
+
+    #[test]
+    fn fee_basis_points_are_bounded() {
+        let validator = validator(OfferLimits {
+            max_fee_basis_points: 500,
+            ..OfferLimits::default()
+        });
+        let result = validator.check_fields(request(json!({ "btcFeeBasisPoints": 501 })), 1);
+        assert_eq!(invalid_fields(result), vec!["btcFeeBasisPoints"]);
+        assert!(validator
+            .check_fields(request(json!({ "btcFeeBasisPoints": 500 })), 1)
+            .is_ok());
+    }
+
+    #[test]
+    fn fee_recipient_is_only_optional_without_fee() {
+        let validator = validator(OfferLimits::default());
+        let (offer, _) = validator
+            .check_fields(
+                request(json!({ "btcFeeRecipient": "", "btcFeeBasisPoints": 0 })),
+                1,
+            )
+            .map_err(FieldErrors)
+            .expect("a valid offer");
+        assert_eq!(offer.fee_recipient.to_string(), REGTEST_ADDRESS);
+        let result = validator.check_fields(request(json!({ "btcFeeRecipient": "" })), 1);
+        assert_eq!(invalid_fields(result), vec!["btcFeeRecipient"]);
+    }
+
+    #[test]
+    fn threshold_is_bounded_by_the_attestors() {
+        let validator = validator(OfferLimits::default());
+        for threshold in [0, 4] {
+            let result = validator.check_fields(request(json!({ "threshold": threshold })), 3);
+            assert_eq!(invalid_fields(result), vec!["threshold"]);
+        }
+        let (offer, _) = validator
+            .check_fields(request(json!({ "threshold": 2 })), 3)
+            .map_err(FieldErrors)
+            .expect("a valid offer");
+        assert_eq!(offer.threshold, 2);
+    }
+
+    #[test]
+    fn addresses_of_other_networks_are_rejected() {
+        for address in [
+            "tb1qw508d6qejxtdg4y5r3zarvary0c5xw7kxpjzsx",
+            "bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t4",
+        ] {
+            assert!(parse_address("btcFeeRecipient", address, Network::Regtest).is_err());
+            let result = validator(OfferLimits::default())
+                .check_fields(request(json!({ "btcFeeRecipient": address })), 1);
+            assert_eq!(invalid_fields(result), vec!["btcFeeRecipient"]);
+        }
+        assert!(parse_address("btcFeeRecipient", REGTEST_ADDRESS, Network::Regtest).is_ok());
+        assert!(parse_address("btcFeeRecipient", "not an address", Network::Regtest).is_err());
+    }
+
+    #[test]
+    fn refund_delay_is_adjusted_to_the_maximum() {
+        let validator = validator(OfferLimits {
+            max_refund_delay: 7200,
+            ..OfferLimits::default()
+        });
+        for requested in [0, 7201] {
+            let (offer, adjustments) = validator
+                .check_fields(request(json!({ "refundDelay": requested })), 1)
+                .map_err(FieldErrors)
+                .expect("a valid offer");
+            assert_eq!(offer.refund_delay, 7200);
+            assert_eq!(adjustments.len(), 1);
+            assert_eq!(adjustments[0].field, "refundDelay");
+            assert_eq!(adjustments[0].requested, requested);
+            assert_eq!(adjustments[0].applied, 7200);
+        }
+        let (offer, adjustments) = validator
+            .check_fields(request(json!({ "refundDelay": 7200 })), 1)
+            .map_err(FieldErrors)
+            .expect("a valid offer");
+        assert_eq!(offer.refund_delay, 7200);
+        assert!(adjustments.is_empty());
+    }
+
+    #[test]
+    fn uuids_are_letters_and_digits() {
+        assert!(is_valid_uuid("abc123"));
+        assert!(is_valid_uuid(&"a".repeat(MAX_UUID_LENGTH)));
+        assert!(!is_valid_uuid(""));
+        assert!(!is_valid_uuid(&"a".repeat(MAX_UUID_LENGTH + 1)));
+        assert!(!is_valid_uuid("../abc"));
+    }
+}
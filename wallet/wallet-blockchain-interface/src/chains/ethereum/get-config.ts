@@ -62,7 +62,7 @@ export default async (config: ChainConfig): Promise<WrappedContract> => {
                 return txReceipt;
             } catch (error) {
                 console.log(error);
-                return error;
+                throw error;
             }
         },
         postCloseDLC: async (uuid, btcTxId) => {
@@ -76,7 +76,7 @@ export default async (config: ChainConfig): Promise<WrappedContract> => {
                 return txReceipt;
             } catch (error) {
                 console.log(error);
-                return error;
+                throw error;
             }
         },
         getDLCInfo: async (uuid) => {
@@ -36,6 +36,13 @@ async function getCallbackContract(uuid: string, contractName: string, deployer:
     return parsePrincipalString(callbackContract) as ContractPrincipal;
 }
 
+// A rejected broadcast is answered with the reason instead of a txid.
+function throwIfRejected(broadcastResponse: TxBroadcastResult) {
+    if ('error' in broadcastResponse && broadcastResponse.error) {
+        throw new Error(`Transaction rejected: ${broadcastResponse.error} (${broadcastResponse.reason})`);
+    }
+}
+
 export default async (config: ChainConfig): Promise<WrappedContract> => {
     console.log(`[Stacks] Loading contract config for ${config.network}...`);
     const walletKey = config.private_key;
@@ -68,10 +75,11 @@ export default async (config: ChainConfig): Promise<WrappedContract> => {
                 console.log('Transaction payload:', transaction2.payload);
                 const broadcastResponse: TxBroadcastResult = await broadcastTransaction(transaction2, stacksNetwork);
                 console.log('Broadcast response: ', broadcastResponse);
+                throwIfRejected(broadcastResponse);
                 return broadcastResponse as any;
             } catch (error) {
                 console.log(error);
-                return error;
+                throw error;
             }
         },
 
@@ -110,10 +118,11 @@ export default async (config: ChainConfig): Promise<WrappedContract> => {
                 console.log('Transaction payload:', transaction.payload);
                 const broadcastResponse = await broadcastTransaction(transaction, stacksNetwork);
                 console.log('Broadcast response: ', broadcastResponse);
+                throwIfRejected(broadcastResponse);
                 return broadcastResponse as any;
             } catch (error) {
                 console.log(error);
-                return error;
+                throw error;
             }
         },
 
@@ -8,6 +8,14 @@ const blockchainWriter = await BlockchainInterfaceService.getBlockchainWriter();
 const router = express.Router();
 const TESTMODE: boolean = ConfigService.getEnv('TEST_MODE_ENABLED') == 'true';
 
+// The write to the chain failed, e.g. the transaction reverted. The router
+// wallet keeps sending the notification until it gets a success.
+function sendChainWriteError(res: express.Response, route: string, error: unknown) {
+    const message = error instanceof Error ? error.message : String(error);
+    console.error(`[WBI] ${route} failed:`, error);
+    res.status(502).send(`${route} failed: ${message}`);
+}
+
 router.post('/set-status-funded', express.json(), localhostOrDockerOnly, async (req, res) => {
     if (!req.body.uuid) {
         res.status(400).send('Missing UUID');
@@ -29,12 +37,16 @@ router.post('/set-status-funded', express.json(), localhostOrDockerOnly, async (
         return;
     }
 
-    const data = await blockchainWriter.setStatusFunded(
-        req.body.uuid as string,
-        req.body.btcTxId as string,
-        req.body.chain as string
-    );
-    res.status(200).send(data);
+    try {
+        const data = await blockchainWriter.setStatusFunded(
+            req.body.uuid as string,
+            req.body.btcTxId as string,
+            req.body.chain as string
+        );
+        res.status(200).send(data);
+    } catch (error) {
+        sendChainWriteError(res, 'set-status-funded', error);
+    }
 });
 
 router.get('/get-all-attestors', express.json(), localhostOrDockerOnly, async (req, res) => {
@@ -64,8 +76,54 @@ router.post('/post-close-dlc', express.json(), localhostOrDockerOnly, async (req
         res.status(200).send('post-close-dlc called in test mode.');
         return;
     }
-    const data = await blockchainWriter.postCloseDLC(uuid as string, btcTxId as string, req.body.chain as string);
-    res.status(200).send(data);
+    try {
+        const data = await blockchainWriter.postCloseDLC(uuid as string, btcTxId as string, req.body.chain as string);
+        res.status(200).send(data);
+    } catch (error) {
+        sendChainWriteError(res, 'post-close-dlc', error);
+    }
+});
+
+router.post('/post-abandon-dlc', express.json(), localhostOrDockerOnly, async (req, res) => {
+    if (!req.body.uuid) {
+        res.status(400).send('Missing UUID');
+        return;
+    }
+    if (!req.body.chain) {
+        res.status(400).send('Missing chain');
+        return;
+    }
+    // The offer expired before being funded, so there is no BTC transaction to report.
+    console.log('[WBI] POST /post-abandon-dlc with UUID, chain:', req.body.uuid, req.body.chain);
+    res.status(200).send('post-abandon-dlc received.');
+});
+
+router.post('/post-refund-dlc', express.json(), localhostOrDockerOnly, async (req, res) => {
+    if (!req.body.uuid) {
+        res.status(400).send('Missing UUID');
+        return;
+    }
+    if (!req.body.btcTxId) {
+        res.status(400).send('Missing BTC TX ID');
+        return;
+    }
+    if (!req.body.chain) {
+        res.status(400).send('Missing chain');
+        return;
+    }
+    const { uuid, btcTxId } = req.body;
+    console.log('[WBI] POST /post-refund-dlc with UUID, BTC TX ID:', uuid, btcTxId);
+
+    if (TESTMODE) {
+        res.status(200).send('post-refund-dlc called in test mode.');
+        return;
+    }
+    try {
+        const data = await blockchainWriter.postRefundDLC(uuid as string, btcTxId as string, req.body.chain as string);
+        res.status(200).send(data);
+    } catch (error) {
+        sendChainWriteError(res, 'post-refund-dlc', error);
+    }
 });
 
 export default router;
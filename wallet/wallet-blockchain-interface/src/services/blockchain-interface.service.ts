@@ -60,6 +60,17 @@ export default class BlockchainInterfaceService {
         return await contractConfig.postCloseDLC(uuid, btcTxId);
     }
 
+    // The DLC is closed with the refund transaction, as the contracts have no
+    // entry point dedicated to refunds.
+    public async postRefundDLC(
+        uuid: string,
+        btcTxId: string,
+        chain: string
+    ): Promise<TransactionReceipt | TxBroadcastResult> {
+        const contractConfig = await this.getWrappedContract(chain);
+        return await contractConfig.postCloseDLC(uuid, btcTxId);
+    }
+
     public async getDLCInfo(uuid: string): Promise<any> {
         let res = await (await RouterWalletService.getRouterWallet()).getChainForUUID(uuid);
         let chain = await res.json();
@@ -9,24 +9,26 @@ extern crate log;
 
 use bitcoin::XOnlyPublicKey;
 use bitcoin::{Network, PrivateKey};
-use dlc_messages::{Message, OfferDlc, SignDlc};
+use dlc_messages::Message;
 use log::{error, info, warn};
-use secp256k1_zkp::UpstreamError;
 use wasm_bindgen::prelude::*;
 
 use lightning::util::ser::Readable;
 
 use secp256k1_zkp::hashes::*;
-use secp256k1_zkp::Secp256k1;
+use secp256k1_zkp::{All, PublicKey, Secp256k1, SecretKey};
 
 use core::panic;
 use std::collections::HashMap;
 use std::fmt;
 use std::{io::Cursor, str::FromStr, sync::Arc};
 
-use dlc_manager::{contract::Contract, ContractId, SystemTimeProvider};
+use dlc_manager::{contract::Contract, ContractId, Time};
 
-use dlc_link_manager::{AsyncOracle, AsyncStorage, Manager};
+use dlc_link_manager::{
+    AsyncOracle, AsyncStorage, FeeRateBounds, Manager, ManagerConfig, OfferAcceptancePolicy,
+    SignedEnvelope,
+};
 
 use std::fmt::Write as _;
 
@@ -36,7 +38,7 @@ use esplora_async_blockchain_provider_js_wallet::EsploraAsyncBlockchainProviderJ
 
 use js_interface_wallet::JSInterfaceWallet;
 
-use attestor_client::AttestorClient;
+use attestor_client::{AttestorClient, OracleApi};
 use serde::{Deserialize, Serialize};
 
 #[macro_use]
@@ -56,12 +58,18 @@ type DlcManager = Manager<
     Arc<EsploraAsyncBlockchainProviderJsWallet>,
     Box<AsyncStorageApiProvider>,
     Arc<AttestorClient>,
-    Arc<SystemTimeProvider>,
+    Arc<JsTimeProvider>,
 >;
 
-// The contracts in dlc-manager expect a node id, but web extensions often don't have this, so hardcode it for now. Should not have any ramifications.
-const STATIC_COUNTERPARTY_NODE_ID: &str =
-    "02fc8e97419286cf05e5d133f41ff6d51f691dda039e9dc007245a421e2c7ec61c";
+/// Time provider backed by the browser clock, `std::time::SystemTime` is not
+/// available on wasm32-unknown-unknown.
+struct JsTimeProvider {}
+
+impl Time for JsTimeProvider {
+    fn unix_time_now(&self) -> u64 {
+        (js_sys::Date::now() / 1000.0) as u64
+    }
+}
 
 #[derive(Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -84,12 +92,50 @@ struct UtxoInput {
     value: u64,
 }
 
+/// Acceptance policy of the offers received, every limit is optional.
+#[derive(Serialize, Deserialize, Default)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+struct JsOfferPolicy {
+    allowed_oracle_keys: Option<Vec<String>>,
+    min_threshold: Option<usize>,
+    max_total_collateral: Option<u64>,
+    min_refund_delay: Option<u32>,
+    max_refund_delay: Option<u32>,
+    max_protocol_fee_basis_points: Option<u64>,
+}
+
+impl JsOfferPolicy {
+    fn into_policy(self) -> Result<OfferAcceptancePolicy, JsError> {
+        let defaults = OfferAcceptancePolicy::default();
+        let allowed_oracle_keys = self
+            .allowed_oracle_keys
+            .unwrap_or_default()
+            .iter()
+            .map(|k| {
+                XOnlyPublicKey::from_str(k)
+                    .map_err(|e| JsError::new(&format!("Error parsing oracle key {}: {}", k, e)))
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(OfferAcceptancePolicy {
+            allowed_oracle_keys,
+            min_threshold: self.min_threshold.unwrap_or(defaults.min_threshold),
+            max_total_collateral: self.max_total_collateral,
+            min_refund_delay: self.min_refund_delay.unwrap_or(defaults.min_refund_delay),
+            max_refund_delay: self.max_refund_delay.unwrap_or(defaults.max_refund_delay),
+            max_protocol_fee_basis_points: self.max_protocol_fee_basis_points,
+        })
+    }
+}
+
 #[wasm_bindgen]
 pub struct JsDLCInterface {
     options: JsDLCInterfaceOptions,
     manager: DlcManager,
     wallet: Arc<JSInterfaceWallet>,
     blockchain: Arc<EsploraAsyncBlockchainProviderJsWallet>,
+    node_secret_key: SecretKey,
+    router_public_key: PublicKey,
+    secp: Secp256k1<All>,
 }
 
 // #[wasm_bindgen]
@@ -120,12 +166,13 @@ where
 
 pub async fn generate_attestor_client(
     attestor_urls: Vec<String>,
+    oracle_api: Option<OracleApi>,
 ) -> HashMap<XOnlyPublicKey, Arc<AttestorClient>> {
     let mut attestor_clients = HashMap::new();
 
     for url in attestor_urls.iter() {
         let p2p_client = match retry!(
-            AttestorClient::new(url).await,
+            AttestorClient::new_with_api(url, oracle_api).await,
             10,
             "attestor client creation",
             6
@@ -143,12 +190,24 @@ pub async fn generate_attestor_client(
 
 #[wasm_bindgen]
 impl JsDLCInterface {
+    /// `router_public_key` is the key the protocol wallet signs its messages
+    /// with, offers signed with any other key are rejected.
+    /// `offer_policy` is the JSON of the limits offers must meet to be
+    /// accepted, e.g. `{"maxTotalCollateral": 100000000,
+    /// "maxProtocolFeeBasisPoints": 100}`, with the keys `allowedOracleKeys`,
+    /// `minThreshold`, `maxTotalCollateral`, `minRefundDelay`,
+    /// `maxRefundDelay` and `maxProtocolFeeBasisPoints`.
+    /// `oracle_api` is the API spoken by the attestors, `dlclink` or
+    /// `p2pderivatives`, detected from each of them when left out.
     pub async fn new(
         privkey: String,
         address: String,
         network: String,
         electrs_url: String,
         storage_api_url: String,
+        router_public_key: String,
+        offer_policy: Option<String>,
+        oracle_api: Option<String>,
     ) -> Result<JsDLCInterface, JsError> {
         console_error_panic_hook::set_once();
 
@@ -159,15 +218,31 @@ impl JsDLCInterface {
         };
 
         let active_network: Network = options.network.parse::<Network>()?;
+        let router_public_key = PublicKey::from_str(&router_public_key)
+            .map_err(|e| JsError::new(&format!("Error parsing router public key: {}", e)))?;
+        let oracle_api = match oracle_api {
+            Some(api) => Some(
+                OracleApi::from_str(&api)
+                    .map_err(|e| JsError::new(&format!("Error parsing oracle api: {}", e)))?,
+            ),
+            None => None,
+        };
+        let offer_policy = match offer_policy {
+            Some(policy) => serde_json::from_str::<JsOfferPolicy>(&policy)
+                .map_err(|e| JsError::new(&format!("Error parsing offer policy: {}", e)))?,
+            None => JsOfferPolicy::default(),
+        }
+        .into_policy()?;
 
         let blockchain: Arc<EsploraAsyncBlockchainProviderJsWallet> =
             Arc::new(EsploraAsyncBlockchainProviderJsWallet::new(
                 options.electrs_url.to_string(),
                 active_network,
+                FeeRateBounds::for_network(active_network),
             ));
 
         // Generate keypair from secret key
-        let seckey = secp256k1_zkp::SecretKey::from_str(&privkey)
+        let seckey = SecretKey::from_str(&privkey)
             .map_err(|e| JsError::new(&format!("Error parsing private key: {}", e)))?;
 
         let secp = Secp256k1::new();
@@ -205,10 +280,10 @@ impl JsDLCInterface {
         };
 
         let protocol_wallet_attestors: HashMap<XOnlyPublicKey, Arc<AttestorClient>> =
-            generate_attestor_client(attestor_urls.clone()).await;
+            generate_attestor_client(attestor_urls.clone(), oracle_api).await;
 
         // Set up time provider
-        let time_provider = SystemTimeProvider {};
+        let time_provider = JsTimeProvider {};
 
         // Create the DLC Manager
         let manager = Manager::new(
@@ -217,6 +292,10 @@ impl JsDLCInterface {
             Box::new(dlc_store),
             Some(protocol_wallet_attestors),
             Arc::new(time_provider),
+            ManagerConfig {
+                offer_policy,
+                ..Default::default()
+            },
         )?;
 
         Ok(JsDLCInterface {
@@ -224,9 +303,18 @@ impl JsDLCInterface {
             manager,
             wallet,
             blockchain,
+            node_secret_key: seckey,
+            router_public_key,
+            secp,
         })
     }
 
+    /// The key identifying this wallet to its counterparties, which the
+    /// protocol wallet binds the offers it makes to.
+    pub fn get_public_key(&self) -> String {
+        PublicKey::from_secret_key(&self.secp, &self.node_secret_key).to_string()
+    }
+
     pub fn get_options(&self) -> Result<JsValue, JsError> {
         Ok(serde_wasm_bindgen::to_value(&self.options)?)
     }
@@ -295,15 +383,23 @@ impl JsDLCInterface {
         //could consider doing a refresh_chain_data here to have the newest utxos
 
         let accept_msg_result = async {
-            let dlc_offer_message: OfferDlc =
+            let envelope: SignedEnvelope =
                 serde_json::from_str(&offer_json).map_err(to_wallet_error)?;
-            let temporary_contract_id = dlc_offer_message.temporary_contract_id;
-
-            let counterparty = STATIC_COUNTERPARTY_NODE_ID
-                .parse()
-                .map_err(|e: UpstreamError| WalletError(e.to_string()))?;
+            // The sender of the offer becomes the counterparty of the contract,
+            // only messages signed with the same key can move it forward.
+            let (message, counterparty) = envelope.open(&self.secp).map_err(to_wallet_error)?;
+            if counterparty != self.router_public_key {
+                return Err(WalletError(format!(
+                    "Offer signed by {} instead of the router wallet",
+                    counterparty
+                )));
+            }
+            let temporary_contract_id = match &message {
+                Message::Offer(offer) => offer.temporary_contract_id,
+                _ => return Err(WalletError("Expected an offer message".to_string())),
+            };
             self.manager
-                .on_dlc_message(&Message::Offer(dlc_offer_message.clone()), counterparty)
+                .on_dlc_message(&message, counterparty)
                 .await
                 .map_err(to_wallet_error)?;
             let (_contract_id, _public_key, accept_msg) = self
@@ -311,7 +407,13 @@ impl JsDLCInterface {
                 .accept_contract_offer(&temporary_contract_id)
                 .await
                 .map_err(to_wallet_error)?;
-            serde_json::to_string(&accept_msg).map_err(to_wallet_error)
+            let accept_envelope = SignedEnvelope::seal(
+                &self.secp,
+                &Message::Accept(accept_msg),
+                &self.node_secret_key,
+            )
+            .map_err(to_wallet_error)?;
+            serde_json::to_string(&accept_envelope).map_err(to_wallet_error)
         };
         match accept_msg_result.await {
             Ok(accept_msg) => Ok(accept_msg),
@@ -327,15 +429,15 @@ impl JsDLCInterface {
         dlc_sign_message: String,
     ) -> Result<String, JsError> {
         let dlc_sign_result = async {
-            let dlc_sign_message: SignDlc =
+            let envelope: SignedEnvelope =
                 serde_json::from_str(&dlc_sign_message).map_err(to_wallet_error)?;
+            let (message, counterparty) = envelope.open(&self.secp).map_err(to_wallet_error)?;
+            let contract_id = match &message {
+                Message::Sign(sign) => sign.contract_id,
+                _ => return Err(WalletError("Expected a sign message".to_string())),
+            };
             self.manager
-                .on_dlc_message(
-                    &Message::Sign(dlc_sign_message.clone()),
-                    STATIC_COUNTERPARTY_NODE_ID
-                        .parse()
-                        .map_err(to_wallet_error)?,
-                )
+                .on_dlc_message(&message, counterparty)
                 .await
                 .map_err(to_wallet_error)?;
             let store = self.manager.get_store();
@@ -344,7 +446,7 @@ impl JsDLCInterface {
                 .await
                 .map_err(to_wallet_error)?
                 .into_iter()
-                .find(|c| c.accepted_contract.get_contract_id() == dlc_sign_message.contract_id);
+                .find(|c| c.accepted_contract.get_contract_id() == contract_id);
             match contract {
                 None => Err(WalletError(
                     "DLC Manager: - Sign Offer Error: Contract not found".to_string(),